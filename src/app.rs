@@ -0,0 +1,2290 @@
+// The Windows binary: every subcommand, run against the local spooler or --server
+
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::{self, IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::ops::ControlFlow;
+use std::os::windows::io::IntoRawHandle;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::{self, exit};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{info, warn, error};
+use serde::Serialize;
+use winapi::shared::winerror::{ERROR_ALREADY_EXISTS, ERROR_UNKNOWN_PORT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::processenv::SetStdHandle;
+use winapi::um::winbase::STD_OUTPUT_HANDLE;
+
+use wsd_to_ip::{EnumScope, MinimalPrinterInfo, RetryPolicy, get_printers_with_scope, get_wsd_printers, get_wsd_printers_refs, is_ip_port, is_wsd_port};
+use wsd_to_ip::name_from_raw;
+use wsd_to_ip::{PrinterKind, get_default_printer, get_printers_level4, get_printers_level5, set_default_printer};
+use wsd_to_ip::{attach_driver_versions, get_driver_info, get_installed_drivers, is_elevated, missing_drivers, relaunch_elevated};
+use wsd_to_ip::{PortInfo, PrinterError, get_all_ports, get_print_monitors, get_tcpip_port_config, TCPIP_MONITOR_NAME};
+use wsd_to_ip::{exclude_printers, filter_printers_by_driver, filter_printers_by_name, filter_printers_by_state, matching_exclusion};
+use wsd_to_ip::{exclude_virtual_printers, DEFAULT_VIRTUAL_DRIVERS};
+use wsd_to_ip::{delete_port, ip_port_name, model_port_name, print_test_page};
+use wsd_to_ip::{check_host_address, render_port_name, template_uses_model, DEFAULT_PORT_NAME_TEMPLATE, MAX_HOST_ADDRESS_LEN};
+use wsd_to_ip::{PortProtocol, TcpipPortConfig, DEFAULT_LPR_PORT_NUMBER, DEFAULT_RAW_PORT_NUMBER};
+use wsd_to_ip::arp::resolve_ip_from_mac;
+use wsd_to_ip::backup::{backup_printers, load_backup, restore_printer, timestamped_backup_name};
+use wsd_to_ip::cache::{AddressCache, CACHE_FILE};
+use wsd_to_ip::correlation::printer_scope;
+use wsd_to_ip::discovery::{cancel_discovery, count_probe_responders, parse_wsd_port, resolve_wsd_ip, set_discovery_interface};
+use wsd_to_ip::dns::reverse_lookup;
+use wsd_to_ip::emit::{powershell_script, reg_file, utf16_with_bom};
+use wsd_to_ip::error::format_error_code;
+use wsd_to_ip::event_log::EventLog;
+use wsd_to_ip::function_discovery::resolve_via_function_discovery;
+use wsd_to_ip::interfaces::list_interfaces;
+use wsd_to_ip::journal::{last_run, load_journal, Journal, Mutation, JOURNAL_FILE};
+use wsd_to_ip::mapping::{load_exclude_list, load_ip_map, load_mac_map, load_server_list, IpMap, MacMap};
+use wsd_to_ip::mdns::{resolve_via_mdns, DEFAULT_MDNS_TIMEOUT};
+use wsd_to_ip::metadata::attach_device_info;
+use wsd_to_ip::plan::{parse_selection, ConversionPlan, ConvertOptions, Lookup, NewPort, PlannedConversion, Skip};
+use wsd_to_ip::report::{ConversionReport, OutcomeStatus, PrinterConversionOutcome};
+use wsd_to_ip::spooler_api::{SpoolerApi, WinSpooler};
+use wsd_to_ip::reachability::{is_reachable_on_port, DEFAULT_REACHABILITY_TIMEOUT_MS};
+use wsd_to_ip::snmp::{query_device_model, DEFAULT_SNMP_TIMEOUT};
+use wsd_to_ip::registry::read_wsd_address_from_registry;
+use wsd_to_ip::spooler::{restart_spooler, spooler_is_running, spooler_state, start_spooler, DEFAULT_SPOOLER_TIMEOUT};
+
+use crate::cli::{Cli, ColorChoice, Command, ConvertArgs, DoctorArgs, ListArgs, OutputFormat, Protocol, RestoreArgs, Scope, SummaryArgs, UndoArgs};
+use crate::config::parse_with_config;
+use crate::logging::init_logging;
+use crate::table::print_printer_table;
+
+// Exit codes, listed for operators in the --help text. Success, nothing to do included, is a plain return with 0
+
+// Some conversions failed, or a file the run depends on could not be read or written
+const EXIT_FAILURE: i32 = 1;
+// There were no printers to work on
+const EXIT_NO_PRINTERS: i32 = 2;
+// Changes need an elevated process and none could be had
+const EXIT_NOT_ELEVATED: i32 = 3;
+// The spooler or another Windows API failed before anything could be done
+const EXIT_WIN32_ERROR: i32 = 4;
+// --timeout ran out before the command finished
+const EXIT_TIMED_OUT: i32 = 5;
+// A convert run stopped with Ctrl-C, following the shell convention of 128 + SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+// How long a timed out command gets to finish the printer it is on and write its report before the process exits
+const TIMEOUT_GRACE: Duration = Duration::from_secs(10);
+
+// How long doctor waits for devices to answer its WS-Discovery probe
+const DOCTOR_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// How many printers summary --by-subnet discovers at once
+const SUMMARY_CONCURRENCY: usize = 16;
+
+// Set by the Ctrl-C handler and checked between printers, so the printer being worked on is always finished
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Set alongside STOP_REQUESTED when --timeout runs out, so the stop is reported as a timeout rather than Ctrl-C
+static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+// Exit code for a run that stopped early, by whichever of Ctrl-C or --timeout stopped it
+fn stopped_exit_code() -> i32 {
+    if TIMED_OUT.load(Ordering::SeqCst) {
+        EXIT_TIMED_OUT
+    } else {
+        EXIT_INTERRUPTED
+    }
+}
+
+fn install_interrupt_handler() {
+    let result = ctrlc::set_handler(|| {
+        // A second Ctrl-C while the first is being honoured means the operator really wants out now
+        if STOP_REQUESTED.swap(true, Ordering::SeqCst) {
+            exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Stop requested, finishing the current printer...");
+    });
+
+    if let Err(e) = result {
+        warn!("[{}] Could not install the Ctrl-C handler: {}", "install_interrupt_handler", e);
+    }
+}
+
+fn enum_scope(scope: Scope) -> EnumScope {
+    match scope {
+        Scope::Local => EnumScope::Local,
+        Scope::Connections => EnumScope::Connections,
+        Scope::Network => EnumScope::Network,
+        Scope::All => EnumScope::All,
+    }
+}
+
+// The real spooler, retrying enumeration as many times as --enum-retries allows
+fn spooler(cli: &Cli) -> WinSpooler {
+    WinSpooler::new(RetryPolicy { attempts: cli.enum_retries.max(1), ..RetryPolicy::default() })
+}
+
+// Enumerate printers on the local machine or --server, exiting with a non-zero code if the spooler could not be queried
+fn load_printers(cli: &Cli) -> Vec<MinimalPrinterInfo> {
+    let server = cli.server.as_deref();
+    info!("[{}] Getting information from {:?} printers on {}", "load_printers", cli.scope, server.unwrap_or("the local machine"));
+    match spooler(cli).enum_printers(server, enum_scope(cli.scope)) {
+        Ok(all_printers) => {
+            info!("[{}] Successfully retrieved printer information", "load_printers");
+            all_printers
+        }
+        Err(e) => {
+            error!("[{}] {}", "load_printers", e);
+            eprintln!("Error: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    }
+}
+
+// Write records to stdout as JSON or CSV. Text and table output are formatted by each caller
+fn print_records<T: Serialize>(records: &[T], format: OutputFormat) {
+    let result = match format {
+        OutputFormat::Text | OutputFormat::Table => Ok(()),
+        OutputFormat::Json => serde_json::to_string_pretty(records)
+            .map(|json| println!("{}", json))
+            .map_err(|e| e.to_string()),
+        OutputFormat::Csv => write_csv(records).map_err(|e| e.to_string()),
+    };
+
+    if let Err(e) = result {
+        error!("[{}] Failed to serialize output as {:?}: {}", "print_records", format, e);
+        eprintln!("Error: failed to serialize output: {}", e);
+        exit(EXIT_FAILURE);
+    }
+}
+
+fn write_csv<T: Serialize>(records: &[T]) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn print_printers(printers: &[MinimalPrinterInfo], format: OutputFormat, color: ColorChoice) {
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Table => return print_printer_table(printers, color),
+        format => return print_records(printers, format),
+    }
+
+    for printer in printers {
+        println!("Printer Name: {:?}\n Port Name: {:?}\n Driver Name: {:?}\n Share Name: {:?}\n Location: {:?}\n Comment: {:?}\n Status: {}\n Attributes: {}",
+            printer.printer_name, printer.port_name, printer.driver_name, printer.share_name, printer.location, printer.comment,
+            printer.status_text(), printer.attribute_flags().join(", "));
+        if let Some(driver_version) = &printer.driver_version {
+            println!(" Driver Version: {}", driver_version);
+        }
+        if let Some(manufacturer) = &printer.device_manufacturer {
+            println!(" Manufacturer: {}", manufacturer);
+        }
+        if let Some(model) = &printer.device_model {
+            println!(" Model: {}", model);
+        }
+        if let Some(serial) = &printer.device_serial {
+            println!(" Serial Number: {}", serial);
+        }
+    }
+}
+
+// Run one phase of a command and log how long it took, so slow print servers show where the time goes
+fn timed<T>(phase: &str, run: impl FnOnce() -> T) -> (T, Duration) {
+    let started = Instant::now();
+    let result = run();
+    let elapsed = started.elapsed();
+    info!("[{}] {} took {:?}", "timed", phase, elapsed);
+
+    (result, elapsed)
+}
+
+// Directory holding the executable, where generated files go unless told otherwise
+fn exe_dir() -> PathBuf {
+    std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Apply the WSD port filter and any user supplied filters from the command line
+fn select_wsd_printers(all_printers: &[MinimalPrinterInfo], cli: &Cli) -> Vec<MinimalPrinterInfo> {
+    apply_filters(exclude_virtual_printers(&get_wsd_printers(all_printers), &virtual_drivers(cli)), cli)
+}
+
+// The drivers of software printers to leave out: --virtual-drivers when given, otherwise the built-in list
+fn virtual_drivers(cli: &Cli) -> Vec<String> {
+    match &cli.virtual_drivers {
+        Some(patterns) => patterns.iter().map(|pattern| pattern.trim().to_string()).filter(|pattern| !pattern.is_empty()).collect(),
+        None => DEFAULT_VIRTUAL_DRIVERS.iter().map(|pattern| pattern.to_string()).collect(),
+    }
+}
+
+// Apply the name, driver and state filters from the command line
+fn apply_filters(mut printers: Vec<MinimalPrinterInfo>, cli: &Cli) -> Vec<MinimalPrinterInfo> {
+    if let Some(pattern) = &cli.name_filter {
+        printers = filter_printers_by_name(&printers, pattern);
+    }
+
+    if let Some(driver) = &cli.driver {
+        printers = filter_printers_by_driver(&printers, driver);
+    }
+
+    if cli.only_online || cli.only_offline {
+        printers = filter_printers_by_state(&printers, cli.only_online);
+    }
+
+    printers
+}
+
+// One row of list --minimal
+#[derive(Serialize)]
+struct PrinterPortRecord {
+    printer_name: String,
+    port_name: String,
+}
+
+// The quick triage listing: one level 5 enumeration, then just the WSD printers' names and ports
+fn run_minimal_list(cli: &Cli) {
+    // Level 5 has no driver or status to filter on or show
+    if cli.driver.is_some() || cli.only_online || cli.only_offline || cli.driver_details || cli.device_info {
+        eprintln!("Error: list --minimal has no driver, device or state details, so it can only be filtered with --name-filter");
+        exit(EXIT_FAILURE);
+    }
+
+    let (all_printers, _) = timed("Enumeration", || match get_printers_level5(cli.server.as_deref(), enum_scope(cli.scope)) {
+        Ok(printers) => printers,
+        Err(e) => {
+            error!("[{}] {}", "run_minimal_list", e);
+            eprintln!("Error: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    });
+    let (wsd_printers, _) = timed("Filtering", || select_wsd_printers(&all_printers, cli));
+
+    let records: Vec<PrinterPortRecord> = wsd_printers.iter()
+        .map(|printer| PrinterPortRecord {
+            printer_name: printer.printer_name.to_string_lossy().into_owned(),
+            port_name: printer.port_name.to_string_lossy().into_owned(),
+        })
+        .collect();
+
+    if !cli.format.is_human() {
+        print_records(&records, cli.format);
+        return;
+    }
+
+    let width = records.iter().map(|record| record.printer_name.chars().count()).max().unwrap_or(0);
+    for record in &records {
+        println!("{:<width$}  {}", record.printer_name, record.port_name, width = width);
+    }
+}
+
+fn run_list(args: &ListArgs, cli: &Cli) {
+    if args.minimal {
+        return run_minimal_list(cli);
+    }
+
+    let (all_printers, _) = timed("Enumeration", || load_printers(cli));
+
+    if all_printers.is_empty() {
+        warn!("[{}] No printers found", "run_list");
+    }
+
+    let (mut wsd_printers, _) = timed("Filtering", || select_wsd_printers(&all_printers, cli));
+
+    if wsd_printers.is_empty() {
+        warn!("[{}] No WSD connected printers found", "run_list");
+    }
+
+    if cli.driver_details {
+        attach_driver_versions(&mut wsd_printers);
+    }
+
+    if cli.device_info {
+        timed("Device metadata", || attach_device_info(&mut wsd_printers));
+    }
+
+    print_printers(&wsd_printers, cli.format, cli.color);
+}
+
+// Enumerate spooler ports, exiting with a non-zero code if they could not be queried
+fn load_ports(server: Option<&str>) -> Vec<PortInfo> {
+    match get_all_ports(server) {
+        Ok(ports) => ports,
+        Err(e) => {
+            error!("[{}] {}", "load_ports", e);
+            eprintln!("Error: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    }
+}
+
+fn run_ports(cli: &Cli) {
+    let ports = load_ports(cli.server.as_deref());
+
+    if !cli.format.is_human() {
+        print_records(&ports, cli.format);
+        return;
+    }
+
+    for port in &ports {
+        println!("Port Name: {:?}\n Monitor: {:?}\n Description: {:?}", port.port_name, port.monitor_name, port.description);
+    }
+}
+
+fn load_monitors(server: Option<&str>) -> Vec<String> {
+    match get_print_monitors(server) {
+        Ok(monitors) => monitors,
+        Err(e) => {
+            error!("[{}] {}", "load_monitors", e);
+            eprintln!("Error: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    }
+}
+
+// One row of the monitors output, so JSON and CSV get a named field
+#[derive(Serialize)]
+struct MonitorRecord<'a> {
+    monitor_name: &'a str,
+}
+
+fn run_monitors(cli: &Cli) {
+    let monitors = load_monitors(cli.server.as_deref());
+
+    match cli.format {
+        OutputFormat::Text | OutputFormat::Table => monitors.iter().for_each(|monitor| println!("{}", monitor)),
+        format => {
+            let records: Vec<MonitorRecord> = monitors.iter().map(|name| MonitorRecord { monitor_name: name }).collect();
+            print_records(&records, format);
+        }
+    }
+}
+
+// One row of the --list-interfaces output, with the addresses in one field so CSV can hold them
+#[derive(Serialize)]
+struct InterfaceRecord {
+    name: String,
+    description: String,
+    addresses: String,
+    up: bool,
+}
+
+fn run_list_interfaces(cli: &Cli) {
+    let Some(interfaces) = list_interfaces() else {
+        eprintln!("Error: could not list the network interfaces");
+        exit(EXIT_WIN32_ERROR);
+    };
+
+    let records: Vec<InterfaceRecord> = interfaces.into_iter()
+        .map(|interface| InterfaceRecord {
+            name: interface.name,
+            description: interface.description,
+            addresses: interface.addresses.iter().map(Ipv4Addr::to_string).collect::<Vec<_>>().join(" "),
+            up: interface.up,
+        })
+        .collect();
+
+    match cli.format {
+        OutputFormat::Text | OutputFormat::Table => {
+            for record in &records {
+                let state = if record.up { "" } else { ", down" };
+                println!("{}: {} ({}{})", record.name, record.addresses, record.description, state);
+            }
+        }
+        format => print_records(&records, format),
+    }
+}
+
+// Use --discovery-interface for every multicast from here on, exiting if it is not an address of this machine,
+// since every Resolve would otherwise fail one at a time
+fn apply_discovery_interface(cli: &Cli) {
+    let Some(address) = cli.discovery_interface else {
+        return;
+    };
+
+    if let Err(e) = UdpSocket::bind((address, 0)) {
+        error!("[{}] Cannot send from {}: {}", "apply_discovery_interface", address, e);
+        eprintln!("Error: {} is not an address of this machine ({}); see --list-interfaces", address, e);
+        exit(EXIT_FAILURE);
+    }
+    set_discovery_interface(address);
+}
+
+// Creating ports goes through the Standard TCP/IP Port monitor, which stripped-down server installs may lack
+fn require_tcpip_monitor(server: Option<&str>) {
+    let monitors = load_monitors(server);
+
+    if !monitors.iter().any(|monitor| monitor.eq_ignore_ascii_case(TCPIP_MONITOR_NAME)) {
+        let e = PrinterError::MonitorMissing(TCPIP_MONITOR_NAME.to_string());
+        error!("[{}] {}", "require_tcpip_monitor", e);
+        eprintln!("Error: {}, so no TCP/IP ports can be created on {}", e, server.unwrap_or("this machine"));
+        exit(EXIT_WIN32_ERROR);
+    }
+}
+
+// One doctor check and what it found
+#[derive(Serialize)]
+struct DoctorCheck {
+    check: &'static str,
+    passed: bool,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code: Option<u32>,
+}
+
+impl DoctorCheck {
+    fn new(check: &'static str, passed: bool, detail: String) -> Self {
+        DoctorCheck { check, passed, detail, error_code: None }
+    }
+
+    fn failed(check: &'static str, error: &PrinterError) -> Self {
+        DoctorCheck { check, passed: false, detail: error.to_string(), error_code: error.code() }
+    }
+}
+
+// Everything doctor found, as written to --report
+#[derive(Serialize)]
+struct DoctorReport<'a> {
+    version: &'static str,
+    server: Option<&'a str>,
+    scope: String,
+    checks: &'a [DoctorCheck],
+    // Every port monitor installed, so a missing or renamed one shows up without asking for another run
+    monitors: Vec<String>,
+    printers: usize,
+    wsd_printers: usize,
+}
+
+// Run every check, even after one fails, since a support request wants the whole picture at once
+fn run_doctor(args: &DoctorArgs, cli: &Cli) {
+    let server = cli.server.as_deref();
+    let mut checks = Vec::new();
+
+    checks.push(if is_elevated() {
+        DoctorCheck::new("elevated", true, "running as administrator".to_string())
+    } else {
+        DoctorCheck::new("elevated", false, "not elevated; convert needs an administrator prompt or --elevate".to_string())
+    });
+
+    // Every other spooler check fails with a bare RPC error while the service is stopped
+    checks.push(match spooler_state(server) {
+        Ok("Running") => DoctorCheck::new("spooler", true, "Print Spooler is running".to_string()),
+        Ok(state) => DoctorCheck::new("spooler", false, format!("Print Spooler is {}; start it or use --start-spooler", state.to_lowercase())),
+        Err(e) => DoctorCheck::failed("spooler", &e),
+    });
+
+    let monitors = get_print_monitors(server);
+    checks.push(match &monitors {
+        Ok(monitors) if monitors.iter().any(|monitor| monitor.eq_ignore_ascii_case(TCPIP_MONITOR_NAME)) => {
+            DoctorCheck::new("tcpip_monitor", true, format!("{} is installed", TCPIP_MONITOR_NAME))
+        }
+        Ok(_) => DoctorCheck::failed("tcpip_monitor", &PrinterError::MonitorMissing(TCPIP_MONITOR_NAME.to_string())),
+        Err(e) => DoctorCheck::failed("tcpip_monitor", e),
+    });
+
+    let printers = get_printers_with_scope(server, enum_scope(cli.scope));
+    let wsd_count = printers.as_ref().map_or(0, |printers| get_wsd_printers_refs(printers).len());
+    checks.push(match &printers {
+        Ok(printers) => DoctorCheck::new("enum_printers", true, format!("{} printers, {} on WSD ports", printers.len(), wsd_count)),
+        Err(e) => DoctorCheck::failed("enum_printers", e),
+    });
+
+    // A printer on a driver that is gone fails whatever port it is on, which converting it will not fix
+    if let Ok(printers) = &printers {
+        checks.push(match get_installed_drivers(server) {
+            Ok(installed) => match missing_drivers(get_wsd_printers_refs(printers), &installed).as_slice() {
+                [] => DoctorCheck::new("drivers", true, format!("every WSD printer's driver is among the {} installed", installed.len())),
+                missing => DoctorCheck::new("drivers", false, format!("drivers not installed for {}", missing.iter()
+                    .map(|printer| format!("{:?} ({:?})", printer.printer_name, printer.driver_name))
+                    .collect::<Vec<_>>()
+                    .join(", "))),
+            },
+            Err(e) => DoctorCheck::failed("drivers", &e),
+        });
+    }
+
+    // Probed from this machine even with --server, since discovery always runs where the tool does
+    checks.push(match count_probe_responders(DOCTOR_PROBE_TIMEOUT) {
+        Ok(0) => DoctorCheck::new("multicast", false, "no device answered a WS-Discovery probe; multicast to 239.255.255.250:3702 may be blocked".to_string()),
+        Ok(count) => DoctorCheck::new("multicast", true, format!("{} devices answered a WS-Discovery probe", count)),
+        Err(e) => DoctorCheck {
+            check: "multicast",
+            passed: false,
+            detail: format!("could not send a WS-Discovery probe: {}", e),
+            error_code: e.raw_os_error().map(|code| code as u32),
+        },
+    });
+
+    for check in &checks {
+        info!("[{}] {}: {} ({})", "run_doctor", check.check, if check.passed { "pass" } else { "FAIL" }, check.detail);
+    }
+
+    if cli.format.is_human() {
+        for check in &checks {
+            println!("{} {}: {}", if check.passed { "PASS" } else { "FAIL" }, check.check, check.detail);
+        }
+    } else {
+        print_records(&checks, cli.format);
+    }
+
+    if let Some(path) = &args.report {
+        let report = DoctorReport {
+            version: env!("CARGO_PKG_VERSION"),
+            server,
+            scope: format!("{:?}", cli.scope),
+            checks: &checks,
+            monitors: monitors.unwrap_or_default(),
+            printers: printers.as_ref().map_or(0, Vec::len),
+            wsd_printers: wsd_count,
+        };
+
+        let result = std::fs::File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| serde_json::to_writer_pretty(io::BufWriter::new(file), &report).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => println!("Wrote diagnostics to {}", path.display()),
+            Err(e) => {
+                error!("[{}] Failed to write {}: {}", "run_doctor", path.display(), e);
+                eprintln!("Error: failed to write diagnostics: {}", e);
+                exit(EXIT_FAILURE);
+            }
+        }
+    }
+
+    if checks.iter().any(|check| !check.passed) {
+        exit(EXIT_FAILURE);
+    }
+}
+
+fn run_status(cli: &Cli) {
+    let all_printers = load_printers(cli);
+    let wsd_printers = get_wsd_printers_refs(&all_printers);
+    let ip_printers = all_printers.iter()
+        .filter(|printer| is_ip_port(&printer.port_name.to_string_lossy()))
+        .count();
+
+    println!("Printers: {}", all_printers.len());
+    println!(" On WSD ports: {}", wsd_printers.len());
+    println!(" On Standard TCP/IP ports: {}", ip_printers);
+
+    match get_printers_level4(cli.server.as_deref()) {
+        Ok(summaries) => {
+            let count = |kind| summaries.iter().filter(|summary| summary.kind() == kind).count();
+            println!(" Local: {}, shared: {}, connections: {}", count(PrinterKind::Local), count(PrinterKind::Shared), count(PrinterKind::Connection));
+        }
+        Err(e) => warn!("[{}] Could not classify printers: {}", "run_status", e),
+    }
+}
+
+// One row of the summary: how many WSD printers use a driver, or with --by-subnet, a driver within one subnet
+#[derive(Serialize)]
+struct SummaryRecord {
+    driver_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subnet: Option<String>,
+    printers: usize,
+}
+
+// The network an address is in, as 192.168.1.0/24. IPv6 networks are always /64, the size of a single link
+fn subnet_of(address: &str, prefix_length: u8) -> String {
+    match address.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(IpAddr::V4(v4)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix_length as u32).unwrap_or(0);
+            format!("{}/{}", Ipv4Addr::from(u32::from(v4) & mask), prefix_length)
+        }
+        Ok(IpAddr::V6(v6)) => format!("{}/64", Ipv6Addr::from(u128::from(v6) & (u128::MAX << 64))),
+        Err(_) => address.to_string(),
+    }
+}
+
+fn run_summary(args: &SummaryArgs, cli: &Cli) {
+    let all_printers = load_printers(cli);
+    let wsd_printers = select_wsd_printers(&all_printers, cli);
+
+    // Discovering addresses is slow, so printers are probed several at a time, as convert does
+    let subnets: Vec<Option<String>> = match args.by_subnet {
+        None => vec![None; wsd_printers.len()],
+        Some(prefix_length) => {
+            let cache = AddressCache::load(&exe_dir().join(CACHE_FILE), Duration::from_secs(args.cache_ttl));
+            let chunk_size = wsd_printers.len().div_ceil(SUMMARY_CONCURRENCY).max(1);
+
+            let subnets = thread::scope(|scope| {
+                let workers: Vec<_> = wsd_printers.chunks(chunk_size)
+                    .map(|chunk| scope.spawn(|| chunk.iter()
+                        .map(|printer| {
+                            let _scope = printer_scope(&printer.printer_name.to_string_lossy());
+                            let subnet = discover_address(printer, &cache, false, false)
+                                .map(|address| subnet_of(&address, prefix_length))
+                                .unwrap_or_else(|| "unresolved".to_string());
+                            Some(subnet)
+                        })
+                        .collect::<Vec<_>>()))
+                    .collect();
+                workers.into_iter().flat_map(|worker| worker.join().unwrap_or_default()).collect()
+            });
+
+            if let Err(e) = cache.save() {
+                warn!("[{}] Could not save the address cache: {}", "run_summary", e);
+            }
+            subnets
+        }
+    };
+
+    let mut counts: Vec<SummaryRecord> = Vec::new();
+    for (printer, subnet) in wsd_printers.iter().zip(subnets) {
+        let driver_name = printer.driver_name.to_string_lossy();
+        match counts.iter_mut().find(|record| record.driver_name == driver_name && record.subnet == subnet) {
+            Some(record) => record.printers += 1,
+            None => counts.push(SummaryRecord { driver_name: driver_name.into_owned(), subnet, printers: 1 }),
+        }
+    }
+
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    for record in &counts {
+        *totals.entry(record.driver_name.clone()).or_default() += record.printers;
+    }
+
+    // Drivers with the most printers first, then each driver's subnets the same way
+    counts.sort_by(|a, b| {
+        totals[&b.driver_name].cmp(&totals[&a.driver_name])
+            .then_with(|| a.driver_name.cmp(&b.driver_name))
+            .then_with(|| b.printers.cmp(&a.printers))
+            .then_with(|| a.subnet.cmp(&b.subnet))
+    });
+
+    if !cli.format.is_human() {
+        print_records(&counts, cli.format);
+        return;
+    }
+
+    println!("WSD printers: {}", wsd_printers.len());
+    let mut previous: Option<&str> = None;
+    for record in &counts {
+        if previous != Some(record.driver_name.as_str()) {
+            println!(" {}: {}", record.driver_name, totals[&record.driver_name]);
+            previous = Some(&record.driver_name);
+        }
+        if let Some(subnet) = &record.subnet {
+            println!("  {}: {}", subnet, record.printers);
+        }
+    }
+}
+
+// Work out which address a printer should be moved to: an explicit --ip, then the --map file,
+// then the address cache, then what Function Discovery already knows, then WS-Discovery, then the registry cache
+fn target_ip(printer: &MinimalPrinterInfo, args: &ConvertArgs, ip_map: &IpMap, mac_map: &MacMap, cache: &AddressCache) -> Option<String> {
+    if let Some(ip) = &args.ip {
+        return Some(ip.clone());
+    }
+
+    if let Some(ip) = ip_map.get(printer.printer_name.to_string_lossy().as_ref()) {
+        info!("[{}] Using mapped address {} for {:?}", "target_ip", ip, printer.printer_name);
+        return Some(ip.clone());
+    }
+
+    // Also operator supplied, so --strict still goes by it, but only once discovery has had its chance
+    let from_mac = || {
+        let mac = mac_map.get(printer.printer_name.to_string_lossy().as_ref())?;
+        info!("[{}] Looking for {:?} by its MAC address {}", "target_ip", printer.printer_name, mac);
+        resolve_ip_from_mac(mac)
+    };
+
+    if args.strict {
+        info!("[{}] {:?} is not in the map and --strict is set", "target_ip", printer.printer_name);
+        return from_mac();
+    }
+
+    discover_ip(printer, args, cache).or_else(from_mac)
+}
+
+// The part of target_ip that goes by the printer's WSD port rather than its name
+fn discover_ip(printer: &MinimalPrinterInfo, args: &ConvertArgs, cache: &AddressCache) -> Option<String> {
+    discover_address(printer, cache, args.refresh, args.mdns)
+}
+
+fn discover_address(printer: &MinimalPrinterInfo, cache: &AddressCache, refresh: bool, mdns: bool) -> Option<String> {
+    let port_name = printer.port_name.to_string_lossy();
+
+    if !refresh {
+        if let Some(ip) = cache.get(&port_name) {
+            return Some(ip);
+        }
+    }
+
+    let discovered = parse_wsd_port(&port_name)
+        .and_then(|port| port.uuid)
+        .and_then(|uuid| resolve_via_function_discovery(&uuid))
+        .or_else(|| resolve_wsd_ip(printer));
+
+    if let Some(ip) = discovered {
+        cache.insert(&port_name, &ip);
+        return Some(ip);
+    }
+
+    if let Some(ip) = read_wsd_address_from_registry(&port_name) {
+        return Some(ip);
+    }
+
+    if !mdns {
+        return None;
+    }
+    let ip = resolve_via_mdns(&printer.printer_name.to_string_lossy(), DEFAULT_MDNS_TIMEOUT)?;
+    cache.insert(&port_name, &ip);
+    Some(ip)
+}
+
+// Exit unless the process is elevated, relaunching through UAC first when allowed to
+fn require_elevation(elevate: bool, command: &str) {
+    if is_elevated() {
+        return;
+    }
+
+    if elevate {
+        match relaunch_elevated() {
+            Ok(exit_code) => exit(exit_code as i32),
+            Err(e) => {
+                error!("[{}] Could not relaunch elevated: {}", "require_elevation", e);
+                eprintln!("Error: could not relaunch elevated: {}", e);
+                exit(EXIT_NOT_ELEVATED);
+            }
+        }
+    }
+
+    error!("[{}] {} requires an elevated process", "require_elevation", command);
+    eprintln!("Error: {} must be run from an elevated (Run as administrator) prompt, or with --elevate", command);
+    exit(EXIT_NOT_ELEVATED);
+}
+
+// The --mac-map file, or an empty map without one. A file that cannot be read ends the run, as with --map
+fn mac_map_or_exit(args: &ConvertArgs) -> MacMap {
+    let Some(path) = &args.mac_map else {
+        return MacMap::new();
+    };
+
+    load_mac_map(path).unwrap_or_else(|e| {
+        error!("[{}] Failed to load MAC map: {}", "mac_map_or_exit", e);
+        eprintln!("Error: failed to load MAC map: {}", e);
+        exit(EXIT_FAILURE);
+    })
+}
+
+// Every --exclude pattern along with those in --exclude-file, exiting if the file cannot be read
+fn exclusions_or_exit(args: &ConvertArgs) -> Vec<String> {
+    let mut exclusions = args.exclude.clone();
+
+    if let Some(path) = &args.exclude_file {
+        match load_exclude_list(path) {
+            Ok(patterns) => exclusions.extend(patterns),
+            Err(e) => {
+                error!("[{}] Failed to load exclusions: {}", "exclusions_or_exit", e);
+                eprintln!("Error: failed to load exclusions: {}", e);
+                exit(EXIT_FAILURE);
+            }
+        }
+    }
+
+    exclusions
+}
+
+// Port settings for newly created TCP/IP ports, defaulting the port number to the protocol's usual one
+fn port_config(args: &ConvertArgs) -> TcpipPortConfig {
+    let (protocol, default_port) = match args.protocol {
+        Protocol::Raw => (PortProtocol::Raw, DEFAULT_RAW_PORT_NUMBER),
+        Protocol::Lpr => (PortProtocol::Lpr, DEFAULT_LPR_PORT_NUMBER),
+    };
+
+    TcpipPortConfig {
+        protocol,
+        port_number: args.port_number.map_or(default_port, |port| port as u32),
+        lpr_queue: args.lpr_queue.clone(),
+        snmp_community: (!args.no_snmp).then(|| args.snmp_community.clone()),
+    }
+}
+
+// Check that something answers at ip and swap it for its host name when --prefer-hostname asks for that
+fn confirm_address(ip: String, args: &ConvertArgs, port_number: u16) -> Lookup {
+    // Pointing a printer at an address nothing answers on just leaves it broken in a different way
+    if !args.force && !is_reachable_on_port(&ip, port_number, DEFAULT_REACHABILITY_TIMEOUT_MS) {
+        return Lookup::Unreachable(ip);
+    }
+
+    // Ports keyed by name keep working when DHCP hands the printer a new address
+    if args.prefer_hostname {
+        match reverse_lookup(&ip) {
+            Some(host_name) if check_host_address(&host_name).is_err() => {
+                warn!("[{}] {} is longer than a port can hold, using {} instead", "confirm_address", host_name, ip);
+                eprintln!("Warning: the host name {} is longer than {} characters, using the address {}", host_name, MAX_HOST_ADDRESS_LEN, ip);
+                return Lookup::Found(ip);
+            }
+            Some(host_name) => return Lookup::Found(host_name),
+            None => {}
+        }
+        info!("[{}] No host name for {}, using the address", "confirm_address", ip);
+    }
+
+    Lookup::Found(ip)
+}
+
+// Resolve every WSD port in a pool separately, since each is a different device. A name-keyed --ip or --map
+// entry only says which device to use when the pool has a single WSD port
+fn look_up_pool(printer: &MinimalPrinterInfo, args: &ConvertArgs, ip_map: &IpMap, mac_map: &MacMap, cache: &AddressCache, port_number: u16) -> Lookup {
+    let wsd_ports: Vec<String> = printer.ports().into_iter().filter(|port| is_wsd_port(port)).collect();
+    let mut addresses = Vec::with_capacity(wsd_ports.len());
+
+    for port in &wsd_ports {
+        let member = MinimalPrinterInfo { port_name: port.into(), ..printer.clone() };
+        let ip = if wsd_ports.len() == 1 {
+            target_ip(&member, args, ip_map, mac_map, cache)
+        } else {
+            discover_ip(&member, args, cache)
+        };
+
+        let Some(ip) = ip else {
+            warn!("[{}] No address for pool member {} of {:?}", "look_up_pool", port, printer.printer_name);
+            return Lookup::NoAddress;
+        };
+
+        match confirm_address(ip, args, port_number) {
+            Lookup::Found(address) => addresses.push((port.clone(), address)),
+            other => return other,
+        }
+    }
+
+    Lookup::FoundPool(addresses)
+}
+
+// Resolve and probe one printer. Safe to run on several printers at once since nothing here touches the spooler
+fn look_up(printer: &MinimalPrinterInfo, args: &ConvertArgs, ip_map: &IpMap, mac_map: &MacMap, cache: &AddressCache, port_number: u16) -> Lookup {
+    // A printer already on a TCP/IP port is only planned when --ip, which needs --printer, gives it a new address
+    if printer.ports().iter().all(|port| is_ip_port(port)) && args.ip.is_none() {
+        return Lookup::AlreadyIp;
+    }
+
+    if printer.is_pooled() {
+        return look_up_pool(printer, args, ip_map, mac_map, cache, port_number);
+    }
+
+    let Some(ip) = target_ip(printer, args, ip_map, mac_map, cache) else {
+        return Lookup::NoAddress;
+    };
+
+    match confirm_address(ip, args, port_number) {
+        Lookup::Found(address) if wants_model(args) => look_up_model(address, args),
+        other => other,
+    }
+}
+
+// Whether new ports are named after the printer's model, by --name-by-model or a {model} in the template
+fn wants_model(args: &ConvertArgs) -> bool {
+    args.name_by_model || args.port_name_template.as_deref().is_some_and(template_uses_model)
+}
+
+// Name of the new port for a printer at address, from --port-name-template when given. A template asking for a
+// model the printer did not report falls back to IP_<address>, as --name-by-model does
+fn port_name_for(args: &ConvertArgs, printer_name: &str, address: &str, model: Option<&str>) -> Result<String, String> {
+    let Some(template) = &args.port_name_template else {
+        return Ok(match model {
+            Some(model) => model_port_name(model, address),
+            None => ip_port_name(address),
+        });
+    };
+
+    if model.is_none() && template_uses_model(template) {
+        warn!("[{}] No model for {:?}, naming its port {}", "port_name_for", printer_name, DEFAULT_PORT_NAME_TEMPLATE);
+        return render_port_name(DEFAULT_PORT_NAME_TEMPLATE, address, printer_name, None);
+    }
+    render_port_name(template, address, printer_name, model)
+}
+
+// Ask the printer at address for its model to name its port after, keeping the plain IP_<address> name when it
+// has no SNMP agent or will not answer to the community
+fn look_up_model(address: String, args: &ConvertArgs) -> Lookup {
+    match query_device_model(&address, &args.snmp_community, DEFAULT_SNMP_TIMEOUT) {
+        Some(model) => Lookup::FoundModel(address, model),
+        None => {
+            warn!("[{}] No model from SNMP at {}, naming its port by address", "look_up_model", address);
+            Lookup::Found(address)
+        }
+    }
+}
+
+// Run look_up over every printer on up to --concurrency worker threads. Results keep the input order;
+// printers not reached because of Ctrl-C are None
+fn look_up_all(printers: &[&MinimalPrinterInfo], args: &ConvertArgs, ip_map: &IpMap, mac_map: &MacMap, cache: &AddressCache, port_number: u16) -> Vec<Option<(Lookup, Duration)>> {
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<(Lookup, Duration)>>> = printers.iter().map(|_| Mutex::new(None)).collect();
+    let workers = args.concurrency.clamp(1, printers.len().max(1));
+    let progress = progress_bar(printers.len(), "Discovering");
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= printers.len() || stop_requested() {
+                    break;
+                }
+
+                let _scope = printer_scope(&printers[index].printer_name.to_string_lossy());
+                let started = Instant::now();
+                progress.set_message(printers[index].printer_name.to_string_lossy().into_owned());
+                let lookup = look_up(printers[index], args, ip_map, mac_map, cache, port_number);
+                *results[index].lock().unwrap() = Some((lookup, started.elapsed()));
+                progress.inc(1);
+            });
+        }
+    });
+    progress.finish_and_clear();
+
+    results.into_iter().map(|result| result.into_inner().unwrap()).collect()
+}
+
+// A bar counting through len printers on stderr, with a spinner and the printer being worked on. It is hidden
+// when stdout is not a terminal, which includes --quiet, so piped and scheduled runs print what they always did.
+// The log gets every line either way
+fn progress_bar(len: usize, prefix: &'static str) -> ProgressBar {
+    if !io::stdout().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let progress = ProgressBar::new(len as u64);
+    if let Ok(style) = ProgressStyle::with_template("{spinner} {prefix} [{pos}/{len}] {wide_msg}") {
+        progress.set_style(style);
+    }
+    progress.set_prefix(prefix);
+    progress.enable_steady_tick(Duration::from_millis(100));
+    progress
+}
+
+// Decide what each selected printer should be moved to, skipping any without a usable address
+fn build_plan(wsd_printers: &[MinimalPrinterInfo], args: &ConvertArgs, ip_map: &IpMap, mac_map: &MacMap, report: &mut ConversionReport) -> ConversionPlan {
+    let port_number = port_config(args).port_number;
+
+    let cache = AddressCache::load(&exe_dir().join(CACHE_FILE), Duration::from_secs(args.cache_ttl));
+
+    // Pools are left alone unless asked for, since every WSD port in them has to be resolved and replaced
+    let (pooled, wsd_printers): (Vec<&MinimalPrinterInfo>, Vec<&MinimalPrinterInfo>) = wsd_printers.iter()
+        .partition(|printer| printer.is_pooled() && !args.convert_pools);
+
+    for printer in &pooled {
+        let _scope = printer_scope(&printer.printer_name.to_string_lossy());
+        warn!("[{}] {:?} pools ports {:?}, skipping", "build_plan", printer.printer_name, printer.port_name);
+        eprintln!("Skipped {:?}: it is a printer pool (use --convert-pools to convert it)", printer.printer_name);
+        report.record_skipped(&printer.printer_name.to_string_lossy(), &printer.port_name.to_string_lossy(), "printer pool", Duration::ZERO);
+    }
+    let wsd_printers = wsd_printers.as_slice();
+
+    let started = Instant::now();
+    let lookups = look_up_all(wsd_printers, args, ip_map, mac_map, &cache, port_number as u16);
+    info!("[{}] Looked up {} printers in {:?} using up to {} threads", "build_plan", wsd_printers.len(), started.elapsed(), args.concurrency);
+    report.record_phase("discovery", started.elapsed());
+
+    // A cache that cannot be written only costs the next run some time
+    if let Err(e) = cache.save() {
+        warn!("[{}] Could not save the address cache: {}", "build_plan", e);
+    }
+
+    // Lookups cut short by an interrupt leave the rest of the printers unplanned
+    let durations: Vec<Duration> = lookups.iter().map_while(|lookup| lookup.as_ref().map(|(_, duration)| *duration)).collect();
+    if durations.len() < wsd_printers.len() {
+        warn!("[{}] Interrupted after looking up {} printers", "build_plan", durations.len());
+    }
+    let lookups = wsd_printers.iter().copied().zip(lookups.into_iter().map_while(|lookup| lookup.map(|(lookup, _)| lookup)));
+
+    // --ip and name-keyed --map entries only stand in for discovery on printers with a single WSD port
+    let manual = |printer: &MinimalPrinterInfo| {
+        (args.ip.is_some() || ip_map.contains_key(printer.printer_name.to_string_lossy().as_ref()))
+            && printer.ports().iter().filter(|port| is_wsd_port(port)).count() <= 1
+    };
+    let (plan, skipped) = ConversionPlan::build(lookups, manual, |printer_name, address, model| port_name_for(args, printer_name, address, model));
+
+    for (index, skip) in skipped {
+        let printer = wsd_printers[index];
+        let printer_name = printer.printer_name.to_string_lossy();
+        let from_port = printer.port_name.to_string_lossy();
+        let duration = durations[index];
+        let _scope = printer_scope(&printer_name);
+
+        match skip {
+            Skip::AlreadyIp => {
+                info!("[{}] {:?} is already on TCP/IP port {:?}, skipping", "build_plan", printer.printer_name, printer.port_name);
+                report.record_skipped(&printer_name, &from_port, "already on a TCP/IP port", duration);
+            }
+            Skip::NoAddress => {
+                warn!("[{}] Could not determine an address for {:?}, skipping", "build_plan", printer.printer_name);
+                eprintln!("Skipped {:?}: no address found (add it to --map, or pass --printer and --ip)", printer.printer_name);
+                report.record_skipped(&printer_name, &from_port, "no address found", duration);
+            }
+            Skip::Unreachable(ip) => {
+                warn!("[{}] {:?} does not answer at {}, skipping", "build_plan", printer.printer_name, ip);
+                eprintln!("Skipped {:?}: {} is not reachable on port {} (use --force to convert anyway)", printer.printer_name, ip, port_number);
+                report.record_skipped(&printer_name, &from_port, &format!("{} not reachable on port {}", ip, port_number), duration);
+            }
+            Skip::PortName(reason) => {
+                warn!("[{}] Cannot name the new port for {:?}: {}, skipping", "build_plan", printer_name, reason);
+                eprintln!("Skipped {:?}: cannot name its new port: {}", printer_name, reason);
+                report.record_skipped(&printer_name, &from_port, &format!("invalid port name: {}", reason), duration);
+            }
+        }
+    }
+
+    plan
+}
+
+// Load a reviewed plan, dropping entries whose printer has gone or has been moved since the plan was written
+fn load_reviewed_plan(path: &Path, all_printers: &[MinimalPrinterInfo], exclusions: &[String], report: &mut ConversionReport) -> ConversionPlan {
+    let reviewed = match ConversionPlan::load(path) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("[{}] Failed to load plan: {}", "load_reviewed_plan", e);
+            eprintln!("Error: failed to load plan: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    let mut plan = ConversionPlan::new();
+
+    for conversion in reviewed.conversions {
+        // A plan reviewed before a printer was added to the deny-list still must not touch it
+        if let Some(pattern) = matching_exclusion(&conversion.printer_name, exclusions) {
+            warn!("[{}] {} from the plan matches exclusion {:?}, skipping", "load_reviewed_plan", conversion.printer_name, pattern);
+            eprintln!("Skipped {:?}: excluded", conversion.printer_name);
+            report.record_skipped(&conversion.printer_name, &conversion.from_port, "excluded", Duration::ZERO);
+            continue;
+        }
+
+        let current_port = all_printers.iter()
+            .find(|printer| printer.printer_name == conversion.printer_os_name())
+            .map(|printer| printer.port_name.to_string_lossy().into_owned());
+
+        match current_port {
+            None => {
+                warn!("[{}] {} from the plan no longer exists, skipping", "load_reviewed_plan", conversion.printer_name);
+                eprintln!("Skipped {:?}: printer not found", conversion.printer_name);
+                report.record_skipped(&conversion.printer_name, &conversion.from_port, "printer not found", Duration::ZERO);
+            }
+            Some(port) if port == conversion.to_port => {
+                info!("[{}] {} is already on {}, nothing to do", "load_reviewed_plan", conversion.printer_name, port);
+                report.record_skipped(&conversion.printer_name, &conversion.from_port, "already converted", Duration::ZERO);
+            }
+            Some(port) if port != conversion.from_port => {
+                warn!("[{}] {} is on {} but the plan expected {}, skipping", "load_reviewed_plan", conversion.printer_name, port, conversion.from_port);
+                eprintln!("Skipped {:?}: now on {} rather than {} as planned", conversion.printer_name, port, conversion.from_port);
+                report.record_skipped(&conversion.printer_name, &conversion.from_port, &format!("now on {}", port), Duration::ZERO);
+            }
+            Some(_) => plan.conversions.push(conversion),
+        }
+    }
+
+    // A plan edited by hand may spell one port two ways
+    plan.share_ports();
+    plan
+}
+
+// Show the plan numbered and keep only the conversions the operator picks. Anything not picked is recorded as
+// skipped; an empty answer or the end of input picks nothing
+fn choose_conversions(plan: ConversionPlan, report: &mut ConversionReport) -> ConversionPlan {
+    if plan.is_empty() {
+        return plan;
+    }
+
+    let width = plan.len().to_string().len();
+    for (index, conversion) in plan.conversions.iter().enumerate() {
+        println!("{:>width$}) {}: {} -> {} ({})", index + 1, conversion.printer_name, conversion.from_port, conversion.to_port, conversion.resolved_ip, width = width);
+    }
+
+    let selected = loop {
+        print!("Convert which printers? (e.g. 1,3-5 or all, empty for none): ");
+        let _ = io::stdout().flush();
+
+        let mut answer = String::new();
+        match io::stdin().read_line(&mut answer) {
+            Ok(0) | Err(_) => break Vec::new(),
+            Ok(_) => {}
+        }
+
+        match parse_selection(&answer, plan.len()) {
+            Ok(selected) => break selected,
+            Err(e) => println!("{}", e),
+        }
+    };
+
+    info!("[{}] Operator selected {} of {} planned conversions", "choose_conversions", selected.len(), plan.len());
+
+    let mut chosen = ConversionPlan { resolutions: plan.resolutions, ..ConversionPlan::new() };
+    for (index, conversion) in plan.conversions.into_iter().enumerate() {
+        if selected.contains(&index) {
+            chosen.conversions.push(conversion);
+        } else {
+            report.record_skipped(&conversion.printer_name, &conversion.from_port, "not selected", Duration::ZERO);
+        }
+    }
+
+    chosen
+}
+
+// Open the Application log for --event-log. Auditing was asked for, so carrying on without it is not an option
+fn open_event_log(enabled: bool) -> Option<EventLog> {
+    if !enabled {
+        return None;
+    }
+
+    match EventLog::open() {
+        Ok(event_log) => Some(event_log),
+        Err(e) => {
+            error!("[{}] {}", "open_event_log", e);
+            eprintln!("Error: could not open the Windows Event Log, nothing was changed: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    }
+}
+
+// The targets as a plan of their own, for ConversionPlan::convert
+fn plan_of(targets: &[(&MinimalPrinterInfo, &PlannedConversion)]) -> ConversionPlan {
+    ConversionPlan {
+        conversions: targets.iter().map(|(_, conversion)| (*conversion).clone()).collect(),
+        ..ConversionPlan::new()
+    }
+}
+
+// Tell the operator, and the Event Log with --event-log, how converting printer went
+fn announce_outcome(printer: &MinimalPrinterInfo, outcome: &PrinterConversionOutcome, event_log: Option<&EventLog>) {
+    let to_port = outcome.to_port.as_deref().unwrap_or_default();
+
+    match &outcome.status {
+        OutcomeStatus::Converted => {
+            println!("Converted {:?}: {:?} -> {}", printer.printer_name, printer.port_name, to_port);
+            if let Some(event_log) = event_log {
+                event_log.converted(&outcome.printer_name, &outcome.from_port, to_port);
+            }
+        }
+        OutcomeStatus::Failed(e) => {
+            if let PrinterError::PortConflict { port, .. } = e {
+                eprintln!("Port {} already exists with other settings; use --reconfigure to change it to match", port);
+            }
+            eprintln!("Failed to convert {:?}: {}", printer.printer_name, e);
+            if let Some(event_log) = event_log {
+                event_log.failed(&outcome.printer_name, &outcome.from_port, to_port, e);
+            }
+        }
+        OutcomeStatus::Skipped(_) => {}
+    }
+}
+
+// What the operator should check on a printer that was just converted
+fn note_after_conversion(printer: &MinimalPrinterInfo, args: &ConvertArgs) {
+    if uses_v4_driver(printer) {
+        warn!("[{}] {:?} uses a v4 driver; check that it still prints", "run_convert", printer.printer_name);
+        println!(" Note: {:?} uses a v4 driver, print a test page to check it", printer.printer_name);
+    }
+    if printer.is_shared() {
+        warn!("[{}] {:?} is shared; clients will not see the new port until the spooler restarts", "run_convert", printer.printer_name);
+        if !args.restart_spooler {
+            println!(" Note: {:?} is shared, restart the Print Spooler (or use --restart-spooler) for the change to reach clients", printer.printer_name);
+        }
+    }
+}
+
+// Print a test page on a printer that was just converted, for --test-page. A page the spooler will not queue is
+// reported but does not undo the conversion, which was already verified
+fn print_test_page_after(printer: &MinimalPrinterInfo, conversion: &PlannedConversion, report: &mut ConversionReport) {
+    let result = print_test_page(&printer.printer_name, &conversion.to_port);
+    match &result {
+        Ok(job_id) => println!(" Test page queued on {:?} as job {}", printer.printer_name, job_id),
+        Err(e) => {
+            warn!("[{}] No test page for {:?}: {}", "print_test_page_after", printer.printer_name, e);
+            eprintln!(" Warning: the spooler did not queue a test page on {:?}: {}", printer.printer_name, e);
+        }
+    }
+    report.record_test_page(&conversion.printer_name, &result);
+}
+
+// Warn about each printer whose driver is not installed, since converting it will not make it print. Not being
+// able to list the drivers only skips the check
+fn warn_missing_drivers<'a>(server: Option<&str>, printers: impl IntoIterator<Item = &'a MinimalPrinterInfo>) {
+    let installed = match get_installed_drivers(server) {
+        Ok(installed) => installed,
+        Err(e) => {
+            warn!("[{}] Could not list the installed drivers, not checking them: {}", "warn_missing_drivers", e);
+            return;
+        }
+    };
+
+    for printer in missing_drivers(printers, &installed) {
+        warn!("[{}] {:?} uses {:?}, which is not installed", "warn_missing_drivers", printer.printer_name, printer.driver_name);
+        eprintln!("Warning: the driver {:?} of {:?} is not installed; it will not print on any port until the driver is reinstalled",
+            printer.driver_name, printer.printer_name);
+    }
+}
+
+// Whether printer uses a Type 4 driver. Printers on them have been seen to misbehave after a port change, so the
+// operator is told to check them
+fn uses_v4_driver(printer: &MinimalPrinterInfo) -> bool {
+    get_driver_info(&printer.printer_name).is_ok_and(|driver| driver.is_v4())
+}
+
+// Write a generated script, exiting if it cannot be saved
+fn write_script(path: &Path, contents: impl AsRef<[u8]>) {
+    if let Err(e) = std::fs::write(path, contents) {
+        let e = PrinterError::FileFailed { path: path.display().to_string(), reason: e.to_string() };
+        error!("[{}] {}", "write_script", e);
+        eprintln!("Error: {}", e);
+        exit(EXIT_FAILURE);
+    }
+    info!("[{}] Wrote {}", "write_script", path.display());
+}
+
+// What watch mode remembers a printer by: the device UUID in its WSD port, which survives a rename, or its name
+fn watch_key(printer: &MinimalPrinterInfo) -> String {
+    parse_wsd_port(&printer.port_name.to_string_lossy())
+        .and_then(|info| info.uuid)
+        .unwrap_or_else(|| printer.printer_name.to_string_lossy().into_owned())
+}
+
+// Sleep for up to duration, waking early if Ctrl-C is pressed
+fn sleep_unless_stopped(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while !stop_requested() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep((deadline - now).min(Duration::from_millis(250)));
+    }
+}
+
+// Poll for WSD printers every --interval seconds and convert each one the first time it is seen, until Ctrl-C.
+// A printer that is skipped for lack of an address is tried again on the next poll; one that was converted or
+// failed is not
+fn run_watch(args: &ConvertArgs, cli: &Cli) {
+    let server = cli.server.as_deref();
+    let spooler = spooler(cli);
+    let port_config = port_config(args);
+    let backup_dir = args.backup_dir.clone().unwrap_or_else(exe_dir);
+    let mut report = ConversionReport::new();
+    let mut handled: HashSet<String> = HashSet::new();
+
+    let ip_map = match &args.map {
+        Some(path) => match load_ip_map(path) {
+            Ok(ip_map) => ip_map,
+            Err(e) => {
+                error!("[{}] Failed to load map: {}", "run_watch", e);
+                eprintln!("Error: failed to load map: {}", e);
+                exit(EXIT_FAILURE);
+            }
+        },
+        None => IpMap::new(),
+    };
+    let mac_map = mac_map_or_exit(args);
+    let exclusions = exclusions_or_exit(args);
+
+    require_tcpip_monitor(server);
+    let event_log = open_event_log(args.event_log);
+    let journal = open_journal(&backup_dir, server);
+    println!("Watching for WSD printers every {}s, press Ctrl-C to stop", args.interval);
+
+    while !stop_requested() {
+        let all_printers = match spooler.enum_printers(server, enum_scope(cli.scope)) {
+            Ok(printers) => printers,
+            Err(e) => {
+                warn!("[{}] Could not enumerate printers, trying again next poll: {}", "run_watch", e);
+                sleep_unless_stopped(Duration::from_secs(args.interval));
+                continue;
+            }
+        };
+
+        let wsd_printers = exclude_printers(&select_wsd_printers(&all_printers, cli), &exclusions);
+        let new_printers: Vec<MinimalPrinterInfo> = wsd_printers.iter()
+            .filter(|printer| !handled.contains(&watch_key(printer)))
+            .cloned()
+            .collect();
+        info!("[{}] Poll found {} WSD printers, {} not yet handled", "run_watch", wsd_printers.len(), new_printers.len());
+
+        if !new_printers.is_empty() {
+            // Skips are not recorded, since a skipped printer comes up again on every poll until it has an address
+            let plan = build_plan(&new_printers, args, &ip_map, &mac_map, &mut ConversionReport::new());
+            let targets: Vec<_> = plan.conversions.iter()
+                .filter_map(|conversion| {
+                    new_printers.iter()
+                        .find(|printer| printer.printer_name == conversion.printer_os_name())
+                        .map(|printer| (printer, conversion))
+                })
+                .collect();
+
+            let to_back_up: Vec<MinimalPrinterInfo> = targets.iter().map(|(printer, _)| (*printer).clone()).collect();
+            let backup_path = backup_dir.join(timestamped_backup_name());
+
+            if targets.is_empty() {
+                info!("[{}] Nothing to convert this poll", "run_watch");
+            } else if let Err(e) = backup_printers(&to_back_up, &backup_path) {
+                error!("[{}] Backup failed, not converting this poll: {}", "run_watch", e);
+                eprintln!("Error: could not write backup, will try again next poll: {}", e);
+            } else {
+                let options = ConvertOptions { server, port_config: &port_config, reconfigure: args.reconfigure, journal: Some(&journal) };
+
+                // Outcomes come back in plan order, so the nth one is for the nth target
+                let mut done = 0;
+                let result = plan_of(&targets).convert(&spooler, &options, |outcome| {
+                    announce_outcome(targets[done].0, outcome, event_log.as_ref());
+                    done += 1;
+                    if stop_requested() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+                });
+
+                match result {
+                    Ok(outcomes) => {
+                        let mut converted: Vec<&MinimalPrinterInfo> = Vec::new();
+                        for ((printer, conversion), outcome) in targets.iter().zip(&outcomes) {
+                            report.record_outcome(outcome);
+                            if outcome.is_converted() {
+                                if args.test_page {
+                                    print_test_page_after(printer, conversion, &mut report);
+                                }
+                                converted.push(printer);
+                            }
+                            handled.insert(watch_key(printer));
+                        }
+
+                        if args.cleanup && !converted.is_empty() {
+                            clean_up_ports(server, &converted, &journal);
+                        }
+                    }
+                    Err(e) => {
+                        error!("[{}] Could not list the existing ports, trying again next poll: {}", "run_watch", e);
+                        eprintln!("Error: could not list the existing ports, will try again next poll: {}", e);
+                    }
+                }
+            }
+        }
+
+        sleep_unless_stopped(Duration::from_secs(args.interval));
+    }
+
+    info!("[{}] Stopped watching after handling {} printers", "run_watch", handled.len());
+    finish_report(&mut report, args);
+    exit(if report.failed > 0 { EXIT_FAILURE } else { stopped_exit_code() });
+}
+
+// The printer --printer names exactly, whatever port it is on, since the usual reason to name one is that it needs
+// fixing. Exits if there is no such printer, or if it is already on a TCP/IP port and no other --ip was given
+fn select_named_printer(all_printers: &[MinimalPrinterInfo], name: &str, args: &ConvertArgs, cli: &Cli) -> Vec<MinimalPrinterInfo> {
+    let Some(printer) = all_printers.iter().find(|printer| printer.printer_name.to_string_lossy() == name) else {
+        error!("[{}] No printer named {}", "select_named_printer", name);
+        eprintln!("Error: no printer named {}", name);
+        exit(EXIT_NO_PRINTERS);
+    };
+
+    // Re-running convert for a printer that was already moved is not an error. With --ip it is done once it is on
+    // the port that address would get, named as --port-name-template asks. A port named after a model the printer
+    // has not been asked for is not recognised, and converting it again finds the port already there
+    let port_name = printer.port_name.to_string_lossy();
+    let done = match args.ip.as_deref() {
+        Some(ip) => port_name_for(args, name, ip, None).is_ok_and(|planned| port_name.eq_ignore_ascii_case(&planned)),
+        None => printer.ports().iter().all(|port| is_ip_port(port)),
+    };
+    if done {
+        info!("[{}] {} is already on TCP/IP port {}, nothing to do", "select_named_printer", name, port_name);
+        println!("{} is already on {}", name, port_name);
+        exit(0);
+    }
+
+    if !printer.ports().iter().any(|port| is_wsd_port(port)) {
+        warn!("[{}] {} is on {}, not a WSD port; converting it as asked", "select_named_printer", name, port_name);
+    }
+
+    let printers = apply_filters(vec![printer.clone()], cli);
+    if printers.is_empty() {
+        error!("[{}] {} does not match the printer filters", "select_named_printer", name);
+        eprintln!("Error: {} does not match --name-filter, --driver, --only-online or --only-offline", name);
+        exit(EXIT_NO_PRINTERS);
+    }
+
+    printers
+}
+
+// The command line this process was started with, less the options a run over --servers-file handles itself
+fn forwarded_args() -> Vec<OsString> {
+    let mut forwarded = Vec::new();
+    let mut args = std::env::args_os().skip(1);
+
+    while let Some(arg) = args.next() {
+        let text = arg.to_string_lossy();
+        if text == "--servers-file" || text == "--report" {
+            args.next();
+        } else if !text.starts_with("--servers-file=") && !text.starts_with("--report=") {
+            forwarded.push(arg);
+        }
+    }
+
+    forwarded
+}
+
+// What a convert run that wrote no report meant by the code it exited with
+fn exit_code_reason(exit_code: i32) -> String {
+    match exit_code {
+        EXIT_NO_PRINTERS => "no WSD printers found".to_string(),
+        EXIT_NOT_ELEVATED => "not elevated".to_string(),
+        EXIT_WIN32_ERROR => "could not enumerate printers".to_string(),
+        EXIT_TIMED_OUT => "timed out".to_string(),
+        EXIT_INTERRUPTED => "interrupted".to_string(),
+        _ => format!("stopped with exit code {} before converting anything", exit_code),
+    }
+}
+
+// Convert every server listed in path, one after another. Each gets a convert run of its own in a child process
+// with the same options and --server set, so a server that cannot be reached or fails part way through cannot
+// take the others down with it, and their reports are gathered into one keyed by server
+fn run_convert_servers(path: &Path, args: &ConvertArgs, cli: &Cli) {
+    if cli.server.is_some() {
+        eprintln!("Error: --server cannot be used with --servers-file");
+        exit(EXIT_FAILURE);
+    }
+
+    let servers = match load_server_list(path) {
+        Ok(servers) => servers,
+        Err(e) => {
+            error!("[{}] Failed to load servers: {}", "run_convert_servers", e);
+            eprintln!("Error: failed to load servers: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("[{}] Could not find this executable: {}", "run_convert_servers", e);
+            eprintln!("Error: could not start convert runs: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+    let forwarded = forwarded_args();
+
+    let mut report = ConversionReport::new();
+    let mut without_printers = 0;
+    let mut failed_servers = 0;
+
+    for (index, server) in servers.iter().enumerate() {
+        if stop_requested() {
+            break;
+        }
+
+        info!("[{}] Converting {} ({} of {})", "run_convert_servers", server, index + 1, servers.len());
+        if cli.format.is_human() {
+            println!("== {} ==", server);
+        }
+
+        // A dry run writes no report, so there is nothing to gather from it but the exit code
+        let child_report = std::env::temp_dir().join(format!("wsd_to_ip-{}-{}.json", process::id(), index));
+        let mut command = process::Command::new(&exe);
+        command.args(&forwarded).arg("--server").arg(server);
+        if !args.dry_run {
+            command.arg("--report").arg(&child_report);
+        }
+
+        let started = Instant::now();
+        let (exit_code, mut reason) = match command.status() {
+            Ok(status) => (status.code().unwrap_or(EXIT_FAILURE), None),
+            Err(e) => (EXIT_FAILURE, Some(format!("could not start a convert run: {}", e))),
+        };
+
+        let result = if args.dry_run || reason.is_some() {
+            None
+        } else {
+            ConversionReport::load(&child_report)
+                .map_err(|e| info!("[{}] No report from {}: {}", "run_convert_servers", server, e))
+                .ok()
+        };
+        let _ = std::fs::remove_file(&child_report);
+
+        if exit_code != 0 && result.is_none() && reason.is_none() {
+            reason = Some(exit_code_reason(exit_code));
+        }
+
+        match exit_code {
+            0 => {}
+            EXIT_NO_PRINTERS => without_printers += 1,
+            _ => {
+                warn!("[{}] {} exited with {}", "run_convert_servers", server, exit_code);
+                failed_servers += 1;
+            }
+        }
+        if args.dry_run {
+            if let Some(reason) = &reason {
+                eprintln!("{}: {}", server, reason);
+            }
+        }
+
+        report.record_server(server, exit_code, result, reason, started.elapsed());
+    }
+
+    if !args.dry_run {
+        finish_report(&mut report, args);
+    }
+
+    if stop_requested() {
+        exit(stopped_exit_code());
+    }
+    if failed_servers > 0 {
+        exit(EXIT_FAILURE);
+    }
+    if without_printers == servers.len() {
+        exit(EXIT_NO_PRINTERS);
+    }
+}
+
+// The dry run a convert run without --allow-modify becomes. Options that only mean something when changes are
+// made are dropped, as they cannot be combined with --dry-run
+fn as_dry_run(args: &ConvertArgs) -> ConvertArgs {
+    ConvertArgs { dry_run: true, watch: false, atomic: false, report: None, event_log: false, test_page: false, ..args.clone() }
+}
+
+fn run_convert(args: &ConvertArgs, cli: &Cli) {
+    // A dry run or emitting a script only reads, but real changes need administrator rights and would otherwise
+    // fail part way through
+    let emitting = args.emit_powershell.is_some() || args.emit_reg.is_some();
+
+    let gated;
+    let args = if !args.allow_modify && !args.dry_run && !emitting {
+        warn!("[{}] --allow-modify was not given, running as a dry run: no printer or port will be changed", "run_convert");
+        eprintln!("Warning: --allow-modify was not given, so this is a dry run and nothing will be changed");
+        gated = as_dry_run(args);
+        &gated
+    } else {
+        args
+    };
+    if !args.dry_run && !emitting {
+        require_elevation(args.elevate, "convert");
+    }
+
+    install_interrupt_handler();
+
+    if args.watch {
+        return run_watch(args, cli);
+    }
+
+    if let Some(path) = &args.servers_file {
+        return run_convert_servers(path, args, cli);
+    }
+
+    let server = cli.server.as_deref();
+    let format = cli.format;
+    let mut report = ConversionReport::new();
+
+    let (all_printers, duration) = timed("Enumeration", || load_printers(cli));
+    report.record_phase("enumeration", duration);
+
+    let exclusions = exclusions_or_exit(args);
+
+    let mut plan = match &args.plan_in {
+        Some(path) => load_reviewed_plan(path, &all_printers, &exclusions, &mut report),
+        None => {
+            let (selected, duration) = timed("Filtering", || match &args.printer {
+                Some(name) => select_named_printer(&all_printers, name, args, cli),
+                None => select_wsd_printers(&all_printers, cli),
+            });
+            report.record_phase("filtering", duration);
+
+            let wsd_printers = exclude_printers(&selected, &exclusions);
+            for printer in selected.iter().filter(|printer| !wsd_printers.iter().any(|kept| kept.printer_name == printer.printer_name)) {
+                println!("Excluded {:?}", printer.printer_name);
+                report.record_skipped(&printer.printer_name.to_string_lossy(), &printer.port_name.to_string_lossy(), "excluded", Duration::ZERO);
+            }
+            if !selected.is_empty() && wsd_printers.is_empty() {
+                warn!("[{}] Every printer selected is excluded", "run_convert");
+                eprintln!("All {} printers selected are excluded, nothing to convert", selected.len());
+                exit(EXIT_NO_PRINTERS);
+            }
+
+            if wsd_printers.is_empty() {
+                warn!("[{}] No WSD connected printers found", "run_convert");
+                eprintln!("No WSD printers found");
+                exit(EXIT_NO_PRINTERS);
+            }
+
+            let ip_map = match &args.map {
+                Some(path) => match load_ip_map(path) {
+                    Ok(ip_map) => ip_map,
+                    Err(e) => {
+                        error!("[{}] Failed to load map: {}", "run_convert", e);
+                        eprintln!("Error: failed to load map: {}", e);
+                        exit(EXIT_FAILURE);
+                    }
+                },
+                None => IpMap::new(),
+            };
+
+            let mac_map = mac_map_or_exit(args);
+
+            build_plan(&wsd_printers, args, &ip_map, &mac_map, &mut report)
+        }
+    };
+
+    // Only a dry run goes on with a plan cut short, so the part that was worked out still gets printed and saved
+    if stop_requested() && !args.dry_run {
+        warn!("[{}] Interrupted while planning, nothing was changed", "run_convert");
+        eprintln!("Interrupted while planning, nothing was changed");
+        finish_report(&mut report, args);
+        exit(stopped_exit_code());
+    }
+
+    if args.interactive {
+        plan = choose_conversions(plan, &mut report);
+
+        if plan.is_empty() {
+            println!("Nothing selected, no printers were changed");
+            finish_report(&mut report, args);
+            return;
+        }
+    }
+
+    for (port, printers) in plan.shared_ports() {
+        info!("[{}] {} will be shared by {}", "run_convert", port, printers.join(", "));
+        report.record_shared_port(&port, &printers);
+    }
+
+    // Each planned conversion alongside the printer it applies to
+    let targets: Vec<_> = plan.conversions.iter()
+        .filter_map(|conversion| {
+            all_printers.iter()
+                .find(|printer| printer.printer_name == conversion.printer_os_name())
+                .map(|printer| (printer, conversion))
+        })
+        .collect();
+    warn_missing_drivers(server, targets.iter().map(|(printer, _)| *printer));
+
+    let port_config = port_config(args);
+    let spooler = spooler(cli);
+
+    // Scripts are for someone else to review and run, so nothing is changed here
+    if let Some(path) = &args.emit_powershell {
+        write_script(path, powershell_script(&plan, &port_config, server));
+        println!("Wrote PowerShell script for {} printers to {}", plan.len(), path.display());
+    }
+    if let Some(path) = &args.emit_reg {
+        write_script(path, utf16_with_bom(&reg_file(&plan, &port_config)));
+        println!("Wrote registry file for {} printers to {}. Restart the Print Spooler after importing it", plan.len(), path.display());
+    }
+    if emitting && !args.dry_run {
+        finish_report(&mut report, args);
+        return;
+    }
+
+    if args.dry_run {
+        // Listing ports only reads, and tells a port that would be created from one that would be reused
+        let existing_ports = if targets.is_empty() { Vec::new() } else { load_ports(server) };
+
+        for (printer, conversion) in &targets {
+            let _scope = printer_scope(&conversion.printer_name);
+            for NewPort { port, address } in &conversion.new_ports {
+                let exists = existing_ports.iter().any(|existing| existing.port_name.to_string_lossy().eq_ignore_ascii_case(port));
+                info!("[{}] Would call XcvDataW AddPort: {} -> {}:{} ({:?}){}", "run_convert", port, address, port_config.port_number,
+                    port_config.protocol, if exists { ", but it already exists" } else { "" });
+                if format.is_human() && !exists {
+                    println!("Would create port {} -> {}:{} ({:?})", port, address, port_config.port_number, port_config.protocol);
+                } else if format.is_human() {
+                    println!("Would reuse existing port {}", port);
+                }
+            }
+            info!("[{}] Would call SetPrinterW: {:?} port {:?} -> {}", "run_convert", printer.printer_name, printer.port_name, conversion.to_port);
+            if format.is_human() {
+                println!("Would move {:?}: {:?} -> {}", printer.printer_name, printer.port_name, conversion.to_port);
+            }
+            if printer.is_shared() && format.is_human() {
+                println!("Note: {:?} is shared, a spooler restart would be needed afterwards", printer.printer_name);
+            }
+            if uses_v4_driver(printer) && format.is_human() {
+                println!("Note: {:?} uses a v4 driver, check that it still prints afterwards", printer.printer_name);
+            }
+        }
+
+        if format.is_human() {
+            print!("{}", plan);
+        } else {
+            print_records(&plan.conversions, format);
+        }
+
+        if let Some(path) = &args.plan_out {
+            if let Err(e) = plan.save(path) {
+                error!("[{}] Failed to write plan: {}", "run_convert", e);
+                eprintln!("Error: failed to write plan: {}", e);
+                exit(EXIT_FAILURE);
+            }
+            println!("Wrote plan for {} printers to {}", plan.len(), path.display());
+        }
+
+        if stop_requested() {
+            exit(stopped_exit_code());
+        }
+        return;
+    }
+
+    if targets.is_empty() {
+        warn!("[{}] Nothing to convert", "run_convert");
+        finish_report(&mut report, args);
+        return;
+    }
+
+    require_tcpip_monitor(server);
+
+    // Take the safety-net backup before anything is mutated, and refuse to carry on without it
+    let to_back_up: Vec<MinimalPrinterInfo> = targets.iter().map(|(printer, _)| (*printer).clone()).collect();
+    let backup_dir = args.backup_dir.clone().unwrap_or_else(exe_dir);
+    let backup_path = backup_dir.join(timestamped_backup_name());
+
+    if let Err(e) = backup_printers(&to_back_up, &backup_path) {
+        error!("[{}] Backup failed, not converting anything: {}", "run_convert", e);
+        eprintln!("Error: could not write backup, nothing was changed: {}", e);
+        exit(EXIT_FAILURE);
+    }
+
+    println!("Backed up {} printers to {}", to_back_up.len(), backup_path.display());
+    let journal = open_journal(&backup_dir, server);
+
+    let event_log = open_event_log(args.event_log);
+
+    // The default printer is a per-user setting on this machine, so it only matters for local conversions
+    let default_printer = if server.is_none() { get_default_printer() } else { None };
+
+    let options = ConvertOptions { server, port_config: &port_config, reconfigure: args.reconfigure, journal: Some(&journal) };
+    let conversion_started = Instant::now();
+    let progress = progress_bar(targets.len(), "Converting");
+
+    // Outcomes come back in plan order, so the nth one is for the nth target
+    let mut done = 0;
+    let result = plan_of(&targets).convert(&spooler, &options, |outcome| {
+        let printer = targets[done].0;
+        done += 1;
+        progress.set_message(outcome.printer_name.clone());
+
+        // What happened to the printer is printed with the bar cleared, so the lines do not run into it
+        progress.suspend(|| {
+            announce_outcome(printer, outcome, event_log.as_ref());
+            if outcome.is_converted() {
+                note_after_conversion(printer, args);
+            }
+        });
+        progress.inc(1);
+
+        if stop_requested() || (args.atomic && !outcome.is_converted()) {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    progress.finish_and_clear();
+
+    let outcomes = match result {
+        Ok(outcomes) => outcomes,
+        Err(e) => {
+            error!("[{}] Could not list the existing ports, nothing was changed: {}", "run_convert", e);
+            eprintln!("Error: could not list the existing ports, nothing was changed: {}", e);
+            exit(EXIT_WIN32_ERROR);
+        }
+    };
+    for outcome in &outcomes {
+        report.record_outcome(outcome);
+    }
+
+    let mut failures = outcomes.iter().filter(|outcome| !outcome.is_converted()).count();
+    let converted: Vec<&MinimalPrinterInfo> = targets.iter().zip(&outcomes)
+        .filter(|(_, outcome)| outcome.is_converted())
+        .map(|((printer, _), _)| *printer)
+        .collect();
+
+    if args.atomic && failures > 0 {
+        roll_back(&spooler, server, &outcomes);
+        report.record_phase("conversion", conversion_started.elapsed());
+        finish_report(&mut report, args);
+        exit(EXIT_FAILURE);
+    }
+
+    if args.test_page {
+        for ((printer, conversion), _) in targets.iter().zip(&outcomes).filter(|(_, outcome)| outcome.is_converted()) {
+            print_test_page_after(printer, conversion, &mut report);
+        }
+    }
+
+    info!("[{}] Conversion of {} printers took {:?}", "run_convert", targets.len(), conversion_started.elapsed());
+    report.record_phase("conversion", conversion_started.elapsed());
+
+    if let Some(default_printer) = &default_printer {
+        if converted.iter().any(|printer| &printer.printer_name == default_printer) {
+            if let Err(e) = set_default_printer(default_printer) {
+                warn!("[{}] Could not restore {:?} as the default printer: {}", "run_convert", default_printer, e);
+                eprintln!("Warning: could not keep {:?} as the default printer: {}", default_printer, e);
+            }
+        }
+    }
+
+    if stop_requested() {
+        warn!("[{}] Interrupted: {} of {} printers converted, {} failed", "run_convert", converted.len(), targets.len(), failures);
+        eprintln!("Interrupted: {} of {} printers converted, {} failed. Backup: {}", converted.len(), targets.len(), failures, backup_path.display());
+
+        if args.atomic {
+            roll_back(&spooler, server, &outcomes);
+        }
+        finish_report(&mut report, args);
+        exit(stopped_exit_code());
+    }
+
+    if args.cleanup && !converted.is_empty() {
+        clean_up_ports(server, &converted, &journal);
+    }
+
+    if args.restart_spooler && !converted.is_empty() {
+        println!("Restarting the Print Spooler...");
+        match restart_spooler(server, DEFAULT_SPOOLER_TIMEOUT) {
+            Ok(()) => println!("Print Spooler restarted"),
+            Err(e) => {
+                error!("[{}] Spooler restart failed: {}", "run_convert", e);
+                eprintln!("Error: could not restart the Print Spooler: {}", e);
+                failures += 1;
+            }
+        }
+    }
+
+    finish_report(&mut report, args);
+
+    if failures > 0 {
+        exit(EXIT_FAILURE);
+    }
+}
+
+// Print the end-of-run summary and write it to --report if one was asked for
+fn finish_report(report: &mut ConversionReport, args: &ConvertArgs) {
+    let total = report.finish();
+    info!("[{}] Run took {:?} in total", "finish_report", total);
+
+    print!("{}", report);
+
+    if let Some(path) = &args.report {
+        if let Err(e) = report.save(path) {
+            error!("[{}] Failed to write report: {}", "finish_report", e);
+            eprintln!("Error: failed to write report: {}", e);
+        }
+    }
+}
+
+// Delete the ports converted printers were moved off. Printers are enumerated again first so a port another
+// printer still uses, including one added since this run started, is never removed
+fn clean_up_ports(server: Option<&str>, converted: &[&MinimalPrinterInfo], journal: &Journal) {
+    let still_in_use: Vec<String> = match get_printers_with_scope(server, EnumScope::All) {
+        Ok(printers) => printers.iter().map(|printer| printer.port_name.to_string_lossy().into_owned()).collect(),
+        Err(e) => {
+            error!("[{}] Could not re-enumerate printers, keeping every old port: {}", "clean_up_ports", e);
+            eprintln!("Cleanup skipped: {}", e);
+            return;
+        }
+    };
+
+    // Only the WSD members of a pool were replaced; the others are still in use by the same printer
+    let mut old_ports: Vec<String> = converted.iter()
+        .flat_map(|printer| printer.ports())
+        .filter(|port| is_wsd_port(port))
+        .collect();
+    old_ports.sort();
+    old_ports.dedup();
+
+    for port in old_ports {
+        // A printer can list several ports separated by commas when pooling is enabled
+        let referenced = still_in_use.iter()
+            .any(|ports| ports.split(',').any(|used| used.trim().eq_ignore_ascii_case(&port)));
+
+        if referenced {
+            info!("[{}] Keeping {}, another printer still uses it", "clean_up_ports", port);
+            println!("Kept port {} (still in use)", port);
+            continue;
+        }
+
+        // What undo needs to create the port again; ports of other monitors, such as WSD ones, have no settings to read
+        let old_settings = get_tcpip_port_config(server, &port)
+            .inspect_err(|e| info!("[{}] Undo cannot create {} again: {}", "clean_up_ports", port, e))
+            .ok();
+
+        match delete_port(server, &port) {
+            Ok(()) => {
+                info!("[{}] Removed {}", "clean_up_ports", port);
+                println!("Removed port {}", port);
+                let (old_address, old_config) = old_settings.unzip();
+                journal.record(Mutation::PortDeleted { port: port.clone(), old_address, old_config });
+            }
+            Err(e) => {
+                warn!("[{}] Could not remove {}: {}", "clean_up_ports", port, e);
+                eprintln!("Could not remove port {}: {}", port, e);
+            }
+        }
+    }
+}
+
+// Undo what this run has changed so far: every printer that was moved goes back to the port it was on, newest
+// first, including one that was moved but then failed its check, and then the ports the run created are deleted.
+// Anything that cannot be reverted is reported so it can be fixed from the backup file. The rollback is not
+// journaled; undoing the run afterwards finds each of its steps already done
+fn roll_back(spooler: &dyn SpoolerApi, server: Option<&str>, outcomes: &[PrinterConversionOutcome]) {
+    let moved: Vec<&PrinterConversionOutcome> = outcomes.iter().filter(|outcome| outcome.moved).collect();
+    let created: Vec<&String> = outcomes.iter().flat_map(|outcome| &outcome.created_ports).collect();
+    warn!("[{}] Rolling back {} moved printers and {} new ports", "roll_back", moved.len(), created.len());
+
+    for outcome in moved.iter().rev() {
+        let _scope = printer_scope(&outcome.printer_name);
+        info!("[{}] Moving {:?} back to {}", "roll_back", outcome.printer_name, outcome.from_port);
+
+        match spooler.set_printer(&outcome.printer_os_name(), &outcome.from_port) {
+            Ok(()) => println!("Rolled back {:?} -> {}", outcome.printer_name, outcome.from_port),
+            Err(e) => {
+                error!("[{}] Failed to roll back {:?}: {}", "roll_back", outcome.printer_name, e);
+                eprintln!("Failed to roll back {:?}: {} (use restore with the backup file)", outcome.printer_name, e);
+            }
+        }
+    }
+
+    // A port a printer could not be moved off of is still in use, and the spooler refuses to delete it
+    for port in created.iter().rev() {
+        info!("[{}] Removing {}, which this run created", "roll_back", port);
+
+        match spooler.delete_port(server, port) {
+            Ok(()) => println!("Removed port {}", port),
+            Err(e) => {
+                error!("[{}] Failed to remove {}: {}", "roll_back", port, e);
+                eprintln!("Failed to remove port {}: {}", port, e);
+            }
+        }
+    }
+}
+
+// Start recording this run's changes in the journal in backup_dir, and refuse to change anything without it
+fn open_journal(backup_dir: &Path, server: Option<&str>) -> Journal {
+    match Journal::open(&backup_dir.join(JOURNAL_FILE), server) {
+        Ok(journal) => journal,
+        Err(e) => {
+            error!("[{}] {}", "open_journal", e);
+            eprintln!("Error: could not open the change journal, nothing was changed: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    }
+}
+
+// Describe how undo reverses mutation
+fn describe_undo(mutation: &Mutation) -> String {
+    match mutation {
+        Mutation::PortCreated { port, .. } => format!("remove port {}", port),
+        Mutation::PortReconfigured { port, old_address, .. } => format!("point port {} back at {}", port, old_address),
+        Mutation::PrinterMoved { printer, old_port, new_port, .. } => format!("move {:?} from {} back to {}", printer, new_port, old_port),
+        Mutation::PortDeleted { port, .. } => format!("create port {} again", port),
+        Mutation::RunUndone { undone_run } => format!("nothing (run {} was undone)", undone_run),
+    }
+}
+
+// Why undo cannot take mutation back, if it cannot. gone holds the ports the run deleted that cannot be created
+// again, which a printer cannot be moved back onto either
+fn undo_blocker(mutation: &Mutation, gone: &[&str]) -> Option<String> {
+    match mutation {
+        Mutation::PortDeleted { port, .. } if !mutation.is_reversible() => match is_wsd_port(port) {
+            true => Some(format!("{} is a WSD port, which only comes back when Windows rediscovers its device", port)),
+            false => Some(format!("the journal does not hold the settings {} had, so it cannot be created again", port)),
+        },
+        Mutation::PrinterMoved { printer, old_port, .. } => old_port.split(',')
+            .map(str::trim)
+            .find(|port| gone.iter().any(|gone| gone.eq_ignore_ascii_case(port)))
+            .map(|port| format!("{} was deleted and cannot be created again; re-add {:?} once Windows rediscovers its device", port, printer)),
+        _ => None,
+    }
+}
+
+// Take mutation back. A step that is already undone, such as a port an --atomic rollback already deleted, counts as
+// done, so that an undo that stopped partway can be run again
+fn undo_mutation(spooler: &dyn SpoolerApi, server: Option<&str>, mutation: &Mutation) -> Result<(), PrinterError> {
+    match mutation {
+        Mutation::PortCreated { port, .. } => match spooler.delete_port(server, port) {
+            Err(PrinterError::PortDeletionFailed { code: ERROR_UNKNOWN_PORT, .. }) => {
+                info!("[{}] {} is already gone", "undo_mutation", port);
+                Ok(())
+            }
+            result => result,
+        },
+        Mutation::PortReconfigured { port, old_address, old_config, .. } => spooler.reconfigure_port(server, old_address, port, old_config),
+        Mutation::PrinterMoved { printer, raw_printer, old_port, .. } => spooler.set_printer(&name_from_raw(printer, raw_printer.as_deref()), old_port),
+        // Only a port whose settings were recorded can be made again; undo_blocker keeps the others from getting here
+        Mutation::PortDeleted { port, old_address: Some(address), old_config: Some(config) } => {
+            match spooler.add_port(server, address, port, config) {
+                Err(PrinterError::PortCreationFailed(ERROR_ALREADY_EXISTS)) => {
+                    info!("[{}] {} already exists again", "undo_mutation", port);
+                    Ok(())
+                }
+                result => result,
+            }
+        }
+        Mutation::PortDeleted { port, .. } => Err(PrinterError::UnknownPort { port: port.clone() }),
+        Mutation::RunUndone { .. } => Ok(()),
+    }
+}
+
+fn run_undo(args: &UndoArgs, cli: &Cli) {
+    let path = args.journal.clone().unwrap_or_else(|| exe_dir().join(JOURNAL_FILE));
+    let entries = match load_journal(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("[{}] Failed to load journal: {}", "run_undo", e);
+            eprintln!("Error: failed to load journal: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    let run = last_run(&entries);
+    let Some(first) = run.first() else {
+        warn!("[{}] Nothing left to undo in {}", "run_undo", path.display());
+        eprintln!("Error: nothing left to undo in {}", path.display());
+        exit(EXIT_NO_PRINTERS);
+    };
+    let run_id = first.run.clone();
+    info!("[{}] Undoing {} changes of run {}, started {}", "run_undo", run.len(), run_id, first.timestamp);
+
+    let gone: Vec<&str> = run.iter()
+        .filter_map(|entry| match &entry.mutation {
+            Mutation::PortDeleted { port, .. } if !entry.mutation.is_reversible() => Some(port.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    if args.dry_run {
+        println!("Would undo {} changes made from {}:", run.len(), first.timestamp);
+        for entry in run.iter().rev() {
+            match undo_blocker(&entry.mutation, &gone) {
+                Some(reason) => println!(" {} (cannot be undone: {})", describe_undo(&entry.mutation), reason),
+                None => println!(" {}", describe_undo(&entry.mutation)),
+            }
+        }
+        return;
+    }
+
+    require_elevation(args.elevate, "undo");
+    let spooler = spooler(cli);
+    let journal = match Journal::open(&path, None) {
+        Ok(journal) => journal,
+        Err(e) => {
+            error!("[{}] {}", "run_undo", e);
+            eprintln!("Error: could not open the change journal, nothing was changed: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    let mut failures = 0;
+    let mut skipped = 0;
+    for entry in run.iter().rev() {
+        let description = describe_undo(&entry.mutation);
+        if let Some(reason) = undo_blocker(&entry.mutation, &gone) {
+            warn!("[{}] Cannot {}: {}", "run_undo", description, reason);
+            println!("Skipped: {} ({})", description, reason);
+            skipped += 1;
+            continue;
+        }
+
+        match undo_mutation(&spooler, entry.server.as_deref(), &entry.mutation) {
+            Ok(()) => {
+                info!("[{}] Did {}", "run_undo", description);
+                println!("Undone: {}", description);
+            }
+            Err(e) => {
+                error!("[{}] Could not {}: {}", "run_undo", description, e);
+                eprintln!("Could not {}: {}", description, e);
+                failures += 1;
+            }
+        }
+    }
+
+    // A run whose reversible changes were only partly undone stays the newest, so undo can be run again once the
+    // cause is fixed. The changes that can never be undone do not hold it back
+    if failures > 0 {
+        eprintln!("{} of {} changes could not be undone; fix them and undo again, or use restore with the backup file", failures, run.len());
+        exit(EXIT_FAILURE);
+    }
+    journal.record(Mutation::RunUndone { undone_run: run_id });
+
+    if skipped > 0 {
+        println!("Undid every change that can be undone; {} of {} were left as they are", skipped, run.len());
+    }
+}
+
+fn run_restore(args: &RestoreArgs, cli: &Cli) {
+    require_elevation(args.elevate, "restore");
+
+    let backup = match load_backup(&args.backup) {
+        Ok(backup) => backup,
+        Err(e) => {
+            error!("[{}] Failed to load backup: {}", "run_restore", e);
+            eprintln!("Error: failed to load backup: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    info!("[{}] Restoring from backup taken {}", "run_restore", backup.created);
+
+    let printers: Vec<_> = backup.printers.iter()
+        .filter(|printer| args.printer.as_ref().is_none_or(|name| &printer.printer_name == name))
+        .collect();
+
+    if printers.is_empty() {
+        warn!("[{}] Nothing in {} to restore", "run_restore", args.backup.display());
+        eprintln!("Error: no matching printers in {}", args.backup.display());
+        exit(EXIT_NO_PRINTERS);
+    }
+
+    let mut failures = 0;
+
+    for printer in printers {
+        let _scope = printer_scope(&printer.printer_name);
+        match restore_printer(printer, cli.server.as_deref()) {
+            Ok(()) => println!("Restored {:?} -> {}", printer.printer_name, printer.port_name),
+            Err(e) => {
+                error!("[{}] Failed to restore {:?}: {}", "run_restore", printer.printer_name, e);
+                eprintln!("Failed to restore {:?}: {}", printer.printer_name, e);
+                failures += 1;
+            }
+        }
+    }
+
+    if failures > 0 {
+        exit(EXIT_FAILURE);
+    }
+}
+
+pub fn run() {
+    // Parse arguments first so bad input is rejected before anything touches the spooler. The config file only
+    // fills in what the command line left out
+    let cli = match parse_with_config() {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("Error: could not load the config file: {}", e);
+            exit(EXIT_FAILURE);
+        }
+    };
+
+    init_logging(&cli);
+
+    if cli.quiet {
+        // The selection prompt would be swallowed and the run left waiting on input nobody can see
+        if matches!(&cli.command, Some(Command::Convert(args)) if args.interactive) {
+            eprintln!("Error: --quiet cannot be used with --interactive");
+            exit(EXIT_FAILURE);
+        }
+        silence_stdout();
+    }
+
+    if cli.list_interfaces {
+        run_list_interfaces(&cli);
+        return;
+    }
+    apply_discovery_interface(&cli);
+
+    let Some(seconds) = cli.timeout else {
+        run_command(&cli);
+        return;
+    };
+
+    // The spooler and the network can both hang with no timeout of their own, so the command runs on a worker
+    // thread and this one only keeps the clock
+    let (done_sender, done) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        run_command(&cli);
+        let _ = done_sender.send(());
+    });
+
+    match done.recv_timeout(Duration::from_secs(seconds)) {
+        Ok(()) => {
+            let _ = worker.join();
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            // The worker panicked and has already printed why
+            exit(EXIT_FAILURE);
+        }
+        Err(RecvTimeoutError::Timeout) => time_out(seconds, &done),
+    }
+}
+
+// Point stdout at NUL for --quiet, so every listing, plan and summary is dropped while stderr still reports errors.
+// std looks the handle up on each write, so this covers everything printed from here on
+fn silence_stdout() {
+    let null = match OpenOptions::new().write(true).open("NUL") {
+        Ok(null) => null,
+        Err(e) => {
+            warn!("[{}] Could not open NUL, output will not be suppressed: {}", "silence_stdout", e);
+            return;
+        }
+    };
+
+    // The handle is left open for the rest of the process
+    if unsafe { SetStdHandle(STD_OUTPUT_HANDLE, null.into_raw_handle() as _) } == 0 {
+        let error_code = unsafe { GetLastError() };
+        warn!("[{}] SetStdHandle failed, output will not be suppressed: {}", "silence_stdout", format_error_code(error_code).unwrap_or_default());
+    }
+}
+
+// Stop a command that ran past --timeout. Discovery is abandoned at once and a conversion stops after the printer
+// it is on, as with Ctrl-C; whatever is still running after TIMEOUT_GRACE is abandoned with the process
+fn time_out(seconds: u64, done: &mpsc::Receiver<()>) -> ! {
+    error!("[{}] Timed out after {} seconds", "time_out", seconds);
+    eprintln!("Error: timed out after {} seconds, stopping", seconds);
+
+    TIMED_OUT.store(true, Ordering::SeqCst);
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+    cancel_discovery();
+
+    if done.recv_timeout(TIMEOUT_GRACE).is_err() {
+        warn!("[{}] Still running {:?} after the timeout, exiting anyway", "time_out", TIMEOUT_GRACE);
+    }
+    exit(EXIT_TIMED_OUT);
+}
+
+// Every subcommand goes through the spooler, and with the service stopped each call only fails with a bare
+// RPC error. Catch that up front and either start the service or say plainly what is wrong
+fn require_spooler(cli: &Cli) {
+    let server = cli.server.as_deref();
+    if spooler_is_running(server) {
+        return;
+    }
+
+    if !cli.start_spooler {
+        error!("[{}] Print Spooler service is not running", "require_spooler");
+        eprintln!("Error: Print Spooler service is not running. Start it, or run again with --start-spooler");
+        exit(EXIT_WIN32_ERROR);
+    }
+
+    eprintln!("Print Spooler service is not running, starting it...");
+    if let Err(e) = start_spooler(server, DEFAULT_SPOOLER_TIMEOUT) {
+        error!("[{}] Could not start the Print Spooler: {}", "require_spooler", e);
+        eprintln!("Error: could not start the Print Spooler service: {}", e);
+        exit(EXIT_WIN32_ERROR);
+    }
+    println!("Started the Print Spooler service");
+}
+
+fn run_command(cli: &Cli) {
+    // doctor reports on the spooler itself rather than stopping at it
+    if !matches!(cli.command, Some(Command::Doctor(_))) {
+        require_spooler(cli);
+    }
+
+    match &cli.command {
+        None => run_list(&ListArgs::default(), cli),
+        Some(Command::List(args)) => run_list(args, cli),
+        Some(Command::Convert(args)) => run_convert(args, cli),
+        Some(Command::Restore(args)) => run_restore(args, cli),
+        Some(Command::Undo(args)) => run_undo(args, cli),
+        Some(Command::Ports) => run_ports(cli),
+        Some(Command::Monitors) => run_monitors(cli),
+        Some(Command::Status) => run_status(cli),
+        Some(Command::Summary(args)) => run_summary(args, cli),
+        Some(Command::Doctor(args)) => run_doctor(args, cli),
+    }
+}
@@ -0,0 +1,71 @@
+use std::mem::size_of;
+use std::net::Ipv4Addr;
+use std::ptr::null_mut;
+
+use log::{info, warn};
+use winapi::shared::ipmib::{MIB_IPNETROW, MIB_IPNETTABLE, MIB_IPNET_TYPE_INVALID};
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_NO_DATA, NO_ERROR};
+use winapi::um::iphlpapi::GetIpNetTable;
+
+use crate::error::format_error_code;
+use crate::mapping::parse_mac;
+
+// Every row of the IPv4 ARP cache, sized by asking GetIpNetTable first. The table can grow between the two
+// calls, so a buffer that turns out too small is simply grown and tried again
+fn ip_net_table() -> Option<Vec<MIB_IPNETROW>> {
+    let mut size: u32 = 0;
+    // u64s keep the buffer aligned for the DWORDs in the table
+    let mut buffer: Vec<u64> = Vec::new();
+
+    loop {
+        let table = if buffer.is_empty() { null_mut() } else { buffer.as_mut_ptr() as *mut MIB_IPNETTABLE };
+        let result = unsafe { GetIpNetTable(table, &mut size, 0) };
+
+        match result {
+            NO_ERROR if !buffer.is_empty() => break,
+            NO_ERROR | ERROR_NO_DATA => return Some(Vec::new()),
+            ERROR_INSUFFICIENT_BUFFER => buffer = vec![0u64; (size as usize).div_ceil(size_of::<u64>())],
+            error_code => {
+                warn!("[{}] GetIpNetTable failed: {}", "ip_net_table", format_error_code(error_code).unwrap_or_default());
+                return None;
+            }
+        }
+    }
+
+    let table = buffer.as_ptr() as *const MIB_IPNETTABLE;
+    let rows = unsafe {
+        let count = (*table).dwNumEntries as usize;
+        std::slice::from_raw_parts((*table).table.as_ptr(), count).to_vec()
+    };
+
+    Some(rows)
+}
+
+// Find the IPv4 address the ARP cache holds for mac. The cache only knows devices this machine has talked to
+// recently on its own subnets, so a printer nobody has printed to in a while may not be in it
+pub fn resolve_ip_from_mac(mac: &str) -> Option<String> {
+    let Some(wanted) = parse_mac(mac) else {
+        warn!("[{}] {:?} is not a MAC address", "resolve_ip_from_mac", mac);
+        return None;
+    };
+
+    let rows = ip_net_table()?;
+    info!("[{}] Searching {} ARP cache entries for {}", "resolve_ip_from_mac", rows.len(), mac);
+
+    let row = rows.iter().find(|row| {
+        row.dwPhysAddrLen as usize == wanted.len()
+            && row.bPhysAddr[..wanted.len()] == wanted
+            && row.Type != MIB_IPNET_TYPE_INVALID
+    });
+
+    let Some(row) = row else {
+        info!("[{}] {} is not in the ARP cache", "resolve_ip_from_mac", mac);
+        return None;
+    };
+
+    // dwAddr holds the address in network byte order
+    let ip = Ipv4Addr::from(row.dwAddr.to_ne_bytes()).to_string();
+    info!("[{}] ARP cache has {} at {}", "resolve_ip_from_mac", mac, ip);
+
+    Some(ip)
+}
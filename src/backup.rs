@@ -0,0 +1,163 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::BufReader;
+#[cfg(windows)]
+use std::io::BufWriter;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+use std::path::Path;
+
+#[cfg(windows)]
+use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+#[cfg(windows)]
+use time::format_description::well_known::Rfc3339;
+#[cfg(windows)]
+use winapi::um::winspool::{PRINTER_INFO_2W, PRINTER_ACCESS_USE};
+
+#[cfg(windows)]
+use crate::convert::{address_from_ip_port_name, PrinterHandle, get_printer_info_2, create_tcpip_port_on_server, set_printer_port};
+use crate::error::PrinterError;
+#[cfg(windows)]
+use crate::printers::{raw_name, MinimalPrinterInfo};
+use crate::printers::name_from_raw;
+#[cfg(windows)]
+use crate::wide::{wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+// The level-2 settings of one printer as they were when the backup was taken
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PrinterBackup {
+    pub printer_name: String,
+    // The name as the spooler gave it when that is not valid Unicode, see raw_name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_printer_name: Option<Vec<u16>>,
+    pub server_name: String,
+    pub share_name: String,
+    pub port_name: String,
+    pub driver_name: String,
+    pub comment: String,
+    pub location: String,
+    pub sep_file: String,
+    pub print_processor: String,
+    pub datatype: String,
+    pub parameters: String,
+    pub attributes: u32,
+    pub priority: u32,
+    pub default_priority: u32,
+    pub start_time: u32,
+    pub until_time: u32,
+}
+
+impl PrinterBackup {
+    // The name to open the printer by, which printer_name is only a display form of when the name is not valid Unicode
+    pub fn printer_os_name(&self) -> OsString {
+        name_from_raw(&self.printer_name, self.raw_printer_name.as_deref())
+    }
+}
+
+// What backup_printers writes: when it was taken and every printer it covers
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub created: String,
+    pub printers: Vec<PrinterBackup>,
+}
+
+#[cfg(windows)]
+fn lossy_wide(ptr: *const u16) -> String {
+    OsString::from_wide(&wide_str_from_raw_ptr(ptr, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned()
+}
+
+// Capture the current level-2 settings of one printer
+#[cfg(windows)]
+pub fn capture_printer(printer: &MinimalPrinterInfo) -> Result<PrinterBackup, PrinterError> {
+    let printer_name = printer.printer_name.to_string_lossy().into_owned();
+    let handle = PrinterHandle::open(&printer.printer_name, PRINTER_ACCESS_USE)?;
+    let buffer = get_printer_info_2(&handle, &printer_name)?;
+
+    let info = unsafe { &*(buffer.as_ptr() as *const PRINTER_INFO_2W) };
+
+    Ok(PrinterBackup {
+        printer_name: lossy_wide(info.pPrinterName),
+        raw_printer_name: raw_name(&printer.printer_name),
+        server_name: lossy_wide(info.pServerName),
+        share_name: lossy_wide(info.pShareName),
+        port_name: lossy_wide(info.pPortName),
+        driver_name: lossy_wide(info.pDriverName),
+        comment: lossy_wide(info.pComment),
+        location: lossy_wide(info.pLocation),
+        sep_file: lossy_wide(info.pSepFile),
+        print_processor: lossy_wide(info.pPrintProcessor),
+        datatype: lossy_wide(info.pDatatype),
+        parameters: lossy_wide(info.pParameters),
+        attributes: info.Attributes,
+        priority: info.Priority,
+        default_priority: info.DefaultPriority,
+        start_time: info.StartTime,
+        until_time: info.UntilTime,
+    })
+}
+
+// Write the current settings of printers to path as JSON, failing if any printer cannot be read
+// so a conversion never goes ahead without a complete safety net
+#[cfg(windows)]
+pub fn backup_printers(printers: &[MinimalPrinterInfo], path: &Path) -> Result<(), PrinterError> {
+    let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+    let mut backups = Vec::with_capacity(printers.len());
+    for printer in printers {
+        backups.push(capture_printer(printer)?);
+    }
+
+    let created = OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default();
+    let backup_file = BackupFile { created, printers: backups };
+
+    let file = File::create(path).map_err(|e| file_error(e.to_string()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &backup_file).map_err(|e| {
+        error!("[{}] Failed to write backup to {}: {}", "backup_printers", path.display(), e);
+        file_error(e.to_string())
+    })?;
+
+    info!("[{}] Backed up {} printers to {}", "backup_printers", backup_file.printers.len(), path.display());
+
+    Ok(())
+}
+
+// Read a file written by backup_printers
+pub fn load_backup(path: &Path) -> Result<BackupFile, PrinterError> {
+    let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+    let file = File::open(path).map_err(|e| file_error(e.to_string()))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| file_error(e.to_string()))
+}
+
+// Name for a backup taken now, e.g. wsd_to_ip-backup-20240131-142501.json
+pub fn timestamped_backup_name() -> String {
+    let now = OffsetDateTime::now_utc();
+    format!(
+        "wsd_to_ip-backup-{:04}{:02}{:02}-{:02}{:02}{:02}.json",
+        now.year(), now.month() as u8, now.day(), now.hour(), now.minute(), now.second()
+    )
+}
+
+// Put a printer back on the port recorded in its backup. A Standard TCP/IP port (IP_<addr>) that has since
+// been deleted is created again first; other ports, such as WSD ones, can only come back through their own monitor
+#[cfg(windows)]
+pub fn restore_printer(backup: &PrinterBackup, server: Option<&str>) -> Result<(), PrinterError> {
+    let printer_name = backup.printer_os_name();
+    let printer_name = printer_name.as_os_str();
+
+    match set_printer_port(printer_name, &backup.port_name) {
+        Err(PrinterError::UnknownPort { port }) => {
+            let Some(ip) = address_from_ip_port_name(&port) else {
+                warn!("[{}] {} no longer exists and is not a port this tool can create", "restore_printer", port);
+                return Err(PrinterError::UnknownPort { port });
+            };
+
+            info!("[{}] Re-creating missing port {} before restoring {}", "restore_printer", port, backup.printer_name);
+            create_tcpip_port_on_server(server, ip, &port)?;
+            set_printer_port(printer_name, &port)
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::discovery::endpoint_uuid_from_port;
+use crate::error::PrinterError;
+
+// How long a cached WS-Discovery answer is trusted before the device is probed again
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub const CACHE_FILE: &str = "wsd_to_ip-cache.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    ip: String,
+    // Unix timestamp of when the address was resolved
+    resolved_at: i64,
+}
+
+// Addresses found through WS-Discovery, keyed by the device UUID in the WSD port name so the entry
+// survives the printer being renamed. Shared between the discovery threads, hence the lock
+pub struct AddressCache {
+    path: PathBuf,
+    ttl: Duration,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl AddressCache {
+    // Open the cache at path. A missing or unreadable file just means starting empty
+    pub fn load(path: &Path, ttl: Duration) -> AddressCache {
+        let entries = match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("[{}] Ignoring unreadable cache {}: {}", "AddressCache::load", path.display(), e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        info!("[{}] Loaded {} cached addresses from {}", "AddressCache::load", entries.len(), path.display());
+
+        AddressCache { path: path.to_path_buf(), ttl, entries: Mutex::new(entries) }
+    }
+
+    // Cached address for the device behind a WSD port, if there is one younger than the TTL
+    pub fn get(&self, port_name: &str) -> Option<String> {
+        let uuid = endpoint_uuid_from_port(port_name)?;
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(&uuid)?;
+
+        let age = OffsetDateTime::now_utc().unix_timestamp() - entry.resolved_at;
+        if age < 0 || age as u64 >= self.ttl.as_secs() {
+            info!("[{}] Cached address for {} is {}s old, ignoring it", "AddressCache::get", uuid, age);
+            return None;
+        }
+
+        info!("[{}] Using cached address {} for {}", "AddressCache::get", entry.ip, uuid);
+        Some(entry.ip.clone())
+    }
+
+    pub fn insert(&self, port_name: &str, ip: &str) {
+        if let Some(uuid) = endpoint_uuid_from_port(port_name) {
+            let entry = CacheEntry { ip: ip.to_string(), resolved_at: OffsetDateTime::now_utc().unix_timestamp() };
+            self.entries.lock().unwrap().insert(uuid, entry);
+        }
+    }
+
+    // Write the cache back to the file it was loaded from
+    pub fn save(&self) -> Result<(), PrinterError> {
+        let file_error = |reason: String| PrinterError::FileFailed { path: self.path.display().to_string(), reason };
+
+        let entries = self.entries.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*entries).map_err(|e| file_error(e.to_string()))?;
+        fs::write(&self.path, json).map_err(|e| file_error(e.to_string()))?;
+
+        info!("[{}] Saved {} cached addresses to {}", "AddressCache::save", entries.len(), self.path.display());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,458 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
+use serde::Deserialize;
+use simplelog::LevelFilter;
+
+use wsd_to_ip::check_port_name_template;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0    Success, including when there was nothing to do
+  1    Some printers failed to convert or restore, or a file could not be read or written
+  2    No printers found to work on (clap also uses 2 for invalid arguments)
+  3    Not elevated, and --elevate was not given or was refused
+  4    The spooler or another Windows API failed
+  5    --timeout ran out before the command finished
+  130  Stopped with Ctrl-C";
+
+// Command line interface for the binary. Parsed and validated before any Win32 call is made
+#[derive(Parser, Debug)]
+#[command(name = "wsd_to_ip", version, about = "Find printers on WSD ports and move them to Standard TCP/IP ports")]
+#[command(after_help = EXIT_CODES_HELP)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Read default options from this TOML file instead of wsd_to_ip.toml next to the executable
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Enumerate and convert printers on this print server instead of the local machine
+    #[arg(long, global = true, value_name = r"\\HOST")]
+    pub server: Option<String>,
+
+    /// Only consider printers whose name matches this regular expression
+    #[arg(long, global = true, value_name = "REGEX", value_parser = parse_regex)]
+    pub name_filter: Option<Regex>,
+
+    /// Only consider printers whose driver name contains this text (case-insensitive)
+    #[arg(long, global = true, value_name = "CONTAINS")]
+    pub driver: Option<String>,
+
+    /// Only consider printers the spooler reports as online
+    #[arg(long, global = true, conflicts_with = "only_offline")]
+    pub only_online: bool,
+
+    /// Only consider printers the spooler reports as offline or unavailable
+    #[arg(long, global = true)]
+    pub only_offline: bool,
+
+    /// Driver names, comma-separated with * and ? wildcards, of software printers such as Microsoft Print to PDF
+    /// that are never treated as WSD printers. Replaces the built-in list, as virtual-drivers in the config file also
+    /// does; pass "" to treat every driver as a device
+    #[arg(long, global = true, value_name = "PATTERNS", value_delimiter = ',')]
+    pub virtual_drivers: Option<Vec<String>>,
+
+    /// Which printers to enumerate. connections is needed to see per-user WSD connections on RDS hosts
+    #[arg(long, global = true, value_enum, default_value_t = Scope::Local)]
+    pub scope: Scope,
+
+    /// How many times to try enumerating printers when the spooler reports a transient error
+    #[arg(long, global = true, value_name = "N", default_value_t = 3)]
+    pub enum_retries: u32,
+
+    /// Give up with exit code 5 if the command has not finished after this many seconds (default: no limit)
+    #[arg(long, global = true, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Start the Print Spooler service if it is stopped instead of exiting
+    #[arg(long, global = true)]
+    pub start_spooler: bool,
+
+    /// Minimum level written to the log: off, error, warn, info, debug or trace
+    #[arg(long, global = true, env = "WSD_TO_IP_LOG", value_name = "LEVEL", default_value = "info")]
+    pub log_level: LevelFilter,
+
+    /// How to write the log file: text for people, json for one JSON object per line (the console stays text)
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Write the log here instead of wsd_to_ip.log next to the executable
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Roll wsd_to_ip.log over to wsd_to_ip.log.1 once it grows past this many megabytes
+    #[arg(long, global = true, value_name = "MB", default_value_t = 10)]
+    pub log_max_size: u64,
+
+    /// Also write log messages to the console
+    #[arg(long, short, global = true)]
+    pub verbose: bool,
+
+    /// Print nothing to stdout, leaving the log file, --report and the exit code to tell what happened
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Also look up each printer's driver version and environment (one extra spooler call per printer)
+    #[arg(long, global = true)]
+    pub driver_details: bool,
+
+    /// Also ask each WSD device for its manufacturer, model and serial number (resolves every device on the network)
+    #[arg(long, global = true)]
+    pub device_info: bool,
+
+    /// How to print the printer inventory
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// When to colour the status column of --format table
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Send WS-Discovery and mDNS multicasts from the interface with this local IPv4 address, for print servers
+    /// whose routing table sends them out of a network the printers are not on
+    #[arg(long, global = true, value_name = "IP")]
+    pub discovery_interface: Option<Ipv4Addr>,
+
+    /// List this machine's network interfaces and their IPv4 addresses, for choosing --discovery-interface, and exit
+    #[arg(long)]
+    pub list_interfaces: bool,
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Printers installed on this machine
+    Local,
+    /// Per-user connections to shared printers
+    Connections,
+    /// Local printers and connections that print over the network
+    Network,
+    /// Local printers and connections
+    All,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable listing
+    Text,
+    /// JSON array of printer objects
+    Json,
+    /// CSV with a header row
+    Csv,
+    /// Column-aligned table of name, port, driver and status (other commands print text)
+    Table,
+}
+
+impl OutputFormat {
+    // Text and table are both formatted by each command rather than serialized
+    pub fn is_human(self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Table)
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Timestamped lines for reading
+    Text,
+    /// Newline-delimited JSON objects with ts, level, target and msg fields
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colour when printing to a console and NO_COLOR is not set
+    Auto,
+    /// Always colour, even when redirected
+    Always,
+    /// Never colour
+    Never,
+}
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    /// Raw (JetDirect/AppSocket) printing, usually on 9100
+    Raw,
+    /// Line Printer Remote, usually on 515
+    Lpr,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List printers connected through WSD ports (the default)
+    List(ListArgs),
+
+    /// Move WSD printers to Standard TCP/IP ports
+    Convert(Box<ConvertArgs>),
+
+    /// Put printers back on the ports recorded in a backup written by convert
+    Restore(RestoreArgs),
+
+    /// Reverse the changes the most recent convert run made, newest first, as recorded in its journal
+    Undo(UndoArgs),
+
+    /// List the ports installed in the spooler and the monitors that own them
+    Ports,
+
+    /// List the port monitors installed in the spooler
+    Monitors,
+
+    /// Summarise how many printers are on WSD ports and how many on TCP/IP ports
+    Status,
+
+    /// Count WSD printers by driver, to see which driver families a conversion would touch most
+    Summary(SummaryArgs),
+
+    /// Check the things a conversion depends on, such as the spooler, elevation, drivers and multicast, and report each
+    Doctor(DoctorArgs),
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct ConvertArgs {
+    /// Convert exactly the printer with this name, whether or not it is on a WSD port (default: every WSD printer)
+    #[arg(long, value_name = "NAME")]
+    pub printer: Option<String>,
+
+    /// Address to point the printer at instead of discovering it
+    #[arg(long, value_name = "ADDR", requires = "printer", value_parser = parse_ip_address)]
+    pub ip: Option<String>,
+
+    /// TOML or JSON file mapping printer names to addresses, consulted before discovery
+    #[arg(long, value_name = "FILE")]
+    pub map: Option<PathBuf>,
+
+    /// TOML or JSON file mapping printer names to MAC addresses, looked up in the ARP cache when discovery finds
+    /// no address. Only finds devices this machine has talked to recently on its own subnets
+    #[arg(long, value_name = "FILE", conflicts_with = "plan_in")]
+    pub mac_map: Option<PathBuf>,
+
+    /// Convert the printers on each print server listed in this file, one \\HOST per line, instead of those on one
+    /// machine. A server that cannot be reached or fails part way through does not stop the others
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["printer", "watch", "plan_in", "plan_out", "emit_powershell", "emit_reg"])]
+    pub servers_file: Option<PathBuf>,
+
+    /// Never convert the printer with this name. * and ? in it match any run of characters and any single one, and
+    /// it can be given more than once
+    #[arg(long, value_name = "NAME")]
+    pub exclude: Vec<String>,
+
+    /// Never convert the printers named in this file, one name or pattern per line as for --exclude
+    #[arg(long, value_name = "FILE")]
+    pub exclude_file: Option<PathBuf>,
+
+    /// Skip printers missing from --map instead of falling back to discovery
+    #[arg(long, requires = "map")]
+    pub strict: bool,
+
+    /// Directory for the backup written before any printer is changed (default: next to the executable)
+    #[arg(long, value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Protocol the new TCP/IP ports use to talk to the printer
+    #[arg(long, value_enum, default_value_t = Protocol::Raw)]
+    pub protocol: Protocol,
+
+    /// TCP port the printer listens on (default: 9100 for Raw, 515 for LPR)
+    #[arg(long = "port", value_name = "NUM")]
+    pub port_number: Option<u16>,
+
+    /// LPR queue name on the device (default: lp)
+    #[arg(long, value_name = "NAME")]
+    pub lpr_queue: Option<String>,
+
+    /// SNMP community the new ports query the printer's status with
+    #[arg(long, value_name = "NAME", default_value = "public", conflicts_with = "no_snmp")]
+    pub snmp_community: String,
+
+    /// Create ports without SNMP status monitoring
+    #[arg(long)]
+    pub no_snmp: bool,
+
+    /// Name new ports from this template instead, with {ip}, {printer} and {model} (asked for over SNMP) filled in,
+    /// such as TCPIP_{ip}. Printers whose name would not make a legal port name are skipped [default: IP_{ip}]
+    #[arg(long, value_name = "TEMPLATE", value_parser = parse_port_name_template, conflicts_with_all = ["name_by_model", "plan_in"])]
+    pub port_name_template: Option<String>,
+
+    /// When WS-Discovery and the registry find no address, ask for the printer by name over mDNS, as AirPrint
+    /// clients do
+    #[arg(long)]
+    pub mdns: bool,
+
+    /// Create ports against the printer's reverse DNS name instead of its IP address when it has one
+    #[arg(long)]
+    pub prefer_hostname: bool,
+
+    /// Ask each printer for its model over SNMP and name its port after it, such as HP_LaserJet_M254_192.168.1.20,
+    /// falling back to IP_<address> when the printer does not answer (pooled printers always use IP_<address>)
+    #[arg(long)]
+    pub name_by_model: bool,
+
+    /// Trust addresses cached from earlier WS-Discovery probes for this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 86400)]
+    pub cache_ttl: u64,
+
+    /// Ignore cached addresses and probe every device again, rewriting the cache
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// How many printers to discover and probe at once. Ports are always changed one printer at a time
+    #[arg(long, value_name = "N", default_value_t = 16)]
+    pub concurrency: usize,
+
+    /// Stop at the first failure and move every printer already converted in this run back to its old port
+    #[arg(long, conflicts_with = "dry_run")]
+    pub atomic: bool,
+
+    /// After converting, delete each old WSD port that no printer uses any more
+    #[arg(long)]
+    pub cleanup: bool,
+
+    /// After converting, restart the Print Spooler so shared printers pick up their new ports (disrupts printing)
+    #[arg(long)]
+    pub restart_spooler: bool,
+
+    /// Write a JSON summary of what happened to each printer to this file
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub report: Option<PathBuf>,
+
+    /// After each verified conversion, print a test page on the printer and report whether the spooler queued it
+    #[arg(long, conflicts_with_all = ["dry_run", "emit_powershell", "emit_reg"])]
+    pub test_page: bool,
+
+    /// Also write an entry to the Windows Application log for every printer converted or failed
+    #[arg(long, conflicts_with = "dry_run")]
+    pub event_log: bool,
+
+    /// Allow convert to change printers and ports. Without it every run is a dry run, whatever else is given, so a
+    /// mistyped scheduled task cannot touch production. Can also be set with allow-modify in the config file
+    #[arg(long)]
+    pub allow_modify: bool,
+
+    /// Print the AddPort and SetPrinterW calls that would be made without making them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// With --dry-run, also write the plan to this JSON file for review
+    #[arg(long, value_name = "FILE", requires = "dry_run")]
+    pub plan_out: Option<PathBuf>,
+
+    /// Write a PowerShell script of Add-PrinterPort and Set-Printer calls making the same changes, instead of
+    /// making them (with --dry-run, as well as printing the plan)
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    pub emit_powershell: Option<PathBuf>,
+
+    /// Write a .reg file defining the new Standard TCP/IP ports, for pre-staging them on imaged or offline machines,
+    /// instead of converting. The ports appear only after a spooler restart, and printers are not moved onto them
+    #[arg(long, value_name = "FILE", conflicts_with = "watch")]
+    pub emit_reg: Option<PathBuf>,
+
+    /// Apply a plan written by --plan-out instead of discovering addresses again
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["ip", "map", "plan_out"])]
+    pub plan_in: Option<PathBuf>,
+
+    /// List the planned conversions and ask which of them to apply before changing anything
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Keep running, converting each WSD printer as it appears, until Ctrl-C
+    #[arg(long, conflicts_with_all = ["dry_run", "plan_in", "interactive", "atomic", "restart_spooler"])]
+    pub watch: bool,
+
+    /// With --watch, how long to wait between polls
+    #[arg(long, value_name = "SECS", default_value_t = 60, requires = "watch", value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+
+    /// Also convert pooled printers, moving each WSD port in the pool to its own TCP/IP port (default: skip them)
+    #[arg(long)]
+    pub convert_pools: bool,
+
+    /// When a port with the planned name already exists but prints elsewhere or with other settings, change it to
+    /// match instead of failing. Every printer already on that port is affected
+    #[arg(long)]
+    pub reconfigure: bool,
+
+    /// Convert printers even if nothing answers on port 9100 at their address
+    #[arg(long)]
+    pub force: bool,
+
+    /// If not already elevated, relaunch through a UAC prompt with the same arguments
+    #[arg(long)]
+    pub elevate: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// Backup file written by convert (wsd_to_ip-backup-*.json)
+    #[arg(value_name = "FILE")]
+    pub backup: PathBuf,
+
+    /// Only restore the printer with this exact name (default: every printer in the backup)
+    #[arg(long, value_name = "NAME")]
+    pub printer: Option<String>,
+
+    /// If not already elevated, relaunch through a UAC prompt with the same arguments
+    #[arg(long)]
+    pub elevate: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct UndoArgs {
+    /// Journal written by convert (default: wsd_to_ip-journal.jsonl next to the executable)
+    #[arg(long, value_name = "FILE")]
+    pub journal: Option<PathBuf>,
+
+    /// Print the changes that would be reversed without reversing them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// If not already elevated, relaunch through a UAC prompt with the same arguments
+    #[arg(long)]
+    pub elevate: bool,
+}
+
+#[derive(Args, Debug, Default)]
+pub struct ListArgs {
+    /// Only list each WSD printer's name and port, from a single quick enumeration that skips status and driver
+    /// details. Cannot be combined with --driver, --driver-details, --device-info, --only-online or --only-offline
+    #[arg(long, visible_alias = "wsd-only")]
+    pub minimal: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SummaryArgs {
+    /// Also split each driver's count by the subnet of the printers' discovered addresses, using this many bits of
+    /// an IPv4 address (default: 24; IPv6 addresses always use 64). Discovery can take a few seconds per printer
+    #[arg(long, value_name = "BITS", num_args = 0..=1, default_missing_value = "24", value_parser = clap::value_parser!(u8).range(0..=32))]
+    pub by_subnet: Option<u8>,
+
+    /// Trust addresses cached from earlier WS-Discovery probes for this many seconds
+    #[arg(long, value_name = "SECS", default_value_t = 86400, requires = "by_subnet")]
+    pub cache_ttl: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Also write every check and what it found to this JSON file, for attaching to a support request
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+}
+
+fn parse_port_name_template(template: &str) -> Result<String, String> {
+    check_port_name_template(template).map(|_| template.to_string())
+}
+
+// Reject --ip values that are not IPv4 or IPv6 literals before they can end up in a port name
+fn parse_ip_address(address: &str) -> Result<String, String> {
+    address.parse::<IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| format!("{:?} is not a valid IPv4 or IPv6 address", address))
+}
+
+// Compile --name-filter during argument parsing so a bad pattern is reported as a usage error
+fn parse_regex(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| e.to_string())
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use clap::parser::ValueSource;
+use serde::Deserialize;
+use simplelog::LevelFilter;
+
+use wsd_to_ip::PrinterError;
+
+use crate::cli::{Cli, Command, Protocol, Scope};
+
+// File name of the config looked for next to the executable when no --config is given
+pub const CONFIG_FILE: &str = "wsd_to_ip.toml";
+
+// Defaults for the options most deployments set the same way every run, such as through Group Policy.
+// Anything given on the command line (or through its environment variable) wins over the file
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub log_level: Option<String>,
+    pub scope: Option<Scope>,
+    pub protocol: Option<Protocol>,
+    pub port: Option<u16>,
+    pub snmp_community: Option<String>,
+    pub concurrency: Option<usize>,
+    pub map: Option<PathBuf>,
+    pub allow_modify: Option<bool>,
+    pub virtual_drivers: Option<Vec<String>>,
+}
+
+pub fn load_config(path: &Path) -> Result<Config, PrinterError> {
+    let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+    let contents = fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+    let config: Config = toml::from_str(&contents).map_err(|e| file_error(e.to_string()))?;
+
+    // Checked here so a bad level is reported against the file rather than silently ignored
+    if let Some(level) = &config.log_level {
+        LevelFilter::from_str(level).map_err(|_| file_error(format!("{:?} is not a log level", level)))?;
+    }
+
+    Ok(config)
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(CONFIG_FILE))
+}
+
+// Whether the user set id themselves, rather than it holding clap's default
+fn given(matches: &ArgMatches, id: &str) -> bool {
+    matches!(matches.value_source(id), Some(ValueSource::CommandLine) | Some(ValueSource::EnvVariable))
+}
+
+// Parse the command line and fill in whatever it left at its default from the config file: the one named by
+// --config, or wsd_to_ip.toml next to the executable if there is one
+pub fn parse_with_config() -> Result<Cli, PrinterError> {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let config = match &cli.config {
+        Some(path) => load_config(path)?,
+        None => match default_config_path().filter(|path| path.is_file()) {
+            Some(path) => load_config(&path)?,
+            None => return Ok(cli),
+        },
+    };
+
+    // Global options can be given before or after the subcommand, and clap records where they came from on
+    // whichever level they were parsed at
+    let subcommand_matches = matches.subcommand().map(|(_, sub)| sub);
+    let global_given = |id: &str| given(&matches, id) || subcommand_matches.is_some_and(|sub| given(sub, id));
+
+    if let Some(level) = &config.log_level {
+        if !global_given("log_level") {
+            // Already validated by load_config
+            cli.log_level = LevelFilter::from_str(level).unwrap_or(cli.log_level);
+        }
+    }
+    if let Some(scope) = config.scope {
+        if !global_given("scope") {
+            cli.scope = scope;
+        }
+    }
+    if config.virtual_drivers.is_some() && !global_given("virtual_drivers") {
+        cli.virtual_drivers = config.virtual_drivers;
+    }
+
+    if let (Some(Command::Convert(args)), Some(sub)) = (&mut cli.command, subcommand_matches) {
+        if let Some(protocol) = config.protocol {
+            if !given(sub, "protocol") {
+                args.protocol = protocol;
+            }
+        }
+        if config.port.is_some() && !given(sub, "port_number") {
+            args.port_number = config.port;
+        }
+        if let Some(community) = config.snmp_community {
+            if !given(sub, "snmp_community") {
+                args.snmp_community = community;
+            }
+        }
+        if let Some(concurrency) = config.concurrency {
+            if !given(sub, "concurrency") {
+                args.concurrency = concurrency;
+            }
+        }
+        if config.allow_modify == Some(true) {
+            args.allow_modify = true;
+        }
+        // --plan-in cannot be combined with a map, so a configured one only applies to runs that could use it
+        if config.map.is_some() && args.map.is_none() && args.plan_in.is_none() {
+            args.map = config.map;
+        }
+    }
+
+    Ok(cli)
+}
@@ -0,0 +1,1104 @@
+use std::ffi::OsStr;
+#[cfg(windows)]
+use std::ffi::OsString;
+use std::net::{IpAddr, Ipv6Addr};
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+#[cfg(windows)]
+use std::ptr::null_mut;
+
+use serde::{Deserialize, Serialize};
+
+use log::{info, error};
+#[cfg(windows)]
+use log::warn;
+#[cfg(windows)]
+use winapi::shared::winerror::{ERROR_UNKNOWN_PORT, ERROR_ALREADY_EXISTS, ERROR_SUCCESS};
+#[cfg(windows)]
+use winapi::um::errhandlingapi::GetLastError;
+#[cfg(windows)]
+use winapi::shared::windef::HDC;
+#[cfg(windows)]
+use winapi::um::wingdi::{DOCINFOW, LOGPIXELSX, LOGPIXELSY, CreateDCW, DeleteDC, EndDoc, EndPage, GetDeviceCaps, StartDocW, StartPage, TextOutW};
+#[cfg(windows)]
+use winapi::um::winnt::HANDLE;
+#[cfg(windows)]
+use winapi::um::winspool::{PRINTER_DEFAULTSW, PRINTER_ALL_ACCESS, OpenPrinterW, GetPrinterW, SetPrinterW, ClosePrinter};
+#[cfg(windows)]
+use winapi::um::winspool::{SERVER_ACCESS_ADMINISTER, XcvDataW, DeletePortW};
+
+#[cfg(windows)]
+use crate::drivers::get_driver_info;
+use crate::error::PrinterError;
+#[cfg(windows)]
+use crate::error::format_error_code;
+#[cfg(any(windows, test))]
+use crate::flags::decode_printer_attributes;
+use crate::printers::MinimalPrinterInfo;
+#[cfg(windows)]
+use crate::printers::{EnumScope, get_printers_with_scope, unc_server_name};
+use crate::sys::DWORD;
+#[cfg(any(windows, test))]
+use crate::sys::{PRINTER_INFO_2W, PRINTER_INFO_5W};
+#[cfg(windows)]
+use crate::wide::{to_wide_null, copy_to_wide_array, string_from_wide_array, wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+// Handle name understood by the spooler as "talk to the Standard TCP/IP Port monitor"
+#[cfg(windows)]
+const TCPIP_XCV_MONITOR: &str = ",XcvMonitor Standard TCP/IP Port";
+
+// Sizes and constants from tcpxcv.h, which winapi does not bind
+#[cfg(windows)]
+const MAX_PORTNAME_LEN: usize = 64;
+const MAX_NETWORKNAME_LEN: usize = 49;
+#[cfg(windows)]
+const MAX_SNMP_COMMUNITY_STR_LEN: usize = 33;
+#[cfg(windows)]
+const MAX_QUEUENAME_LEN: usize = 33;
+#[cfg(windows)]
+const MAX_IPADDR_STR_LEN: usize = 16;
+pub(crate) const PROTOCOL_RAWTCP_TYPE: DWORD = 1;
+pub(crate) const PROTOCOL_LPR_TYPE: DWORD = 2;
+pub(crate) const LPR_DBLSPOOL: DWORD = 0;
+pub const DEFAULT_RAW_PORT_NUMBER: DWORD = 9100;
+pub const DEFAULT_LPR_PORT_NUMBER: DWORD = 515;
+pub const DEFAULT_SNMP_COMMUNITY: &str = "public";
+pub(crate) const DEFAULT_SNMP_DEV_INDEX: DWORD = 1;
+
+// How the Standard TCP/IP port talks to the device
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortProtocol {
+    Raw,
+    Lpr,
+}
+
+// Settings for a new Standard TCP/IP port. The default, Raw on 9100 with SNMP status using the "public"
+// community, matches what the Add Printer wizard creates and suits almost every network printer
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TcpipPortConfig {
+    pub protocol: PortProtocol,
+    pub port_number: DWORD,
+    pub lpr_queue: Option<String>,
+    // None turns SNMP off, leaving the queue's status as unknown
+    pub snmp_community: Option<String>,
+}
+
+impl Default for TcpipPortConfig {
+    fn default() -> Self {
+        TcpipPortConfig {
+            protocol: PortProtocol::Raw,
+            port_number: DEFAULT_RAW_PORT_NUMBER,
+            lpr_queue: None,
+            snmp_community: Some(DEFAULT_SNMP_COMMUNITY.to_string()),
+        }
+    }
+}
+
+impl TcpipPortConfig {
+    // LPR devices want a queue name; "lp" is what most of them accept when none is configured
+    pub fn queue(&self) -> &str {
+        self.lpr_queue.as_deref().unwrap_or("lp")
+    }
+
+    // How a port with these settings printing to address differs from wanted printing to wanted_address, one
+    // description per setting, for telling the operator why an existing port cannot just be reused. Empty when
+    // the port already does what is wanted
+    pub fn differences(&self, address: &str, wanted: &TcpipPortConfig, wanted_address: &str) -> Vec<String> {
+        let bare = |address: &str| address.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+        let protocol = |protocol: PortProtocol| match protocol {
+            PortProtocol::Raw => "Raw",
+            PortProtocol::Lpr => "LPR",
+        };
+        let snmp = |community: &Option<String>| match community {
+            Some(community) => format!("SNMP with community {:?}", community),
+            None => "no SNMP".to_string(),
+        };
+
+        let mut differences = Vec::new();
+        if bare(address) != bare(wanted_address) {
+            differences.push(format!("it prints to {} instead of {}", address, wanted_address));
+        }
+        if self.protocol != wanted.protocol {
+            differences.push(format!("it uses {} instead of {}", protocol(self.protocol), protocol(wanted.protocol)));
+        }
+        if self.port_number != wanted.port_number {
+            differences.push(format!("it uses port {} instead of {}", self.port_number, wanted.port_number));
+        }
+        if self.protocol == PortProtocol::Lpr && wanted.protocol == PortProtocol::Lpr && !self.queue().eq_ignore_ascii_case(wanted.queue()) {
+            differences.push(format!("its LPR queue is {:?} instead of {:?}", self.queue(), wanted.queue()));
+        }
+        if self.snmp_community != wanted.snmp_community {
+            differences.push(format!("it uses {} instead of {}", snmp(&self.snmp_community), snmp(&wanted.snmp_community)));
+        }
+
+        differences
+    }
+}
+
+// Input blob for the Standard TCP/IP Port monitor's AddPort command
+#[cfg(windows)]
+#[repr(C)]
+#[allow(non_snake_case)]
+struct PORT_DATA_1 {
+    sztPortName: [u16; MAX_PORTNAME_LEN],
+    dwVersion: DWORD,
+    dwProtocol: DWORD,
+    cbSize: DWORD,
+    dwReserved: DWORD,
+    sztHostAddress: [u16; MAX_NETWORKNAME_LEN],
+    sztSNMPCommunity: [u16; MAX_SNMP_COMMUNITY_STR_LEN],
+    dwDoubleSpool: DWORD,
+    sztQueue: [u16; MAX_QUEUENAME_LEN],
+    sztIPAddress: [u16; MAX_IPADDR_STR_LEN],
+    Reserved: [u8; 540],
+    dwPortNumber: DWORD,
+    dwSNMPEnabled: DWORD,
+    dwSNMPDevIndex: DWORD,
+}
+
+// Input blob for the monitor's GetConfigInfo command
+#[cfg(windows)]
+#[repr(C)]
+#[allow(non_snake_case)]
+struct CONFIG_INFO_DATA_1 {
+    Reserved: [u8; 128],
+    dwVersion: DWORD,
+}
+
+// The longest host name or address a Standard TCP/IP port can print to, in UTF-16 units, leaving room for the NUL
+pub const MAX_HOST_ADDRESS_LEN: usize = MAX_NETWORKNAME_LEN - 1;
+
+// Whether address fits the monitor's host address field. A longer one would be cut short and the port would print
+// to some other host, so it is refused before the monitor is asked
+pub fn check_host_address(address: &str) -> Result<(), PrinterError> {
+    let bare = address.trim_start_matches('[').trim_end_matches(']');
+    if bare.encode_utf16().count() > MAX_HOST_ADDRESS_LEN {
+        return Err(PrinterError::HostAddressTooLong { address: bare.to_string(), max: MAX_HOST_ADDRESS_LEN });
+    }
+    Ok(())
+}
+
+// Name of the Standard TCP/IP port that a printer at the given address is moved to. IPv6 literals are
+// bracketed, IP_[fe80::1], the way Windows names them, so the colons cannot be mistaken for a separator
+pub fn ip_port_name(ip: &str) -> String {
+    if ip.split('%').next().unwrap_or(ip).parse::<Ipv6Addr>().is_ok() {
+        format!("IP_[{}]", ip)
+    } else {
+        format!("IP_{}", ip)
+    }
+}
+
+// The longest port name the Standard TCP/IP Port monitor accepts
+const MAX_PORT_NAME_LEN: usize = 63;
+
+// Name of the port for a printer at address whose SNMP agent reports model, such as HP_LaserJet_M254_192.168.1.20.
+// Anything in the model other than letters and digits becomes an underscore, and the model is cut short rather
+// than the address when the name would be too long. Only the part before any comma or semicolon is used, since
+// sysDescr often carries firmware details after it
+pub fn model_port_name(model: &str, address: &str) -> String {
+    let suffix = ip_port_name(address).split_off("IP".len());
+    let name = model_for_port_name(model);
+    let name = name.as_str();
+
+    let room = MAX_PORT_NAME_LEN.saturating_sub(suffix.len());
+    let name = name[..name.len().min(room)].trim_end_matches('_');
+    if name.is_empty() {
+        return ip_port_name(address);
+    }
+    format!("{}{}", name, suffix)
+}
+
+// A model as it goes into a port name: letters and digits with single underscores between the words
+fn model_for_port_name(model: &str) -> String {
+    let model = model.split([',', ';']).next().unwrap_or(model);
+
+    let mut name = String::new();
+    for c in model.chars() {
+        if c.is_ascii_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_end_matches('_').to_string()
+}
+
+// Default for --port-name-template, the names the Add Printer wizard gives Standard TCP/IP ports
+pub const DEFAULT_PORT_NAME_TEMPLATE: &str = "IP_{ip}";
+
+const PORT_NAME_PLACEHOLDERS: [&str; 3] = ["ip", "printer", "model"];
+
+// Split template into its literal text and placeholders, rejecting unknown placeholders and stray braces
+fn template_parts(template: &str) -> Result<Vec<(bool, &str)>, String> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+
+    while !rest.is_empty() {
+        match rest.find(['{', '}']) {
+            Some(start) if rest[start..].starts_with('{') => {
+                if start > 0 {
+                    parts.push((false, &rest[..start]));
+                }
+                let end = rest[start..].find('}').ok_or_else(|| format!("{:?} has a {{ without a matching }}", template))?;
+                let placeholder = &rest[start + 1..start + end];
+                if !PORT_NAME_PLACEHOLDERS.contains(&placeholder) {
+                    return Err(format!("{{{}}} is not a placeholder; use {{ip}}, {{printer}} or {{model}}", placeholder));
+                }
+                parts.push((true, placeholder));
+                rest = &rest[start + end + 1..];
+            }
+            Some(_) => return Err(format!("{:?} has a }} without a matching {{", template)),
+            None => {
+                parts.push((false, rest));
+                rest = "";
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+// Check a port name template before anything is planned with it
+pub fn check_port_name_template(template: &str) -> Result<(), String> {
+    let parts = template_parts(template)?;
+    if !parts.iter().any(|(placeholder, _)| *placeholder) {
+        return Err(format!("{:?} has no placeholders, so every printer would get the same port", template));
+    }
+    Ok(())
+}
+
+// Whether template names ports after the printer's model, which has to be asked for over SNMP
+pub fn template_uses_model(template: &str) -> bool {
+    template.contains("{model}")
+}
+
+// Whether the Standard TCP/IP Port monitor would accept name for a new port. Ports are keys in the registry, so
+// no backslashes, and the spooler keeps a printer's ports as a comma separated list, so no commas either
+pub fn validate_port_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("the port name is empty".to_string());
+    }
+    // The monitor counts UTF-16 units, not the bytes of the UTF-8 form
+    if name.encode_utf16().count() > MAX_PORT_NAME_LEN {
+        return Err(format!("{:?} is longer than {} characters", name, MAX_PORT_NAME_LEN));
+    }
+    if let Some(c) = name.chars().find(|c| matches!(c, ',' | '\\') || c.is_control()) {
+        return Err(format!("{:?} contains {:?}, which port names cannot", name, c));
+    }
+    Ok(())
+}
+
+// Fill in a --port-name-template for a printer at address. {ip} is written as in ip_port_name, so the default
+// template gives exactly the names this tool has always used, and {model} as in model_port_name
+pub fn render_port_name(template: &str, address: &str, printer_name: &str, model: Option<&str>) -> Result<String, String> {
+    let mut name = String::new();
+
+    for (placeholder, text) in template_parts(template)? {
+        match (placeholder, text) {
+            (false, text) => name.push_str(text),
+            (true, "ip") => name.push_str(&ip_port_name(address)["IP_".len()..]),
+            (true, "printer") => name.push_str(printer_name),
+            (true, _) => {
+                let model = model.map(model_for_port_name).filter(|model| !model.is_empty())
+                    .ok_or_else(|| "the printer did not report a model".to_string())?;
+                name.push_str(&model);
+            }
+        }
+    }
+
+    validate_port_name(&name)?;
+    Ok(name)
+}
+
+// The address a port created by ip_port_name or model_port_name prints to, without any IPv6 brackets
+pub fn address_from_ip_port_name(port_name: &str) -> Option<&str> {
+    let address = match port_name.strip_prefix("IP_") {
+        Some(address) => address,
+        None => port_name.rsplit_once('_').map(|(_, address)| address)?,
+    };
+    let address = address.strip_prefix('[').and_then(|v6| v6.strip_suffix(']')).unwrap_or(address);
+
+    // A model-named port only counts when what follows the model really is an address
+    if !port_name.starts_with("IP_") && address.split('%').next().unwrap_or(address).parse::<IpAddr>().is_err() {
+        return None;
+    }
+    Some(address)
+}
+
+// Owns a handle returned by OpenPrinterW and closes it when dropped
+#[cfg(windows)]
+pub(crate) struct PrinterHandle(pub(crate) HANDLE);
+
+#[cfg(windows)]
+impl PrinterHandle {
+    pub(crate) fn open(name: &OsStr, desired_access: DWORD) -> Result<PrinterHandle, PrinterError> {
+        let mut wide_name = to_wide_null(name);
+        let mut handle: HANDLE = null_mut();
+        let mut defaults = PRINTER_DEFAULTSW {
+            pDataType: null_mut(),
+            pDevMode: null_mut(),
+            DesiredAccess: desired_access,
+        };
+
+        let open_result = unsafe { OpenPrinterW(wide_name.as_mut_ptr(), &mut handle, &mut defaults) };
+
+        if open_result == 0 {
+            let error_code = unsafe { GetLastError() };
+            error!("[{}] OpenPrinterW failed for {:?}: {}", "PrinterHandle::open", name, format_error_code(error_code).unwrap_or_default());
+            return Err(PrinterError::OpenPrinterFailed { name: name.to_string_lossy().into_owned(), code: error_code });
+        }
+
+        Ok(PrinterHandle(handle))
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PrinterHandle {
+    fn drop(&mut self) {
+        unsafe {
+            ClosePrinter(self.0);
+        }
+    }
+}
+
+// Read a printer's current PRINTER_INFO_2W. The returned buffer starts with the struct and also holds
+// the strings its pointers refer to, so it must stay alive for as long as those pointers are used
+#[cfg(windows)]
+pub(crate) fn get_printer_info_2(handle: &PrinterHandle, printer_name: &str) -> Result<Vec<u8>, PrinterError> {
+    get_printer_info(handle, printer_name, 2)
+}
+
+// Read a printer's current settings at level, in a buffer laid out as get_printer_info_2 describes
+#[cfg(windows)]
+fn get_printer_info(handle: &PrinterHandle, printer_name: &str, level: DWORD) -> Result<Vec<u8>, PrinterError> {
+    // First call to GetPrinterW is to get the number of bytes needed for the PRINTER_INFO_* struct
+    let mut bytes_needed: DWORD = 0;
+    unsafe {
+        GetPrinterW(handle.0, level, null_mut(), 0, &mut bytes_needed);
+    }
+
+    if bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] GetPrinterW failed to set bytes_needed: {}", "get_printer_info", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::GetPrinterFailed { name: printer_name.to_string(), code: error_code });
+    }
+
+    // Second call to GetPrinterW fills the buffer with the current settings
+    let mut buffer = vec![0u8; bytes_needed as usize];
+    let get_printer_result = unsafe {
+        GetPrinterW(handle.0, level, buffer.as_mut_ptr(), bytes_needed, &mut bytes_needed)
+    };
+
+    if get_printer_result == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] GetPrinterW failed to populate buffer: {}", "get_printer_info", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::GetPrinterFailed { name: printer_name.to_string(), code: error_code });
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(windows)]
+fn lossy_wide(ptr: *const u16) -> String {
+    OsString::from_wide(&wide_str_from_raw_ptr(ptr, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned()
+}
+
+// Point an existing printer at the Standard TCP/IP port IP_<ip>, leaving every other level-2 setting untouched
+#[cfg(windows)]
+pub fn convert_printer_to_ip(printer: &MinimalPrinterInfo, ip: &str) -> Result<(), PrinterError> {
+    set_printer_port(&printer.printer_name, &ip_port_name(ip))
+}
+
+// Move a printer onto an existing port by name, leaving every other level-2 setting untouched. Printers with a v4
+// driver are moved through level 5 instead, see set_printer_port_level_5
+#[cfg(windows)]
+pub fn set_printer_port(printer_name: &OsStr, port_name: &str) -> Result<(), PrinterError> {
+    let name = printer_name.to_string_lossy().into_owned();
+
+    info!("[{}] Opening {:?} with PRINTER_ALL_ACCESS", "set_printer_port", printer_name);
+    let handle = PrinterHandle::open(printer_name, PRINTER_ALL_ACCESS)?;
+
+    let mut buffer = get_printer_info_2(&handle, &name)?;
+
+    // A driver that cannot be read is treated as v3, which is what the level-2 path was always used for
+    let v4 = get_driver_info(printer_name).is_ok_and(|driver| driver.is_v4());
+
+    // pDevMode is handed back as read, still pointing into buffer, so the defaults users print with (paper size,
+    // duplex, tray) are written back unchanged. A null one would make the spooler fall back to driver defaults
+    let kept = kept_settings(&buffer);
+    match &kept.devmode {
+        Some(bytes) => info!("[{}] Keeping the {} byte DEVMODE of {:?}", "set_printer_port", bytes.len(), printer_name),
+        None => info!("[{}] {:?} has no DEVMODE to keep", "set_printer_port", printer_name),
+    }
+
+    // Swap in the new port. The wide string must outlive the SetPrinterW call below
+    let mut wide_port_name = to_wide_null(OsStr::new(port_name));
+    point_level_2_at(&mut buffer, &mut wide_port_name);
+
+    if v4 {
+        warn!("[{}] {:?} uses the v4 driver {:?}; moving only its port, check that it still prints afterwards", "set_printer_port", printer_name, kept.driver_name);
+        set_printer_port_level_5(&handle, &name, port_name, kept.attributes)?;
+    } else {
+        info!("[{}] Calling SetPrinterW to move {:?} to {}", "set_printer_port", printer_name, port_name);
+        let set_printer_result = unsafe { SetPrinterW(handle.0, 2, buffer.as_mut_ptr(), 0) };
+
+        if set_printer_result == 0 {
+            return Err(set_printer_error(&name, port_name));
+        }
+    }
+
+    info!("[{}] Successfully moved {:?} to {}", "set_printer_port", printer_name, port_name);
+
+    warn_if_settings_changed(&handle, &name, &kept);
+
+    Ok(())
+}
+
+// v4 drivers are tied to the driver package they were installed from and keep most of their configuration in it
+// and in PrintTicket, and some of them rebuild that when a level-2 SetPrinterW writes the driver and DEVMODE back,
+// even unchanged. PRINTER_INFO_5W holds only the name, port, attributes and timeouts, so nothing else is written
+#[cfg(windows)]
+fn set_printer_port_level_5(handle: &PrinterHandle, printer_name: &str, port_name: &str, attributes: DWORD) -> Result<(), PrinterError> {
+    let mut buffer = get_printer_info(handle, printer_name, 5)?;
+
+    // The wide string must outlive the SetPrinterW call below
+    let mut wide_port_name = to_wide_null(OsStr::new(port_name));
+    point_level_5_at(&mut buffer, &mut wide_port_name, attributes);
+
+    info!("[{}] Calling SetPrinterW at level 5 to move {} to {}", "set_printer_port_level_5", printer_name, port_name);
+    if unsafe { SetPrinterW(handle.0, 5, buffer.as_mut_ptr(), 0) } == 0 {
+        return Err(set_printer_error(printer_name, port_name));
+    }
+
+    Ok(())
+}
+
+// Point the PRINTER_INFO_2W at the start of buffer at port_name, a null-terminated wide string, and leave everything
+// else as GetPrinterW filled it in. Attributes in particular are written back as read. None of them belong to the
+// port: PRINTER_ATTRIBUTE_ENABLE_BIDI is a queue setting the Standard TCP/IP monitor honours through SNMP, and
+// dropping it loses the driver's status and configuration pages. warn_if_settings_changed checks they all stuck
+#[cfg(any(windows, test))]
+fn point_level_2_at(buffer: &mut [u8], port_name: &mut [u16]) {
+    assert!(buffer.len() >= std::mem::size_of::<PRINTER_INFO_2W>());
+    let printer_info = unsafe { &mut *(buffer.as_mut_ptr() as *mut PRINTER_INFO_2W) };
+    printer_info.pPortName = port_name.as_mut_ptr();
+
+    // A null security descriptor tells SetPrinterW to leave the existing ACL alone
+    printer_info.pSecurityDescriptor = std::ptr::null_mut();
+}
+
+// Point the PRINTER_INFO_5W at the start of buffer at port_name, with the attributes read at level 2 so that the
+// level-5 write keeps the same ones the level-2 one would
+#[cfg(any(windows, test))]
+fn point_level_5_at(buffer: &mut [u8], port_name: &mut [u16], attributes: DWORD) {
+    assert!(buffer.len() >= std::mem::size_of::<PRINTER_INFO_5W>());
+    let printer_info = unsafe { &mut *(buffer.as_mut_ptr() as *mut PRINTER_INFO_5W) };
+    printer_info.pPortName = port_name.as_mut_ptr();
+    printer_info.Attributes = attributes;
+}
+
+// The error for a SetPrinterW call that just failed moving printer_name to port_name
+#[cfg(windows)]
+fn set_printer_error(printer_name: &str, port_name: &str) -> PrinterError {
+    let error_code = unsafe { GetLastError() };
+    let win_error = format_error_code(error_code).unwrap_or_default();
+
+    if error_code == ERROR_UNKNOWN_PORT {
+        error!("[{}] Port {} does not exist yet: {}", "set_printer_port", port_name, win_error);
+        return PrinterError::UnknownPort { port: port_name.to_string() };
+    }
+
+    error!("[{}] SetPrinterW failed with error code: {}", "set_printer_port", win_error);
+    PrinterError::SetPrinterFailed { name: printer_name.to_string(), code: error_code }
+}
+
+// Where dmSize and dmDriverExtra sit in a DEVMODEW, after the 32 WCHAR dmDeviceName and the two version WORDs
+#[cfg(any(windows, test))]
+const DEVMODE_SIZE_OFFSET: usize = 68;
+
+// The DEVMODE offset bytes into buffer, including the driver-private bytes that follow the public fields, as long as
+// its own dmSize and dmDriverExtra say. None when that runs past the end of buffer
+#[cfg(any(windows, test))]
+pub(crate) fn devmode_in(buffer: &[u8], offset: usize) -> Option<&[u8]> {
+    let devmode = buffer.get(offset..)?;
+    let sizes = devmode.get(DEVMODE_SIZE_OFFSET..DEVMODE_SIZE_OFFSET + 4)?;
+    let size = u16::from_le_bytes([sizes[0], sizes[1]]) as usize + u16::from_le_bytes([sizes[2], sizes[3]]) as usize;
+    devmode.get(..size)
+}
+
+// What moving a printer to another port must leave as it was
+#[cfg(any(windows, test))]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KeptSettings {
+    pub(crate) attributes: DWORD,
+    pub(crate) devmode: Option<Vec<u8>>,
+    pub(crate) driver_name: String,
+}
+
+// The spooler is free to adjust settings on SetPrinterW, for example clearing bidi support when the new port's
+// monitor lacks it, or a driver rewriting its DEVMODE. That does not undo the move, but is worth telling the
+// operator about: one warning for each way actual differs from expected
+#[cfg(any(windows, test))]
+pub(crate) fn settings_changes(printer_name: &str, expected: &KeptSettings, actual: &KeptSettings) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if actual.attributes != expected.attributes {
+        changes.push(format!("{} attributes changed from {:#x} to {:#x}; lost: [{}], gained: [{}]", printer_name,
+            expected.attributes, actual.attributes, decode_printer_attributes(expected.attributes & !actual.attributes).join(", "),
+            decode_printer_attributes(actual.attributes & !expected.attributes).join(", ")));
+    }
+
+    if actual.devmode != expected.devmode {
+        changes.push(format!("The driver changed the default DEVMODE of {}; check its paper, duplex and tray defaults", printer_name));
+    }
+
+    if !actual.driver_name.eq_ignore_ascii_case(&expected.driver_name) {
+        changes.push(format!("{} is now on the driver {:?} instead of {:?}; reinstall its driver if it no longer prints", printer_name, actual.driver_name, expected.driver_name));
+    }
+
+    changes
+}
+
+// The settings of the PRINTER_INFO_2W at the start of buffer, as get_printer_info_2 filled it. GetPrinterW points
+// pDevMode into buffer, so the DEVMODE is found by its offset there
+#[cfg(windows)]
+fn kept_settings(buffer: &[u8]) -> KeptSettings {
+    let printer_info = unsafe { &*(buffer.as_ptr() as *const PRINTER_INFO_2W) };
+    let devmode = (!printer_info.pDevMode.is_null())
+        .then(|| (printer_info.pDevMode as usize).checked_sub(buffer.as_ptr() as usize))
+        .flatten()
+        .and_then(|offset| devmode_in(buffer, offset))
+        .map(<[u8]>::to_vec);
+
+    KeptSettings { attributes: printer_info.Attributes, devmode, driver_name: lossy_wide(printer_info.pDriverName) }
+}
+
+// Read printer_name back after a move and warn about anything settings_changes finds
+#[cfg(windows)]
+fn warn_if_settings_changed(handle: &PrinterHandle, printer_name: &str, expected: &KeptSettings) {
+    let Ok(buffer) = get_printer_info_2(handle, printer_name) else {
+        return;
+    };
+
+    for change in settings_changes(printer_name, expected, &kept_settings(&buffer)) {
+        warn!("[{}] {}", "set_printer_port", change);
+    }
+}
+
+// SetPrinterW can succeed while the spooler keeps serving the old settings, so read the printer back from a fresh
+// enumeration and make sure it really is on expected_port now
+#[cfg(windows)]
+pub fn verify_printer_port(server: Option<&str>, printer_name: &OsStr, expected_port: &str) -> Result<(), PrinterError> {
+    // Look in every scope so a printer found as a connection is found again
+    let printers = get_printers_with_scope(server, EnumScope::All)?;
+    check_printer_port(&printers, printer_name, expected_port)
+}
+
+// Whether printer_name is on expected_port among printers, as freshly enumerated
+pub(crate) fn check_printer_port(printers: &[MinimalPrinterInfo], printer_name: &OsStr, expected_port: &str) -> Result<(), PrinterError> {
+    let name = printer_name.to_string_lossy().into_owned();
+    let actual = printers.iter()
+        .find(|printer| printer.printer_name == printer_name)
+        .map(|printer| printer.port_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    // Pooled ports come back as a list whose spacing is the spooler's own, so compare them member by member
+    let members = |ports: &str| ports.split(',').map(|port| port.trim().to_ascii_lowercase()).collect::<Vec<_>>();
+    if members(&actual) != members(expected_port) {
+        error!("[{}] {} reports port {:?} after conversion, expected {}", "verify_printer_port", name, actual, expected_port);
+        return Err(PrinterError::PortUnchanged { name, expected: expected_port.to_string(), actual });
+    }
+
+    info!("[{}] Verified {} is on {}", "verify_printer_port", name, expected_port);
+
+    Ok(())
+}
+
+// Owns a printer device context returned by CreateDCW and deletes it when dropped
+#[cfg(windows)]
+struct PrinterDc(HDC);
+
+#[cfg(windows)]
+impl Drop for PrinterDc {
+    fn drop(&mut self) {
+        unsafe {
+            DeleteDC(self.0);
+        }
+    }
+}
+
+// Print a short test page on printer_name through its own driver, as the Print Test Page button does, and return
+// the id of the job the spooler queued for it. Queueing is as far as the spooler can vouch for; whether the page
+// comes out is up to the device
+#[cfg(windows)]
+pub fn print_test_page(printer_name: &OsStr, port_name: &str) -> Result<DWORD, PrinterError> {
+    let name = printer_name.to_string_lossy().into_owned();
+    let failed = |operation: &'static str| {
+        let code = unsafe { GetLastError() };
+        error!("[{}] {} failed for {}: {}", "print_test_page", operation, name, format_error_code(code).unwrap_or_default());
+        PrinterError::TestPageFailed { name: name.clone(), operation, code }
+    };
+
+    let wide_name = to_wide_null(printer_name);
+    let dc = unsafe { CreateDCW(null_mut(), wide_name.as_ptr(), null_mut(), null_mut()) };
+    if dc.is_null() {
+        return Err(failed("CreateDCW"));
+    }
+    let dc = PrinterDc(dc);
+
+    let document_name = to_wide_null(OsStr::new("wsd_to_ip test page"));
+    let document = DOCINFOW {
+        cbSize: std::mem::size_of::<DOCINFOW>() as i32,
+        lpszDocName: document_name.as_ptr(),
+        lpszOutput: null_mut(),
+        lpszDatatype: null_mut(),
+        fwType: 0,
+    };
+    let job_id = unsafe { StartDocW(dc.0, &document) };
+    if job_id <= 0 {
+        return Err(failed("StartDocW"));
+    }
+
+    if unsafe { StartPage(dc.0) } <= 0 {
+        let e = failed("StartPage");
+        unsafe { EndDoc(dc.0) };
+        return Err(e);
+    }
+
+    // An inch in from the top left corner, a line every sixth of an inch, whatever the device resolution
+    let (dpi_x, dpi_y) = unsafe { (GetDeviceCaps(dc.0, LOGPIXELSX), GetDeviceCaps(dc.0, LOGPIXELSY)) };
+    let lines = [
+        "wsd_to_ip test page".to_string(),
+        format!("Printer: {}", name),
+        format!("Port: {}", port_name),
+        "This printer was moved from its WSD port to a Standard TCP/IP port".to_string(),
+    ];
+    for (row, line) in lines.iter().enumerate() {
+        let text: Vec<u16> = line.encode_utf16().collect();
+        unsafe { TextOutW(dc.0, dpi_x, dpi_y + row as i32 * dpi_y / 6, text.as_ptr(), text.len() as i32) };
+    }
+
+    if unsafe { EndPage(dc.0) } <= 0 {
+        let e = failed("EndPage");
+        unsafe { EndDoc(dc.0) };
+        return Err(e);
+    }
+    if unsafe { EndDoc(dc.0) } <= 0 {
+        return Err(failed("EndDoc"));
+    }
+
+    info!("[{}] Test page for {} queued as job {}", "print_test_page", name, job_id);
+
+    Ok(job_id as DWORD)
+}
+
+// Ask the Standard TCP/IP Port monitor to add a Raw port named port_name that prints to ip on 9100
+#[cfg(windows)]
+pub fn create_tcpip_port(ip: &str, port_name: &str) -> Result<(), PrinterError> {
+    create_tcpip_port_on_server(None, ip, port_name)
+}
+
+// Same as create_tcpip_port, but against the port monitor of a print server rather than this machine
+#[cfg(windows)]
+pub fn create_tcpip_port_on_server(server: Option<&str>, ip: &str, port_name: &str) -> Result<(), PrinterError> {
+    create_tcpip_port_with_config(server, ip, port_name, &TcpipPortConfig::default())
+}
+
+// Open an Xcv handle, such as the monitor's or one of its ports', on server or on this machine
+#[cfg(windows)]
+fn open_xcv(server: Option<&str>, object: &str) -> Result<PrinterHandle, PrinterError> {
+    let name = match server {
+        Some(server) => format!("{}\\{}", unc_server_name(server), object),
+        None => object.to_string(),
+    };
+
+    info!("[{}] Opening {} with SERVER_ACCESS_ADMINISTER", "open_xcv", name);
+    PrinterHandle::open(OsStr::new(&name), SERVER_ACCESS_ADMINISTER)
+}
+
+// Handle name for talking to the Standard TCP/IP Port monitor about one of its ports
+#[cfg(windows)]
+fn xcv_port(port_name: &str) -> String {
+    format!(",XcvPort {}", port_name)
+}
+
+// Send command to an Xcv handle, returning the monitor's status. The Err is XcvDataW's own failure to reach
+// the monitor at all
+#[cfg(windows)]
+fn xcv_data<I, O>(handle: &PrinterHandle, command: &str, input: &mut I, output: Option<&mut O>) -> Result<DWORD, DWORD> {
+    let command = to_wide_null(OsStr::new(command));
+    let (output_ptr, output_size) = match output {
+        Some(output) => (output as *mut O as *mut u8, std::mem::size_of::<O>() as DWORD),
+        None => (null_mut(), 0),
+    };
+    let mut output_needed: DWORD = 0;
+    let mut status: DWORD = 0;
+
+    let xcv_result = unsafe {
+        XcvDataW(
+            handle.0,
+            command.as_ptr(),
+            input as *mut I as *mut u8,
+            std::mem::size_of::<I>() as DWORD,
+            output_ptr,
+            output_size,
+            &mut output_needed,
+            &mut status,
+        )
+    };
+
+    if xcv_result == 0 {
+        return Err(unsafe { GetLastError() });
+    }
+    Ok(status)
+}
+
+// The PORT_DATA_1 describing a port named port_name that prints to ip with config
+#[cfg(windows)]
+fn port_data(ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<PORT_DATA_1, PrinterError> {
+    if let Err(e) = check_host_address(ip) {
+        error!("[{}] Not setting up {}: {}", "port_data", port_name, e);
+        return Err(e);
+    }
+
+    let mut port_data: PORT_DATA_1 = unsafe { std::mem::zeroed() };
+    copy_to_wide_array(&mut port_data.sztPortName, port_name);
+    // The monitor wants the bare literal for IPv6, the brackets only belong in URLs and port names
+    copy_to_wide_array(&mut port_data.sztHostAddress, ip.trim_start_matches('[').trim_end_matches(']'));
+    port_data.dwVersion = 1;
+    port_data.cbSize = std::mem::size_of::<PORT_DATA_1>() as DWORD;
+    port_data.dwPortNumber = config.port_number;
+    match config.protocol {
+        PortProtocol::Raw => port_data.dwProtocol = PROTOCOL_RAWTCP_TYPE,
+        PortProtocol::Lpr => {
+            port_data.dwProtocol = PROTOCOL_LPR_TYPE;
+            port_data.dwDoubleSpool = LPR_DBLSPOOL;
+            copy_to_wide_array(&mut port_data.sztQueue, config.queue());
+        }
+    }
+    if let Some(community) = &config.snmp_community {
+        port_data.dwSNMPEnabled = 1;
+        port_data.dwSNMPDevIndex = DEFAULT_SNMP_DEV_INDEX;
+        copy_to_wide_array(&mut port_data.sztSNMPCommunity, community);
+    }
+
+    Ok(port_data)
+}
+
+// Add a Standard TCP/IP port using the protocol and port number in config
+#[cfg(windows)]
+pub fn create_tcpip_port_with_config(server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+    let mut port_data = port_data(ip, port_name, config)?;
+    let handle = open_xcv(server, TCPIP_XCV_MONITOR)?;
+
+    info!("[{}] Calling XcvDataW AddPort for {} -> {}:{} ({:?})", "create_tcpip_port", port_name, ip, config.port_number, config.protocol);
+    let status = xcv_data::<_, ()>(&handle, "AddPort", &mut port_data, None).map_err(|error_code| {
+        error!("[{}] XcvDataW failed with error code: {}", "create_tcpip_port", format_error_code(error_code).unwrap_or_default());
+        PrinterError::PortCreationFailed(error_code)
+    })?;
+
+    // XcvDataW itself succeeding only means the monitor was reached; the monitor's verdict is in status
+    if status == ERROR_ALREADY_EXISTS {
+        info!("[{}] Port {} already exists, nothing to do", "create_tcpip_port", port_name);
+        return Ok(());
+    }
+
+    if status != ERROR_SUCCESS {
+        let win_error = format_error_code(status).unwrap_or_default();
+        error!("[{}] AddPort for {} failed with status: {}", "create_tcpip_port", port_name, win_error);
+        return Err(PrinterError::PortCreationFailed(status));
+    }
+
+    info!("[{}] Successfully created port {}", "create_tcpip_port", port_name);
+
+    Ok(())
+}
+
+// Read back how the existing Standard TCP/IP port port_name is set up: the address it prints to and its settings
+#[cfg(windows)]
+pub fn get_tcpip_port_config(server: Option<&str>, port_name: &str) -> Result<(String, TcpipPortConfig), PrinterError> {
+    let failed = |code| PrinterError::PortConfigFailed { command: "GetConfigInfo", port: port_name.to_string(), code };
+
+    let handle = open_xcv(server, &xcv_port(port_name))?;
+    let mut request = CONFIG_INFO_DATA_1 { Reserved: [0; 128], dwVersion: 1 };
+    let mut port_data: PORT_DATA_1 = unsafe { std::mem::zeroed() };
+
+    // Ports of other monitors, such as WSD ones, do not answer this
+    let status = xcv_data(&handle, "GetConfigInfo", &mut request, Some(&mut port_data)).map_err(failed)?;
+    if status != ERROR_SUCCESS {
+        warn!("[{}] GetConfigInfo for {} failed with status: {}", "get_tcpip_port_config", port_name, format_error_code(status).unwrap_or_default());
+        return Err(failed(status));
+    }
+
+    // The monitor keeps IPv4 literals in their own field and everything else as the host address
+    let host_address = string_from_wide_array(&port_data.sztHostAddress);
+    let address = match host_address.is_empty() {
+        true => string_from_wide_array(&port_data.sztIPAddress),
+        false => host_address,
+    };
+
+    let protocol = match port_data.dwProtocol {
+        PROTOCOL_LPR_TYPE => PortProtocol::Lpr,
+        _ => PortProtocol::Raw,
+    };
+    let config = TcpipPortConfig {
+        protocol,
+        port_number: port_data.dwPortNumber,
+        lpr_queue: (protocol == PortProtocol::Lpr).then(|| string_from_wide_array(&port_data.sztQueue)),
+        snmp_community: (port_data.dwSNMPEnabled != 0).then(|| string_from_wide_array(&port_data.sztSNMPCommunity)),
+    };
+
+    info!("[{}] {} prints to {}:{} ({:?})", "get_tcpip_port_config", port_name, address, config.port_number, config.protocol);
+    Ok((address, config))
+}
+
+// Change the existing Standard TCP/IP port port_name to print to ip with config
+#[cfg(windows)]
+pub fn reconfigure_tcpip_port(server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+    let failed = |code| PrinterError::PortConfigFailed { command: "ConfigPort", port: port_name.to_string(), code };
+
+    let mut port_data = port_data(ip, port_name, config)?;
+    let handle = open_xcv(server, &xcv_port(port_name))?;
+
+    info!("[{}] Calling XcvDataW ConfigPort for {} -> {}:{} ({:?})", "reconfigure_tcpip_port", port_name, ip, config.port_number, config.protocol);
+    let status = xcv_data::<_, ()>(&handle, "ConfigPort", &mut port_data, None).map_err(failed)?;
+    if status != ERROR_SUCCESS {
+        error!("[{}] ConfigPort for {} failed with status: {}", "reconfigure_tcpip_port", port_name, format_error_code(status).unwrap_or_default());
+        return Err(failed(status));
+    }
+
+    info!("[{}] Reconfigured port {}", "reconfigure_tcpip_port", port_name);
+    Ok(())
+}
+
+// Remove a port from the spooler on a print server, or on this machine when server is None.
+// The caller is responsible for making sure no printer still uses it
+#[cfg(windows)]
+pub fn delete_port(server: Option<&str>, port_name: &str) -> Result<(), PrinterError> {
+    let mut wide_server = server.map(|server| to_wide_null(OsStr::new(&unc_server_name(server))));
+    let server_ptr = wide_server.as_mut().map_or(null_mut(), |name| name.as_mut_ptr());
+    let mut wide_port_name = to_wide_null(OsStr::new(port_name));
+
+    info!("[{}] Calling DeletePortW for {}", "delete_port", port_name);
+    let delete_result = unsafe { DeletePortW(server_ptr, null_mut(), wide_port_name.as_mut_ptr()) };
+
+    if delete_result == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] DeletePortW failed with error code: {}", "delete_port", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::PortDeletionFailed { port: port_name.to_string(), code: error_code });
+    }
+
+    info!("[{}] Successfully deleted port {}", "delete_port", port_name);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::{PRINTER_ATTRIBUTE_DO_COMPLETE_FIRST, PRINTER_ATTRIBUTE_ENABLE_BIDI, PRINTER_ATTRIBUTE_KEEPPRINTEDJOBS};
+    use crate::sys::{PRINTER_ATTRIBUTE_LOCAL, PRINTER_ATTRIBUTE_PUBLISHED, PRINTER_ATTRIBUTE_QUEUED, PRINTER_ATTRIBUTE_SHARED};
+
+    const ATTRIBUTES: DWORD = PRINTER_ATTRIBUTE_ENABLE_BIDI | PRINTER_ATTRIBUTE_SHARED | PRINTER_ATTRIBUTE_QUEUED | PRINTER_ATTRIBUTE_LOCAL
+        | PRINTER_ATTRIBUTE_KEEPPRINTEDJOBS | PRINTER_ATTRIBUTE_DO_COMPLETE_FIRST | PRINTER_ATTRIBUTE_PUBLISHED;
+
+    // The bytes of value, the way GetPrinterW hands a struct back at the start of its buffer
+    fn bytes_of<T>(value: &mut T) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(value as *mut T as *mut u8, std::mem::size_of::<T>()) }
+    }
+
+    #[test]
+    fn moves_a_level_2_printer_and_keeps_everything_else() {
+        let mut old_port: Vec<u16> = "WSD-0a1b2c3d\0".encode_utf16().collect();
+        let mut new_port: Vec<u16> = "IP_10.0.0.5\0".encode_utf16().collect();
+        let mut info: PRINTER_INFO_2W = unsafe { std::mem::zeroed() };
+        info.pPortName = old_port.as_mut_ptr();
+        info.pDevMode = 0x1000 as *mut _;
+        info.pSecurityDescriptor = 0x2000 as *mut _;
+        info.Attributes = ATTRIBUTES;
+        info.Priority = 42;
+        info.Status = 0x80;
+        info.AveragePPM = 38;
+
+        point_level_2_at(bytes_of(&mut info), &mut new_port);
+
+        assert_eq!(info.pPortName, new_port.as_mut_ptr());
+        assert!(info.pSecurityDescriptor.is_null());
+        assert_eq!(info.pDevMode as usize, 0x1000);
+        assert_eq!(info.Attributes, ATTRIBUTES);
+        assert_eq!((info.Priority, info.Status, info.AveragePPM), (42, 0x80, 38));
+    }
+
+    #[test]
+    fn moves_a_level_5_printer_with_the_attributes_read_at_level_2() {
+        let mut new_port: Vec<u16> = "IP_10.0.0.5\0".encode_utf16().collect();
+        let mut info: PRINTER_INFO_5W = unsafe { std::mem::zeroed() };
+        info.Attributes = PRINTER_ATTRIBUTE_QUEUED;
+        info.DeviceNotSelectedTimeout = 15000;
+        info.TransmissionRetryTimeout = 45000;
+
+        point_level_5_at(bytes_of(&mut info), &mut new_port, ATTRIBUTES);
+
+        assert_eq!(info.pPortName, new_port.as_mut_ptr());
+        assert_eq!(info.Attributes, ATTRIBUTES);
+        assert!(decode_printer_attributes(info.Attributes).contains(&"Enable BiDi"));
+        assert_eq!((info.DeviceNotSelectedTimeout, info.TransmissionRetryTimeout), (15000, 45000));
+    }
+
+    // A DEVMODE for device with driver_extra private bytes after its dmSize byte public part
+    fn devmode(device: &str, size: u16, driver_extra: u16) -> Vec<u8> {
+        let mut devmode: Vec<u8> = device.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        devmode.resize(DEVMODE_SIZE_OFFSET, 0);
+        devmode.extend(size.to_le_bytes());
+        devmode.extend(driver_extra.to_le_bytes());
+        devmode.resize(size as usize, 0x11);
+        devmode.extend((0..driver_extra).map(|byte| byte as u8));
+        devmode
+    }
+
+    #[test]
+    fn finds_the_devmode_and_its_private_bytes_in_a_buffer() {
+        let front_desk = devmode("HP LaserJet Pro M404", 220, 12);
+
+        // As GetPrinterW lays it out: the struct and strings first, the DEVMODE after them, then more strings
+        let mut buffer = vec![0xaa; 40];
+        buffer.extend(&front_desk);
+        buffer.extend([0xbb; 16]);
+
+        assert_eq!(devmode_in(&buffer, 40), Some(&front_desk[..]));
+        assert_eq!(devmode_in(&front_desk, 0).map(<[u8]>::len), Some(232));
+    }
+
+    #[test]
+    fn ignores_a_devmode_that_runs_past_its_buffer() {
+        let front_desk = devmode("HP LaserJet Pro M404", 220, 12);
+        assert_eq!(devmode_in(&front_desk[..231], 0), None);
+        assert_eq!(devmode_in(&front_desk[..70], 0), None);
+        assert_eq!(devmode_in(&front_desk, 300), None);
+    }
+
+    #[test]
+    fn reports_every_setting_a_move_changed() {
+        let before = KeptSettings {
+            attributes: PRINTER_ATTRIBUTE_QUEUED | PRINTER_ATTRIBUTE_ENABLE_BIDI,
+            devmode: Some(devmode("HP LaserJet Pro M404", 220, 12)),
+            driver_name: "HP LaserJet Pro M404".to_string(),
+        };
+        assert!(settings_changes("Front desk", &before, &before.clone()).is_empty());
+
+        let same_driver = KeptSettings { driver_name: "hp laserjet pro m404".to_string(), ..before.clone() };
+        assert!(settings_changes("Front desk", &before, &same_driver).is_empty());
+
+        // One bit of the driver-private part is enough to count as a different DEVMODE
+        let mut rewritten = before.devmode.clone().unwrap();
+        *rewritten.last_mut().unwrap() ^= 1;
+        let after = KeptSettings { attributes: PRINTER_ATTRIBUTE_QUEUED, devmode: Some(rewritten), driver_name: "Microsoft IPP Class Driver".to_string() };
+
+        let changes = settings_changes("Front desk", &before, &after);
+        assert_eq!(changes.len(), 3, "{:?}", changes);
+        assert!(changes[0].contains("lost: [Enable BiDi], gained: []"), "{}", changes[0]);
+        assert!(changes[1].contains("DEVMODE"), "{}", changes[1]);
+        assert!(changes[2].contains("Microsoft IPP Class Driver"), "{}", changes[2]);
+
+        let dropped = KeptSettings { devmode: None, ..before.clone() };
+        assert_eq!(settings_changes("Front desk", &before, &dropped).len(), 1);
+    }
+
+    #[test]
+    fn names_ports_after_the_address() {
+        assert_eq!(ip_port_name("192.168.1.20"), "IP_192.168.1.20");
+        assert_eq!(ip_port_name("frontdesk.corp.example"), "IP_frontdesk.corp.example");
+        assert_eq!(ip_port_name("2001:db8::20"), "IP_[2001:db8::20]");
+        assert_eq!(ip_port_name("fe80::1%4"), "IP_[fe80::1%4]");
+    }
+
+    #[test]
+    fn reads_the_address_back_out_of_port_names() {
+        assert_eq!(address_from_ip_port_name("IP_192.168.1.20"), Some("192.168.1.20"));
+        assert_eq!(address_from_ip_port_name("IP_frontdesk.corp.example"), Some("frontdesk.corp.example"));
+        assert_eq!(address_from_ip_port_name("IP_[2001:db8::20]"), Some("2001:db8::20"));
+        assert_eq!(address_from_ip_port_name("HP_LaserJet_M254_192.168.1.20"), Some("192.168.1.20"));
+        assert_eq!(address_from_ip_port_name("HP_LaserJet_[fe80::1%4]"), Some("fe80::1%4"));
+
+        assert_eq!(address_from_ip_port_name("HP_LaserJet_M254"), None);
+        assert_eq!(address_from_ip_port_name("WSD-0a1b2c3d"), None);
+        assert_eq!(address_from_ip_port_name("USB001"), None);
+    }
+
+    #[test]
+    fn names_ports_after_the_model() {
+        assert_eq!(model_port_name("HP LaserJet M254dw", "192.168.1.20"), "HP_LaserJet_M254dw_192.168.1.20");
+        assert_eq!(model_port_name("Brother HL-L2350DW series; FW 1.21", "10.0.0.7"), "Brother_HL_L2350DW_series_10.0.0.7");
+        assert_eq!(model_port_name("Canon {iR-ADV} C5535", "10.0.0.8"), "Canon_iR_ADV_C5535_10.0.0.8");
+    }
+
+    #[test]
+    fn falls_back_to_the_ip_name_without_a_usable_model() {
+        assert_eq!(model_port_name("", "10.0.0.9"), "IP_10.0.0.9");
+        assert_eq!(model_port_name("{}, ;", "10.0.0.9"), "IP_10.0.0.9");
+    }
+
+    #[test]
+    fn cuts_long_models_to_fit_the_port_name() {
+        let name = model_port_name(&"A".repeat(100), "192.168.100.200");
+        assert_eq!(name.len(), MAX_PORT_NAME_LEN);
+        assert!(name.ends_with("_192.168.100.200"));
+    }
+
+    #[test]
+    fn accepts_usual_port_names() {
+        for name in ["IP_192.168.1.20", "TCPIP_10.0.0.5", "HP_LaserJet_M254_192.168.1.20", "WSD-{6B3C9D4E-1F2A-4B5C-8D7E-0A1B2C3D4E5F}", "IP_[fe80::1]"] {
+            assert_eq!(validate_port_name(name), Ok(()), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_illegal_port_names() {
+        for name in ["", "   ", "IP_10.0.0.5,IP_10.0.0.6", "Printers\\IP_10.0.0.5", "IP_10.0.0.5\n", &"x".repeat(MAX_PORT_NAME_LEN + 1)] {
+            assert!(validate_port_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn measures_port_names_in_utf16_units() {
+        // 63 characters, each two bytes in UTF-8 but one UTF-16 unit
+        let name = "\u{00e9}".repeat(MAX_PORT_NAME_LEN);
+        assert!(name.len() > MAX_PORT_NAME_LEN);
+        assert_eq!(validate_port_name(&name), Ok(()));
+        assert!(validate_port_name(&format!("{}x", name)).is_err());
+
+        // Outside the BMP a character takes two units
+        assert!(validate_port_name(&"\u{1f5a8}".repeat(MAX_PORT_NAME_LEN / 2)).is_ok());
+        assert!(validate_port_name(&"\u{1f5a8}".repeat(MAX_PORT_NAME_LEN / 2 + 1)).is_err());
+    }
+
+    #[test]
+    fn checks_braces_in_port_name_templates() {
+        assert_eq!(check_port_name_template("TCPIP_{ip}"), Ok(()));
+        assert!(check_port_name_template("TCPIP_{ip").is_err());
+        assert!(check_port_name_template("TCPIP_ip}").is_err());
+        assert!(check_port_name_template("{address}").is_err());
+        assert!(check_port_name_template("TCPIP").is_err());
+    }
+
+    #[test]
+    fn refuses_host_names_the_monitor_would_cut_short() {
+        assert!(check_host_address("192.168.1.20").is_ok());
+        assert!(check_host_address("[fe80::1234:5678:9abc:def0%12]").is_ok());
+        assert!(check_host_address(&"p".repeat(MAX_HOST_ADDRESS_LEN)).is_ok());
+
+        let fqdn = "printer-3rd-floor-east-wing.print.example-corp.internal";
+        assert!(fqdn.len() > MAX_HOST_ADDRESS_LEN);
+        assert!(matches!(check_host_address(fqdn), Err(PrinterError::HostAddressTooLong { max: MAX_HOST_ADDRESS_LEN, .. })));
+    }
+
+    #[test]
+    fn renders_port_name_templates() {
+        assert_eq!(render_port_name(DEFAULT_PORT_NAME_TEMPLATE, "10.0.0.5", "Office", None).as_deref(), Ok("IP_10.0.0.5"));
+        assert_eq!(render_port_name("{printer}_{ip}", "10.0.0.5", "Office", None).as_deref(), Ok("Office_10.0.0.5"));
+        assert_eq!(render_port_name("{model}-{ip}", "10.0.0.5", "Office", Some("HP LaserJet")).as_deref(), Ok("HP_LaserJet-10.0.0.5"));
+        assert!(render_port_name("{model}-{ip}", "10.0.0.5", "Office", None).is_err());
+        assert!(render_port_name("{printer}_{ip}", "10.0.0.5", "Front, Desk", None).is_err());
+    }
+}
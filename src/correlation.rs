@@ -0,0 +1,68 @@
+// Short ids for following one printer through a log where discovery threads interleave their lines. Each printer
+// gets its id the first time it is asked for, and keeps it for the rest of the process so that the planning,
+// converting and reporting of one printer all carry the same id
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use rand::Rng;
+
+fn ids() -> &'static Mutex<HashMap<String, String>> {
+    static IDS: OnceLock<Mutex<HashMap<String, String>>> = OnceLock::new();
+    IDS.get_or_init(Default::default)
+}
+
+thread_local! {
+    // Id of the printer this thread is working on, put in front of each log line it writes
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// The id of printer_name, assigning a new one if it has none yet
+pub fn correlation_id(printer_name: &str) -> String {
+    let mut ids = ids().lock().unwrap();
+    if let Some(id) = ids.get(printer_name) {
+        return id.clone();
+    }
+
+    // Six hex digits are short enough to read and ample for the printers in a single run
+    let id = loop {
+        let candidate = format!("{:06x}", rand::thread_rng().gen_range(0..0x100_0000));
+        if !ids.values().any(|id| *id == candidate) {
+            break candidate;
+        }
+    };
+    ids.insert(printer_name.to_string(), id.clone());
+
+    id
+}
+
+// The id of printer_name if it was given one, without assigning one
+pub fn existing_correlation_id(printer_name: &str) -> Option<String> {
+    ids().lock().unwrap().get(printer_name).cloned()
+}
+
+// The id of the printer the calling thread is working on, if any
+pub fn current_correlation_id() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+// Marks the calling thread as working on a printer until dropped, when the previous printer, if any, is current again
+pub struct PrinterScope {
+    previous: Option<String>,
+}
+
+impl Drop for PrinterScope {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+// Log everything the calling thread writes from now until the returned scope is dropped under printer_name's id
+pub fn printer_scope(printer_name: &str) -> PrinterScope {
+    let id = correlation_id(printer_name);
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(id));
+
+    PrinterScope { previous }
+}
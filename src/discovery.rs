@@ -0,0 +1,490 @@
+use std::io;
+use std::net::{Ipv4Addr, UdpSocket};
+#[cfg(windows)]
+use std::os::windows::io::AsRawSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use log::{info, warn, error};
+use rand::Rng;
+#[cfg(windows)]
+use winapi::shared::ws2def::IPPROTO_IP;
+#[cfg(windows)]
+use winapi::shared::ws2ipdef::IP_MULTICAST_IF;
+#[cfg(windows)]
+use winapi::um::winsock2::{setsockopt, SOCKET, SOCKET_ERROR};
+
+use crate::printers::MinimalPrinterInfo;
+
+// WS-Discovery multicast group and port
+const WS_DISCOVERY_ADDR: &str = "239.255.255.250:3702";
+
+// How long to wait for a ResolveMatches before giving up
+pub const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often a wait for ResolveMatches wakes up to check whether discovery was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Once set, every Resolve in flight gives up at its next wake-up and new ones are not sent
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+// Stop all WS-Discovery waits, in every thread, for the rest of the process
+pub fn cancel_discovery() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub(crate) fn discovery_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+// Local address multicast discovery goes out from, when the routing table's choice is the wrong network
+static INTERFACE: OnceLock<Ipv4Addr> = OnceLock::new();
+
+// Send every WS-Discovery and mDNS multicast from the interface with this address, for the rest of the process.
+// Only the first call has any effect
+pub fn set_discovery_interface(address: Ipv4Addr) {
+    if INTERFACE.set(address).is_ok() {
+        info!("[{}] Multicasting discovery from {}", "set_discovery_interface", address);
+    }
+}
+
+// Pick the interface multicasts on socket leave from. Binding to its address is not always enough for Windows
+// to route the multicast out of it, so it is named as the multicast interface as well
+#[cfg(windows)]
+fn set_multicast_interface(socket: &UdpSocket, address: Ipv4Addr) -> io::Result<()> {
+    let in_addr = u32::from(address).to_be();
+    let result = unsafe {
+        setsockopt(
+            socket.as_raw_socket() as SOCKET,
+            IPPROTO_IP,
+            IP_MULTICAST_IF,
+            &in_addr as *const u32 as *const i8,
+            std::mem::size_of::<u32>() as i32,
+        )
+    };
+
+    if result == SOCKET_ERROR {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// Elsewhere the bound address has to do
+#[cfg(not(windows))]
+fn set_multicast_interface(_socket: &UdpSocket, _address: Ipv4Addr) -> io::Result<()> {
+    Ok(())
+}
+
+// A UDP socket to multicast a query from and hear the answers on: bound to the --discovery-interface address
+// when one was given, otherwise to every interface with the routing table choosing where multicasts go
+pub(crate) fn multicast_socket() -> io::Result<UdpSocket> {
+    let Some(address) = INTERFACE.get() else {
+        return UdpSocket::bind("0.0.0.0:0");
+    };
+
+    let socket = UdpSocket::bind((*address, 0))?;
+    set_multicast_interface(&socket, *address)?;
+    Ok(socket)
+}
+
+// Build a random (version 4) UUID for the MessageID header
+pub(crate) fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// How the WSD port monitor has named its ports across Windows versions: WSD-<uuid> with or without a suffix,
+// WSD_ and brace-wrapped variants, and the WSDPORT_<n> form, whose <n> is only an index. Longer prefixes come
+// first so WSDPORT is not taken for WSD, and a bare WSD only counts when a braced uuid follows it
+const WSD_PORT_PREFIXES: [&str; 5] = ["WSDPORT_", "WSDPORT", "WSD-", "WSD_", "WSD"];
+
+// The parts of a WSD port name: one of the prefixes, then a uuid that may be wrapped in braces and an optional .<suffix>
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WsdPortInfo {
+    // Device endpoint reference, lowercased and without braces. None for a WSDPORT_<n> port, which names no device
+    pub uuid: Option<String>,
+    // Whatever followed the first '.', typically a function index such as 0036
+    pub suffix: Option<String>,
+}
+
+// Split a WSD port name into its device UUID and suffix. Anything that is not a WSD port gives None. Spooler
+// APIs have been seen to hand port names back in lower case, so the prefix is matched ignoring case
+pub fn parse_wsd_port(port: &str) -> Option<WsdPortInfo> {
+    let port = port.trim_start();
+    let (prefix, rest) = WSD_PORT_PREFIXES.iter().find_map(|prefix| {
+        port.get(..prefix.len())
+            .filter(|start| start.eq_ignore_ascii_case(prefix))
+            .map(|_| (*prefix, &port[prefix.len()..]))
+            .filter(|(prefix, rest)| *prefix != "WSD" || rest.starts_with('{'))
+    })?;
+
+    let (uuid, suffix) = match rest.split_once('.') {
+        Some((uuid, suffix)) => (uuid, Some(suffix)),
+        None => (rest, None),
+    };
+    let uuid = uuid.trim().trim_start_matches('{').trim_end_matches('}');
+
+    if uuid.is_empty() || !uuid.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+        return None;
+    }
+
+    // The number after WSDPORT_ only tells the monitor's ports apart; resolving it as an endpoint would ask the
+    // network for a device that does not exist
+    let index_only = prefix.starts_with("WSDPORT") && uuid.chars().all(|c| c.is_ascii_digit());
+
+    Some(WsdPortInfo {
+        uuid: (!index_only).then(|| uuid.to_lowercase()),
+        suffix: suffix.filter(|suffix| !suffix.is_empty()).map(str::to_string),
+    })
+}
+
+// The device UUID a WSD port refers to, which is also its WS-Discovery endpoint reference
+pub(crate) fn endpoint_uuid_from_port(port_name: &str) -> Option<String> {
+    parse_wsd_port(port_name).and_then(|info| info.uuid)
+}
+
+// SOAP envelope for a WS-Discovery Resolve of a single endpoint reference
+fn build_resolve_message(message_id: &str, endpoint_address: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\" ",
+            "xmlns:wsa=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" ",
+            "xmlns:wsd=\"http://schemas.xmlsoap.org/ws/2005/04/discovery\">",
+            "<soap:Header>",
+            "<wsa:To>urn:schemas-xmlsoap-org:ws:2005:04:discovery</wsa:To>",
+            "<wsa:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/Resolve</wsa:Action>",
+            "<wsa:MessageID>urn:uuid:{}</wsa:MessageID>",
+            "</soap:Header>",
+            "<soap:Body>",
+            "<wsd:Resolve><wsa:EndpointReference><wsa:Address>{}</wsa:Address></wsa:EndpointReference></wsd:Resolve>",
+            "</soap:Body>",
+            "</soap:Envelope>"
+        ),
+        message_id, endpoint_address
+    )
+}
+
+// SOAP envelope for a WS-Discovery Probe with no types, which every device on the link answers
+fn build_probe_message(message_id: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\" ",
+            "xmlns:wsa=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\" ",
+            "xmlns:wsd=\"http://schemas.xmlsoap.org/ws/2005/04/discovery\">",
+            "<soap:Header>",
+            "<wsa:To>urn:schemas-xmlsoap-org:ws:2005:04:discovery</wsa:To>",
+            "<wsa:Action>http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</wsa:Action>",
+            "<wsa:MessageID>urn:uuid:{}</wsa:MessageID>",
+            "</soap:Header>",
+            "<soap:Body><wsd:Probe/></soap:Body>",
+            "</soap:Envelope>"
+        ),
+        message_id
+    )
+}
+
+// Multicast a WS-Discovery Probe and count the devices that answer within timeout. None answering on a network
+// with WSD printers on it usually means multicast is filtered between here and them
+pub fn count_probe_responders(timeout: Duration) -> std::io::Result<usize> {
+    let socket = multicast_socket()?;
+    let message_id = random_uuid();
+
+    info!("[{}] Sending Probe to {}", "count_probe_responders", WS_DISCOVERY_ADDR);
+    socket.send_to(build_probe_message(&message_id).as_bytes(), WS_DISCOVERY_ADDR)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0u8; 65536];
+    let mut responders = Vec::new();
+
+    while !discovery_cancelled() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(remaining.min(CANCEL_POLL_INTERVAL)))?;
+
+        match socket.recv_from(&mut buffer) {
+            Ok((len, from)) => {
+                // Only answers to this probe count, not stray traffic aimed at the same port
+                let response = String::from_utf8_lossy(&buffer[..len]);
+                if response.contains("ProbeMatches") && response.contains(&message_id) && !responders.contains(&from.ip()) {
+                    responders.push(from.ip());
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    info!("[{}] {} devices answered", "count_probe_responders", responders.len());
+
+    Ok(responders.len())
+}
+
+// Return the text content of the first element with the given local name, whatever its namespace prefix
+fn element_text<'a>(xml: &'a str, local_name: &str) -> Option<&'a str> {
+    let open_tag_end = format!("{}>", local_name);
+    let mut search_from = 0;
+
+    while let Some(offset) = xml[search_from..].find(&open_tag_end) {
+        let tag_end = search_from + offset;
+        let tag_start = xml[..tag_end].rfind('<')?;
+        let tag_name = &xml[tag_start + 1..tag_end];
+
+        // Skip closing tags and elements that merely end with local_name (e.g. Foo<local_name>)
+        if !tag_name.starts_with('/') && (tag_name.is_empty() || tag_name.ends_with(':')) {
+            let content_start = tag_end + open_tag_end.len();
+            let content_end = content_start + xml[content_start..].find("</")?;
+            return Some(xml[content_start..content_end].trim());
+        }
+
+        search_from = tag_end + open_tag_end.len();
+    }
+
+    None
+}
+
+// Pull the host out of a transport address such as http://192.168.1.20:5357/uuid or http://[fe80::1]:5357/
+pub(crate) fn host_from_url(url: &str) -> Option<String> {
+    let after_scheme = &url[url.find("://")? + 3..];
+    let authority = after_scheme.split('/').next()?;
+
+    let host = if let Some(bracketed) = authority.strip_prefix('[') {
+        // IPv6 literal; a link-local zone index arrives URL-encoded as %25<zone>
+        bracketed.split(']').next()?.replace("%25", "%")
+    } else {
+        authority.split(':').next()?.to_string()
+    };
+
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+// Ask the network where the WSD device behind printer lives, waiting up to DEFAULT_RESOLVE_TIMEOUT
+pub fn resolve_wsd_ip(printer: &MinimalPrinterInfo) -> Option<String> {
+    resolve_wsd_ip_with_timeout(printer, DEFAULT_RESOLVE_TIMEOUT)
+}
+
+// Multicast a WS-Discovery Resolve for the printer's endpoint and return the host from the first matching XAddrs
+pub fn resolve_wsd_ip_with_timeout(printer: &MinimalPrinterInfo, timeout: Duration) -> Option<String> {
+    let xaddrs = resolve_wsd_xaddrs(printer, timeout)?;
+    let host = xaddrs.iter().find_map(|url| host_from_url(url))?;
+    info!("[{}] Resolved {} to {}", "resolve_wsd_ip", printer.port_name.to_string_lossy(), host);
+
+    Some(host)
+}
+
+// Multicast a WS-Discovery Resolve for the printer's endpoint and return every transport address in the first
+// matching XAddrs that has a host in it
+pub fn resolve_wsd_xaddrs(printer: &MinimalPrinterInfo, timeout: Duration) -> Option<Vec<String>> {
+    let port_name = printer.port_name.to_string_lossy();
+
+    let uuid = match endpoint_uuid_from_port(&port_name) {
+        Some(uuid) => uuid,
+        None => {
+            warn!("[{}] Port {} does not carry a WSD endpoint reference", "resolve_wsd_ip", port_name);
+            return None;
+        }
+    };
+
+    if discovery_cancelled() {
+        info!("[{}] Discovery was cancelled, not resolving {}", "resolve_wsd_ip", uuid);
+        return None;
+    }
+
+    let endpoint_address = format!("urn:uuid:{}", uuid);
+    let message_id = random_uuid();
+    let message = build_resolve_message(&message_id, &endpoint_address);
+
+    let socket = match multicast_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!("[{}] Failed to bind UDP socket: {}", "resolve_wsd_ip", e);
+            return None;
+        }
+    };
+
+    info!("[{}] Sending Resolve for {} to {}", "resolve_wsd_ip", endpoint_address, WS_DISCOVERY_ADDR);
+    if let Err(e) = socket.send_to(message.as_bytes(), WS_DISCOVERY_ADDR) {
+        error!("[{}] Failed to send Resolve: {}", "resolve_wsd_ip", e);
+        return None;
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0u8; 65536];
+
+    loop {
+        if discovery_cancelled() {
+            warn!("[{}] Discovery cancelled while waiting for ResolveMatches for {}", "resolve_wsd_ip", endpoint_address);
+            return None;
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            warn!("[{}] Timed out after {:?} waiting for ResolveMatches for {}", "resolve_wsd_ip", timeout, endpoint_address);
+            return None;
+        }
+
+        // Wait in short slices so a cancel is noticed without waiting out the full timeout
+        if let Err(e) = socket.set_read_timeout(Some(remaining.min(CANCEL_POLL_INTERVAL))) {
+            error!("[{}] Failed to set socket timeout: {}", "resolve_wsd_ip", e);
+            return None;
+        }
+
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => {
+                error!("[{}] Failed to receive from socket: {}", "resolve_wsd_ip", e);
+                return None;
+            }
+        };
+
+        let response = String::from_utf8_lossy(&buffer[..len]);
+
+        // Other devices may answer unrelated probes on the same socket; only accept a match for our endpoint
+        if !response.contains("ResolveMatches") || !response.to_lowercase().contains(&endpoint_address) {
+            info!("[{}] Ignoring unrelated response from {}", "resolve_wsd_ip", from);
+            continue;
+        }
+
+        let xaddrs = match element_text(&response, "XAddrs") {
+            Some(xaddrs) => xaddrs,
+            None => {
+                warn!("[{}] ResolveMatches from {} carried no XAddrs", "resolve_wsd_ip", from);
+                continue;
+            }
+        };
+
+        info!("[{}] Received XAddrs {} from {}", "resolve_wsd_ip", xaddrs, from);
+        let urls: Vec<String> = xaddrs.split_whitespace()
+            .filter(|url| host_from_url(url).is_some())
+            .map(str::to_string)
+            .collect();
+        if !urls.is_empty() {
+            return Some(urls);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{address_from_ip_port_name, ip_port_name};
+    use crate::printers::is_wsd_port;
+
+    fn parsed(uuid: &str, suffix: Option<&str>) -> Option<WsdPortInfo> {
+        Some(WsdPortInfo { uuid: Some(uuid.to_string()), suffix: suffix.map(str::to_string) })
+    }
+
+    #[test]
+    fn parses_plain_wsd_ports() {
+        assert_eq!(parse_wsd_port("WSD-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f"), parsed("6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f", None));
+        assert_eq!(
+            parse_wsd_port("WSD-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f.0036"),
+            parsed("6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f", Some("0036"))
+        );
+    }
+
+    #[test]
+    fn parses_braced_and_upper_case_uuids() {
+        assert_eq!(
+            parse_wsd_port("WSD-{6B3C9D4E-1F2A-4B5C-8D7E-0A1B2C3D4E5F}"),
+            parsed("6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f", None)
+        );
+        assert_eq!(
+            parse_wsd_port("WSD-{6B3C9D4E-1F2A-4B5C-8D7E-0A1B2C3D4E5F}.0041"),
+            parsed("6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f", Some("0041"))
+        );
+    }
+
+    #[test]
+    fn parses_every_prefix_the_monitor_uses_in_any_case() {
+        let uuid = "6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f";
+        for port in [
+            "wsd-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f",
+            "Wsd-6B3C9D4E-1F2A-4B5C-8D7E-0A1B2C3D4E5F",
+            "WSD_6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f",
+            "WSD{6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f}",
+            "wsd_{6B3C9D4E-1F2A-4B5C-8D7E-0A1B2C3D4E5F}",
+            " WSD-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f",
+        ] {
+            assert_eq!(parse_wsd_port(port), parsed(uuid, None), "{}", port);
+        }
+
+        assert_eq!(parse_wsd_port("wsd-0a1b2c3d.0036"), parsed("0a1b2c3d", Some("0036")));
+    }
+
+    #[test]
+    fn agrees_with_is_wsd_port() {
+        for port in ["WSD-0a1b2c3d", "wsd-0a1b2c3d.0036", "WSD_0a1b2c3d", "WSD{0a1b2c3d}", "WSDPORT_3", "wsdport_3"] {
+            assert!(parse_wsd_port(port).is_some() && is_wsd_port(port), "{}", port);
+        }
+        for port in ["WSD0a1b2c3d", "WSDPORT_", "WSD-printer", "WSDish", "IP_10.0.0.5", "USB001"] {
+            assert!(parse_wsd_port(port).is_none() && !is_wsd_port(port), "{}", port);
+        }
+    }
+
+    #[test]
+    fn gives_no_uuid_for_numbered_wsdport_ports() {
+        for port in ["WSDPORT_3", "wsdport_12", "WSDPORT7", "WSDPORT_3.0036"] {
+            assert!(is_wsd_port(port), "{}", port);
+            assert_eq!(parse_wsd_port(port).and_then(|info| info.uuid), None, "{}", port);
+            assert_eq!(endpoint_uuid_from_port(port), None, "{}", port);
+        }
+        assert_eq!(parse_wsd_port("WSDPORT_3.0036").and_then(|info| info.suffix).as_deref(), Some("0036"));
+        assert_eq!(parse_wsd_port("WSDPORT_0a1b2c3d"), parsed("0a1b2c3d", None));
+    }
+
+    #[test]
+    fn keeps_everything_after_the_first_dot_as_the_suffix() {
+        assert_eq!(parse_wsd_port("WSD-0a1b2c3d.0036.1"), parsed("0a1b2c3d", Some("0036.1")));
+        assert_eq!(parse_wsd_port("WSD-0a1b2c3d."), parsed("0a1b2c3d", None));
+    }
+
+    #[test]
+    fn rejects_ports_that_are_not_wsd() {
+        for port in ["IP_192.168.1.20", "USB001", "LPT1:", "WSD-", "WSD-{}", "WSD-printer", "WS-0a1b2c3d", ""] {
+            assert_eq!(parse_wsd_port(port), None, "{}", port);
+        }
+    }
+
+    #[test]
+    fn takes_hosts_out_of_transport_addresses() {
+        assert_eq!(host_from_url("http://192.168.1.20:5357/6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f").as_deref(), Some("192.168.1.20"));
+        assert_eq!(host_from_url("http://printer.local/").as_deref(), Some("printer.local"));
+        assert_eq!(host_from_url("https://[2001:db8::20]:443/wsd").as_deref(), Some("2001:db8::20"));
+        assert_eq!(host_from_url("http://[fe80::1%254]:5357/").as_deref(), Some("fe80::1%4"));
+        assert_eq!(host_from_url("http:///path"), None);
+        assert_eq!(host_from_url("192.168.1.20"), None);
+    }
+
+    #[test]
+    fn round_trips_ipv6_hosts_through_port_names() {
+        for (url, port_name) in [
+            ("http://[2001:db8::20]:5357/", "IP_[2001:db8::20]"),
+            ("http://[fe80::1%254]:5357/6b3c9d4e", "IP_[fe80::1%4]"),
+            ("http://192.168.1.20:5357/", "IP_192.168.1.20"),
+        ] {
+            let host = host_from_url(url).unwrap();
+            assert_eq!(ip_port_name(&host), port_name);
+            assert_eq!(address_from_ip_port_name(port_name), Some(host.as_str()));
+        }
+    }
+
+    #[test]
+    fn endpoint_uuid_is_the_parsed_uuid() {
+        assert_eq!(endpoint_uuid_from_port("WSD-{0A1B2C3D-0000-4000-8000-000000000001}.0036").as_deref(), Some("0a1b2c3d-0000-4000-8000-000000000001"));
+        assert_eq!(endpoint_uuid_from_port("IP_10.0.0.5"), None);
+    }
+}
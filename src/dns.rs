@@ -0,0 +1,76 @@
+use std::ffi::OsString;
+use std::mem::{size_of, zeroed};
+use std::net::IpAddr;
+use std::os::windows::ffi::OsStringExt;
+use std::ptr::null_mut;
+
+use log::{info, warn};
+use winapi::shared::ws2def::{AF_INET, AF_INET6, NI_MAXHOST, NI_NAMEREQD, SOCKADDR, SOCKADDR_IN};
+use winapi::shared::ws2ipdef::SOCKADDR_IN6;
+use winapi::um::winsock2::{WSACleanup, WSAStartup, WSADATA};
+use winapi::um::ws2tcpip::GetNameInfoW;
+
+use crate::wide::wide_str_from_raw_ptr;
+
+// Winsock 2.2, the version every supported Windows ships
+const WINSOCK_VERSION: u16 = 0x0202;
+
+// Keeps Winsock started for as long as it is alive. WSAStartup is reference counted, so this is safe
+// alongside the standard library's own initialisation
+struct Winsock;
+
+impl Winsock {
+    fn start() -> Option<Winsock> {
+        let mut data: WSADATA = unsafe { zeroed() };
+        let result = unsafe { WSAStartup(WINSOCK_VERSION, &mut data) };
+        if result != 0 {
+            warn!("[{}] WSAStartup failed with error {}", "reverse_lookup", result);
+            return None;
+        }
+        Some(Winsock)
+    }
+}
+
+impl Drop for Winsock {
+    fn drop(&mut self) {
+        unsafe { WSACleanup() };
+    }
+}
+
+// Look up the host name registered for ip in reverse DNS. None if ip is not an address or has no PTR record
+pub fn reverse_lookup(ip: &str) -> Option<String> {
+    let address: IpAddr = ip.trim_matches(|c| c == '[' || c == ']').parse().ok()?;
+    let _winsock = Winsock::start()?;
+
+    let mut v4: SOCKADDR_IN = unsafe { zeroed() };
+    let mut v6: SOCKADDR_IN6 = unsafe { zeroed() };
+
+    let (sockaddr, length) = match address {
+        IpAddr::V4(v4_address) => {
+            v4.sin_family = AF_INET as u16;
+            unsafe { *v4.sin_addr.S_un.S_addr_mut() = u32::from_ne_bytes(v4_address.octets()) };
+            (&v4 as *const SOCKADDR_IN as *const SOCKADDR, size_of::<SOCKADDR_IN>())
+        }
+        IpAddr::V6(v6_address) => {
+            v6.sin6_family = AF_INET6 as u16;
+            unsafe { *v6.sin6_addr.u.Byte_mut() = v6_address.octets() };
+            (&v6 as *const SOCKADDR_IN6 as *const SOCKADDR, size_of::<SOCKADDR_IN6>())
+        }
+    };
+
+    let mut host = vec![0u16; NI_MAXHOST as usize];
+    // NI_NAMEREQD makes a missing PTR record an error rather than handing the address back as text
+    let result = unsafe {
+        GetNameInfoW(sockaddr, length as i32, host.as_mut_ptr(), host.len() as u32, null_mut(), 0, NI_NAMEREQD)
+    };
+
+    if result != 0 {
+        info!("[{}] No reverse DNS name for {} (error {})", "reverse_lookup", ip, result);
+        return None;
+    }
+
+    let name = OsString::from_wide(&wide_str_from_raw_ptr(host.as_ptr(), host.len())).to_string_lossy().into_owned();
+    info!("[{}] {} is {}", "reverse_lookup", ip, name);
+
+    Some(name)
+}
@@ -0,0 +1,200 @@
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::ptr::null_mut;
+
+use log::{info, warn, error};
+use serde::Serialize;
+use time::OffsetDateTime;
+use winapi::shared::minwindef::{DWORD, FILETIME};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winspool::{DRIVER_INFO_1W, DRIVER_INFO_8W, PRINTER_ACCESS_USE, EnumPrinterDriversW, GetPrinterDriverW};
+use winapi::um::winspool::{PRINTER_DRIVER_PACKAGE_AWARE, PRINTER_DRIVER_XPS, PRINTER_DRIVER_SANDBOX_ENABLED, PRINTER_DRIVER_CLASS};
+use winapi::um::winspool::{PRINTER_DRIVER_DERIVED, PRINTER_DRIVER_NOT_SHAREABLE, PRINTER_DRIVER_SOFT_RESET_REQUIRED, PRINTER_DRIVER_SANDBOX_DISABLED};
+
+use crate::convert::PrinterHandle;
+use crate::error::{PrinterError, format_error_code};
+use crate::printers::{unc_server_name, MinimalPrinterInfo};
+use crate::wide::{to_wide_null, wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+// Seconds between the FILETIME epoch (1601-01-01) and the Unix epoch
+const FILETIME_UNIX_OFFSET_SECS: i64 = 11_644_473_600;
+
+// cVersion of Type 4 drivers, which are installed from a driver package and configured through PrintTicket rather
+// than the DEVMODE alone
+pub const V4_DRIVER_VERSION: DWORD = 4;
+
+// DRIVER_INFO_8W.dwPrinterDriverAttributes bits and the names they are logged under
+const DRIVER_ATTRIBUTE_NAMES: [(DWORD, &str); 8] = [
+    (PRINTER_DRIVER_PACKAGE_AWARE, "Package Aware"),
+    (PRINTER_DRIVER_XPS, "XPS"),
+    (PRINTER_DRIVER_SANDBOX_ENABLED, "Sandbox Enabled"),
+    (PRINTER_DRIVER_CLASS, "Class"),
+    (PRINTER_DRIVER_DERIVED, "Derived"),
+    (PRINTER_DRIVER_NOT_SHAREABLE, "Not Shareable"),
+    (PRINTER_DRIVER_SOFT_RESET_REQUIRED, "Soft Reset Required"),
+    (PRINTER_DRIVER_SANDBOX_DISABLED, "Sandbox Disabled"),
+];
+
+// What GetPrinterDriverW reports at level 8 about the driver a printer uses
+#[derive(Clone, Debug, Serialize)]
+pub struct DriverInfo {
+    pub name: String,
+    // Platform the driver was built for, e.g. "Windows x64" or "Windows ARM64"
+    pub environment: String,
+    // Driver model: 3 for classic v3 drivers, 4 for v4 class drivers
+    pub model_version: DWORD,
+    // File version from the INF, as a.b.c.d
+    pub version: String,
+    // DriverVer date from the INF, as YYYY-MM-DD
+    pub date: String,
+    pub manufacturer: String,
+    pub provider: String,
+    // PRINTER_DRIVER_* bits, such as whether it is an XPS or class driver
+    pub attributes: DWORD,
+}
+
+impl DriverInfo {
+    pub fn is_v4(&self) -> bool {
+        self.model_version == V4_DRIVER_VERSION
+    }
+
+    pub fn attribute_names(&self) -> Vec<&'static str> {
+        DRIVER_ATTRIBUTE_NAMES.iter()
+            .filter(|(bit, _)| self.attributes & bit != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+fn lossy_wide(ptr: *const u16) -> String {
+    OsString::from_wide(&wide_str_from_raw_ptr(ptr, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned()
+}
+
+// DWORDLONG driver versions pack four 16-bit parts, most significant first
+fn format_driver_version(version: u64) -> String {
+    format!("{}.{}.{}.{}", version >> 48, (version >> 32) & 0xffff, (version >> 16) & 0xffff, version & 0xffff)
+}
+
+fn format_driver_date(date: &FILETIME) -> String {
+    let ticks = ((date.dwHighDateTime as u64) << 32) | date.dwLowDateTime as u64;
+    if ticks == 0 {
+        return String::new();
+    }
+
+    OffsetDateTime::from_unix_timestamp((ticks / 10_000_000) as i64 - FILETIME_UNIX_OFFSET_SECS)
+        .map(|date| format!("{:04}-{:02}-{:02}", date.year(), date.month() as u8, date.day()))
+        .unwrap_or_default()
+}
+
+// Look up the driver installed for printer_name in this machine's environment
+pub fn get_driver_info(printer_name: &OsStr) -> Result<DriverInfo, PrinterError> {
+    let handle = PrinterHandle::open(printer_name, PRINTER_ACCESS_USE)?;
+
+    // First call to GetPrinterDriverW is to get the number of bytes needed for the DRIVER_INFO_8W struct
+    let mut bytes_needed: DWORD = 0;
+    unsafe {
+        GetPrinterDriverW(handle.0, null_mut(), 8, null_mut(), 0, &mut bytes_needed);
+    }
+
+    if bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] GetPrinterDriverW failed to set bytes_needed: {}", "get_driver_info", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::GetDriverFailed { name: printer_name.to_string_lossy().into_owned(), code: error_code });
+    }
+
+    let mut buffer = vec![0u8; bytes_needed as usize];
+    let result = unsafe {
+        GetPrinterDriverW(handle.0, null_mut(), 8, buffer.as_mut_ptr(), bytes_needed, &mut bytes_needed)
+    };
+
+    if result == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] GetPrinterDriverW failed to populate buffer: {}", "get_driver_info", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::GetDriverFailed { name: printer_name.to_string_lossy().into_owned(), code: error_code });
+    }
+
+    let driver = unsafe { &*(buffer.as_ptr() as *const DRIVER_INFO_8W) };
+    let driver_info = DriverInfo {
+        name: lossy_wide(driver.pName),
+        environment: lossy_wide(driver.pEnvironment),
+        model_version: driver.cVersion,
+        version: format_driver_version(driver.dwlDriverVersion),
+        date: format_driver_date(&driver.ftDriverDate),
+        manufacturer: lossy_wide(driver.pszMfgName),
+        provider: lossy_wide(driver.pszProvider),
+        attributes: driver.dwPrinterDriverAttributes,
+    };
+
+    info!("[{}] {:?} uses {} {} ({}), a v{} driver [{}]", "get_driver_info", printer_name, driver_info.name, driver_info.version,
+        driver_info.environment, driver_info.model_version, driver_info.attribute_names().join(", "));
+
+    Ok(driver_info)
+}
+
+// Fill in driver_version on each printer. A printer whose driver cannot be read is left without one
+pub fn attach_driver_versions(printers: &mut [MinimalPrinterInfo]) {
+    for printer in printers {
+        printer.driver_version = get_driver_info(&printer.printer_name)
+            .map(|driver| format!("{} ({}, {})", driver.version, driver.environment, driver.date))
+            .ok();
+    }
+}
+
+// Names of every printer driver installed on server, or on this machine when server is None, for the environment
+// this process runs in
+pub fn get_installed_drivers(server: Option<&str>) -> Result<Vec<String>, PrinterError> {
+    let mut wide_server = server.map(|server| to_wide_null(OsStr::new(&unc_server_name(server))));
+    let server_ptr = wide_server.as_mut().map_or(null_mut(), |name| name.as_mut_ptr());
+
+    let mut bytes_needed: DWORD = 0;
+    let mut num_drivers: DWORD = 0;
+
+    // First call to EnumPrinterDriversW is to get the number of bytes needed
+    info!("[{}] First call to EnumPrinterDriversW to determine bytes_needed on {}", "get_installed_drivers", server.unwrap_or("the local machine"));
+    let enum_drivers_result1 = unsafe {
+        EnumPrinterDriversW(server_ptr, null_mut(), 1, null_mut(), 0, &mut bytes_needed, &mut num_drivers)
+    };
+
+    if enum_drivers_result1 == 0 && bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumPrinterDriversW failed to set bytes_needed: {}", "get_installed_drivers", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumDriversFailed { code: error_code });
+    } else if bytes_needed == 0 {
+        warn!("[{}] No drivers found", "get_installed_drivers");
+        return Ok(Vec::new());
+    }
+
+    let mut buffer = vec![0u8; bytes_needed as usize];
+
+    // Second call to EnumPrinterDriversW fills the buffer with DRIVER_INFO_1W structs
+    let enum_drivers_result2 = unsafe {
+        EnumPrinterDriversW(server_ptr, null_mut(), 1, buffer.as_mut_ptr(), bytes_needed, &mut bytes_needed, &mut num_drivers)
+    };
+
+    if enum_drivers_result2 == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumPrinterDriversW failed to populate buffer: {}", "get_installed_drivers", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumDriversFailed { code: error_code });
+    }
+
+    let driver_info = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const DRIVER_INFO_1W, num_drivers as usize)
+    };
+
+    let drivers: Vec<String> = driver_info.iter().map(|driver| lossy_wide(driver.pName)).collect();
+
+    info!("[{}] Found {} drivers", "get_installed_drivers", drivers.len());
+
+    Ok(drivers)
+}
+
+// Printers whose driver is not among installed. Such a printer fails to print whatever port it is on, so
+// converting it will not help. A printer enumerated without its driver name, as at level 4, is not counted
+pub fn missing_drivers<'a>(printers: impl IntoIterator<Item = &'a MinimalPrinterInfo>, installed: &[String]) -> Vec<&'a MinimalPrinterInfo> {
+    printers.into_iter()
+        .filter(|printer| {
+            let driver_name = printer.driver_name.to_string_lossy();
+            !driver_name.is_empty() && !installed.iter().any(|driver| driver.eq_ignore_ascii_case(&driver_name))
+        })
+        .collect()
+}
@@ -0,0 +1,141 @@
+use std::ffi::OsStr;
+use std::io;
+use std::mem;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::null_mut;
+
+use log::{info, error};
+use winapi::shared::minwindef::DWORD;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetExitCodeProcess, OpenProcessToken};
+use winapi::um::securitybaseapi::GetTokenInformation;
+use winapi::um::shellapi::{SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW, ShellExecuteExW};
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::INFINITE;
+use winapi::um::winnt::{HANDLE, TOKEN_ELEVATION, TOKEN_QUERY, TokenElevation};
+use winapi::um::winuser::SW_SHOWNORMAL;
+
+use crate::error::format_error_code;
+use crate::wide::to_wide_null;
+
+// Whether this process is running with an elevated (administrator) token.
+// SetPrinterW and port creation fail with access denied without one
+pub fn is_elevated() -> bool {
+    let mut token: HANDLE = null_mut();
+
+    if unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) } == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] OpenProcessToken failed: {}", "is_elevated", format_error_code(error_code).unwrap_or_default());
+        return false;
+    }
+
+    let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+    let mut returned_length: DWORD = 0;
+    let token_info_result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            &mut elevation as *mut TOKEN_ELEVATION as *mut _,
+            mem::size_of::<TOKEN_ELEVATION>() as DWORD,
+            &mut returned_length,
+        )
+    };
+    let error_code = unsafe { GetLastError() };
+
+    unsafe {
+        CloseHandle(token);
+    }
+
+    if token_info_result == 0 {
+        error!("[{}] GetTokenInformation failed: {}", "is_elevated", format_error_code(error_code).unwrap_or_default());
+        return false;
+    }
+
+    let elevated = elevation.TokenIsElevated != 0;
+    info!("[{}] Process token elevated: {}", "is_elevated", elevated);
+
+    elevated
+}
+
+// Append arg to a command line so that CommandLineToArgvW in the child splits it back out unchanged
+fn append_quoted_arg(command_line: &mut Vec<u16>, arg: &OsStr) {
+    const QUOTE: u16 = b'"' as u16;
+    const BACKSLASH: u16 = b'\\' as u16;
+
+    let wide: Vec<u16> = arg.encode_wide().collect();
+    let needs_quotes = wide.is_empty() || wide.iter().any(|&c| c == b' ' as u16 || c == b'\t' as u16 || c == QUOTE);
+
+    if !command_line.is_empty() {
+        command_line.push(b' ' as u16);
+    }
+
+    if !needs_quotes {
+        command_line.extend(wide);
+        return;
+    }
+
+    // Backslashes are only special when they precede a quote, where each one has to be doubled
+    command_line.push(QUOTE);
+    let mut backslashes = 0;
+    for c in wide {
+        if c == BACKSLASH {
+            backslashes += 1;
+            continue;
+        }
+
+        if c == QUOTE {
+            command_line.extend(std::iter::repeat_n(BACKSLASH, backslashes * 2 + 1));
+        } else {
+            command_line.extend(std::iter::repeat_n(BACKSLASH, backslashes));
+        }
+        command_line.push(c);
+        backslashes = 0;
+    }
+    command_line.extend(std::iter::repeat_n(BACKSLASH, backslashes * 2));
+    command_line.push(QUOTE);
+}
+
+// Start this executable again through a UAC prompt with the same arguments and working directory,
+// wait for it to finish and return its exit code
+pub fn relaunch_elevated() -> io::Result<u32> {
+    let exe = std::env::current_exe()?;
+    let current_dir = std::env::current_dir()?;
+
+    let mut parameters: Vec<u16> = Vec::new();
+    for arg in std::env::args_os().skip(1) {
+        append_quoted_arg(&mut parameters, &arg);
+    }
+    parameters.push(0);
+
+    let verb = to_wide_null(OsStr::new("runas"));
+    let file = to_wide_null(exe.as_os_str());
+    let directory = to_wide_null(current_dir.as_os_str());
+
+    let mut exec_info: SHELLEXECUTEINFOW = unsafe { mem::zeroed() };
+    exec_info.cbSize = mem::size_of::<SHELLEXECUTEINFOW>() as DWORD;
+    exec_info.fMask = SEE_MASK_NOCLOSEPROCESS;
+    exec_info.lpVerb = verb.as_ptr();
+    exec_info.lpFile = file.as_ptr();
+    exec_info.lpParameters = parameters.as_ptr();
+    exec_info.lpDirectory = directory.as_ptr();
+    exec_info.nShow = SW_SHOWNORMAL;
+
+    info!("[{}] Relaunching {} elevated with {:?}", "relaunch_elevated", exe.display(), String::from_utf16_lossy(&parameters[..parameters.len() - 1]));
+    if unsafe { ShellExecuteExW(&mut exec_info) } == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] ShellExecuteExW failed: {}", "relaunch_elevated", format_error_code(error_code).unwrap_or_default());
+        return Err(io::Error::from_raw_os_error(error_code as i32));
+    }
+
+    let mut exit_code: DWORD = 0;
+    unsafe {
+        WaitForSingleObject(exec_info.hProcess, INFINITE);
+        GetExitCodeProcess(exec_info.hProcess, &mut exit_code);
+        CloseHandle(exec_info.hProcess);
+    }
+
+    info!("[{}] Elevated instance exited with {}", "relaunch_elevated", exit_code);
+
+    Ok(exit_code)
+}
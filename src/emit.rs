@@ -0,0 +1,240 @@
+// Write a conversion plan out as something another tool applies, for change-control processes that need to
+// review and run the change themselves instead of letting convert make it
+
+use std::fmt::Write;
+use std::net::Ipv4Addr;
+
+use crate::convert::{PortProtocol, TcpipPortConfig, DEFAULT_SNMP_DEV_INDEX, LPR_DBLSPOOL, PROTOCOL_LPR_TYPE, PROTOCOL_RAWTCP_TYPE};
+use crate::plan::{ConversionPlan, NewPort};
+use crate::sys::DWORD;
+
+// Quote text as a PowerShell single-quoted string, where the only escape is doubling the quote. PowerShell also
+// treats typographic quotes as quotes, and printer names pasted from documents sometimes contain them
+fn powershell_quote(text: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in text.chars() {
+        if matches!(c, '\'' | '\u{2018}' | '\u{2019}') {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+// The Add-PrinterPort arguments for a new port at address, following config
+fn add_printer_port_arguments(port: &str, address: &str, config: &TcpipPortConfig) -> String {
+    let address = address.trim_start_matches('[').trim_end_matches(']');
+    let mut arguments = format!("-Name {}", powershell_quote(port));
+
+    // The LPR parameter set has no port number; Add-PrinterPort always uses 515 for it
+    match config.protocol {
+        PortProtocol::Raw => {
+            let _ = write!(arguments, " -PrinterHostAddress {} -PortNumber {}", powershell_quote(address), config.port_number);
+        }
+        PortProtocol::Lpr => {
+            let _ = write!(arguments, " -LprHostAddress {} -LprQueueName {}", powershell_quote(address), powershell_quote(config.queue()));
+        }
+    }
+
+    if let Some(community) = &config.snmp_community {
+        let _ = write!(arguments, " -SNMP {} -SNMPCommunity {}", DEFAULT_SNMP_DEV_INDEX, powershell_quote(community));
+    }
+
+    arguments
+}
+
+// A PowerShell script that makes the same changes as applying plan: Add-PrinterPort for each port that does not
+// exist yet, then Set-Printer to move the printer onto it. It stops at the first failure, leaving the printers
+// after it untouched. server is the print server the changes are made on, or None for the machine it runs on
+pub fn powershell_script(plan: &ConversionPlan, config: &TcpipPortConfig, server: Option<&str>) -> String {
+    let computer = server
+        .map(|server| format!(" -ComputerName {}", powershell_quote(server.trim_start_matches('\\'))))
+        .unwrap_or_default();
+
+    let mut script = String::new();
+    let _ = writeln!(script, "# Moves {} printers from WSD ports to Standard TCP/IP ports. Written by wsd_to_ip convert --emit-powershell", plan.len());
+    let _ = writeln!(script, "#Requires -Modules PrintManagement");
+    let _ = writeln!(script, "$ErrorActionPreference = 'Stop'");
+
+    for conversion in &plan.conversions {
+        let _ = writeln!(script);
+        let _ = writeln!(script, "# {}: {} -> {}", conversion.printer_name, conversion.from_port, conversion.to_port);
+
+        for NewPort { port, address } in &conversion.new_ports {
+            let _ = writeln!(script, "if (-not (Get-PrinterPort -Name {}{} -ErrorAction SilentlyContinue)) {{", powershell_quote(port), computer);
+            let _ = writeln!(script, "    Add-PrinterPort {}{}", add_printer_port_arguments(port, address, config), computer);
+            let _ = writeln!(script, "}}");
+        }
+
+        let _ = writeln!(script, "Set-Printer -Name {} -PortName {}{}", powershell_quote(&conversion.printer_name), powershell_quote(&conversion.to_port), computer);
+    }
+
+    script
+}
+
+// Where the Standard TCP/IP Port monitor keeps its port definitions
+const TCPIP_PORTS_KEY: &str = r"HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\Print\Monitors\Standard TCP/IP Port\Ports";
+
+// Version of the port entry layout the monitor writes itself on current Windows
+const TCPIP_PORT_VERSION: DWORD = 2;
+
+// Quote text as a .reg string value, escaping backslashes and double quotes
+fn reg_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+fn reg_dword(name: &str, value: DWORD) -> String {
+    format!("{}=dword:{:08x}", reg_quote(name), value)
+}
+
+fn reg_string(name: &str, value: &str) -> String {
+    format!("{}={}", reg_quote(name), reg_quote(value))
+}
+
+// A .reg fragment defining every port plan would create, with the values the Standard TCP/IP Port monitor
+// writes for a port configured the same way. Printers are not moved onto the ports, and the monitor only reads
+// its ports when the spooler starts, so the Print Spooler has to be restarted after importing it. Lines end in
+// CRLF as regedit expects; the file itself has to be saved as UTF-16, see utf16_with_bom
+pub fn reg_file(plan: &ConversionPlan, config: &TcpipPortConfig) -> String {
+    let mut lines = vec![
+        "Windows Registry Editor Version 5.00".to_string(),
+        String::new(),
+        format!("; Standard TCP/IP ports for {} printers. Written by wsd_to_ip convert --emit-reg", plan.len()),
+        "; The ports only appear once the Print Spooler is restarted, and printers still have to be moved onto them".to_string(),
+    ];
+
+    let mut written: Vec<&str> = Vec::new();
+    for conversion in &plan.conversions {
+        for NewPort { port, address } in &conversion.new_ports {
+            // Printers sharing a device share its port, which only needs defining once
+            if written.iter().any(|done| done.eq_ignore_ascii_case(port)) {
+                continue;
+            }
+            written.push(port);
+
+            let address = address.trim_start_matches('[').trim_end_matches(']');
+
+            lines.push(String::new());
+            lines.push(format!("; {}", conversion.printer_name));
+            lines.push(format!("[{}\\{}]", TCPIP_PORTS_KEY, port));
+            lines.push(reg_dword("Protocol", match config.protocol {
+                PortProtocol::Raw => PROTOCOL_RAWTCP_TYPE,
+                PortProtocol::Lpr => PROTOCOL_LPR_TYPE,
+            }));
+            lines.push(reg_dword("Version", TCPIP_PORT_VERSION));
+
+            // The monitor keeps IPv4 literals apart from host names; IPv6 addresses go in with the names
+            if address.parse::<Ipv4Addr>().is_ok() {
+                lines.push(reg_string("HostName", ""));
+                lines.push(reg_string("IPAddress", address));
+            } else {
+                lines.push(reg_string("HostName", address));
+                lines.push(reg_string("IPAddress", ""));
+            }
+            lines.push(reg_string("HWAddress", ""));
+            lines.push(reg_dword("PortNumber", config.port_number));
+
+            if config.protocol == PortProtocol::Lpr {
+                lines.push(reg_string("Queue", config.queue()));
+                lines.push(reg_dword("Double Spool", LPR_DBLSPOOL));
+            }
+
+            match &config.snmp_community {
+                Some(community) => {
+                    lines.push(reg_string("SNMP Community", community));
+                    lines.push(reg_dword("SNMP Enabled", 1));
+                    lines.push(reg_dword("SNMP Index", DEFAULT_SNMP_DEV_INDEX));
+                }
+                None => lines.push(reg_dword("SNMP Enabled", 0)),
+            }
+        }
+    }
+
+    lines.push(String::new());
+    lines.join("\r\n")
+}
+
+// Encode a .reg file the way regedit writes them: UTF-16LE behind a byte order mark. Version 5.00 files in any
+// other encoding are misread once a printer name strays outside ASCII
+pub fn utf16_with_bom(text: &str) -> Vec<u8> {
+    std::iter::once(0xfeff_u16)
+        .chain(text.encode_utf16())
+        .flat_map(|unit| unit.to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    // The lines of script after its three header lines
+    fn body(script: &str) -> Vec<&str> {
+        script.lines().skip(3).collect()
+    }
+
+    #[test]
+    fn doubles_every_kind_of_single_quote() {
+        assert_eq!(powershell_quote("Front desk"), "'Front desk'");
+        assert_eq!(powershell_quote("Bob's printer"), "'Bob''s printer'");
+        assert_eq!(powershell_quote("Bob\u{2019}s \u{2018}colour\u{2019}"), "'Bob\u{2019}\u{2019}s \u{2018}\u{2018}colour\u{2019}\u{2019}'");
+        assert_eq!(powershell_quote("$(Stop-Service Spooler) \"x\""), "'$(Stop-Service Spooler) \"x\"'");
+    }
+
+    #[test]
+    fn quotes_printer_names_with_single_quotes() {
+        let mut plan = ConversionPlan::new();
+        plan.push(OsStr::new("Bob's 'Front' desk"), "WSD-0a1b2c3d".to_string(), "IP_10.0.0.5".to_string(), "10.0.0.5".to_string());
+
+        let script = powershell_script(&plan, &TcpipPortConfig::default(), None);
+        assert!(script.starts_with("# Moves 1 printers from WSD ports to Standard TCP/IP ports."));
+        assert_eq!(body(&script), [
+            "",
+            "# Bob's 'Front' desk: WSD-0a1b2c3d -> IP_10.0.0.5",
+            "if (-not (Get-PrinterPort -Name 'IP_10.0.0.5' -ErrorAction SilentlyContinue)) {",
+            "    Add-PrinterPort -Name 'IP_10.0.0.5' -PrinterHostAddress '10.0.0.5' -PortNumber 9100 -SNMP 1 -SNMPCommunity 'public'",
+            "}",
+            "Set-Printer -Name 'Bob''s ''Front'' desk' -PortName 'IP_10.0.0.5'",
+        ]);
+    }
+
+    #[test]
+    fn adds_only_the_new_members_of_a_pool() {
+        let mut plan = ConversionPlan::new();
+        let new_ports = vec![
+            NewPort { port: "IP_10.0.0.5".to_string(), address: "10.0.0.5".to_string() },
+            NewPort { port: "IP_fe80::1".to_string(), address: "[fe80::1]".to_string() },
+        ];
+        plan.push_pool(
+            OsStr::new("Pool"),
+            "WSD-0a1b2c3d,IP_10.0.0.9,WSD-0a1b2c3e".to_string(),
+            "IP_10.0.0.5,IP_10.0.0.9,IP_fe80::1".to_string(),
+            new_ports,
+        );
+
+        let config = TcpipPortConfig { protocol: PortProtocol::Lpr, lpr_queue: Some("q'1".to_string()), snmp_community: None, ..TcpipPortConfig::default() };
+        let script = powershell_script(&plan, &config, Some(r"\\printsrv"));
+        assert_eq!(body(&script), [
+            "",
+            "# Pool: WSD-0a1b2c3d,IP_10.0.0.9,WSD-0a1b2c3e -> IP_10.0.0.5,IP_10.0.0.9,IP_fe80::1",
+            "if (-not (Get-PrinterPort -Name 'IP_10.0.0.5' -ComputerName 'printsrv' -ErrorAction SilentlyContinue)) {",
+            "    Add-PrinterPort -Name 'IP_10.0.0.5' -LprHostAddress '10.0.0.5' -LprQueueName 'q''1' -ComputerName 'printsrv'",
+            "}",
+            "if (-not (Get-PrinterPort -Name 'IP_fe80::1' -ComputerName 'printsrv' -ErrorAction SilentlyContinue)) {",
+            "    Add-PrinterPort -Name 'IP_fe80::1' -LprHostAddress 'fe80::1' -LprQueueName 'q''1' -ComputerName 'printsrv'",
+            "}",
+            "Set-Printer -Name 'Pool' -PortName 'IP_10.0.0.5,IP_10.0.0.9,IP_fe80::1' -ComputerName 'printsrv'",
+        ]);
+    }
+
+    #[test]
+    fn moves_a_pool_onto_ports_that_all_exist_without_adding_any() {
+        let mut plan = ConversionPlan::new();
+        plan.push_pool(OsStr::new("Pool"), "WSD-0a1b2c3d,IP_10.0.0.9".to_string(), "IP_10.0.0.9".to_string(), Vec::new());
+
+        let script = powershell_script(&plan, &TcpipPortConfig::default(), None);
+        assert_eq!(body(&script), ["", "# Pool: WSD-0a1b2c3d,IP_10.0.0.9 -> IP_10.0.0.9", "Set-Printer -Name 'Pool' -PortName 'IP_10.0.0.9'"]);
+    }
+}
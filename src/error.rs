@@ -0,0 +1,147 @@
+#[cfg(windows)]
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+#[cfg(windows)]
+use std::ptr;
+
+use thiserror::Error;
+
+use crate::sys::{DWORD, ERROR_UNKNOWN_PORT};
+
+// Failures from the Win32 printing APIs and the files the tool reads. Codes are the raw values reported by GetLastError
+// (or the monitor status for XcvData) so callers can match on specific Windows errors
+#[derive(Debug, Error)]
+pub enum PrinterError {
+    #[error("EnumPrintersW failed in {call_site} with error {code}{}", describe(*code))]
+    EnumFailed { call_site: &'static str, code: u32 },
+
+    #[error("EnumPortsW failed with error {code}{}", describe(*code))]
+    EnumPortsFailed { code: u32 },
+
+    #[error("EnumMonitorsW failed with error {code}{}", describe(*code))]
+    EnumMonitorsFailed { code: u32 },
+
+    #[error("EnumPrinterDriversW failed with error {code}{}", describe(*code))]
+    EnumDriversFailed { code: u32 },
+
+    #[error("the {0} port monitor is not installed")]
+    MonitorMissing(String),
+
+    #[error("OpenPrinterW failed for {name} with error {code}{}", describe(*code))]
+    OpenPrinterFailed { name: String, code: u32 },
+
+    #[error("GetPrinterW failed for {name} with error {code}{}", describe(*code))]
+    GetPrinterFailed { name: String, code: u32 },
+
+    #[error("GetPrinterDriverW failed for {name} with error {code}{}", describe(*code))]
+    GetDriverFailed { name: String, code: u32 },
+
+    #[error("SetPrinterW failed for {name} with error {code}{}", describe(*code))]
+    SetPrinterFailed { name: String, code: u32 },
+
+    #[error("conversion reported success but port unchanged: {name} is on {actual} instead of {expected}")]
+    PortUnchanged { name: String, expected: String, actual: String },
+
+    #[error("SetDefaultPrinterW failed for {name} with error {code}{}", describe(*code))]
+    SetDefaultFailed { name: String, code: u32 },
+
+    #[error("port {port} does not exist and must be created first")]
+    UnknownPort { port: String },
+
+    #[error("creating the Standard TCP/IP port failed with error {0}{}", describe(*.0))]
+    PortCreationFailed(u32),
+
+    #[error("XcvDataW {command} failed for {port} with error {code}{}", describe(*code))]
+    PortConfigFailed { command: &'static str, port: String, code: u32 },
+
+    #[error("{address} is longer than the {max} characters a Standard TCP/IP port can print to")]
+    HostAddressTooLong { address: String, max: usize },
+
+    #[error("port {port} already exists but {differences}")]
+    PortConflict { port: String, differences: String },
+
+    #[error("DeletePortW failed for {port} with error {code}{}", describe(*code))]
+    PortDeletionFailed { port: String, code: u32 },
+
+    #[error("{operation} failed with error {code}{}", describe(*code))]
+    ServiceFailed { operation: &'static str, code: u32 },
+
+    #[error("timed out waiting for the Print Spooler to become {state}")]
+    ServiceTimeout { state: &'static str },
+
+    #[error("RegisterEventSourceW failed with error {code}{}", describe(*code))]
+    EventLogFailed { code: u32 },
+
+    #[error("{operation} failed printing a test page on {name} with error {code}{}", describe(*code))]
+    TestPageFailed { name: String, operation: &'static str, code: u32 },
+
+    #[error("timed out resolving the WSD device address")]
+    WsdResolutionTimeout,
+
+    #[error("{path}: {reason}")]
+    FileFailed { path: String, reason: String },
+}
+
+impl PrinterError {
+    // The Windows error or monitor status behind this error, if there is one
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            PrinterError::EnumFailed { code, .. }
+            | PrinterError::EnumPortsFailed { code }
+            | PrinterError::EnumMonitorsFailed { code }
+            | PrinterError::EnumDriversFailed { code }
+            | PrinterError::OpenPrinterFailed { code, .. }
+            | PrinterError::GetPrinterFailed { code, .. }
+            | PrinterError::GetDriverFailed { code, .. }
+            | PrinterError::SetPrinterFailed { code, .. }
+            | PrinterError::SetDefaultFailed { code, .. }
+            | PrinterError::PortConfigFailed { code, .. }
+            | PrinterError::PortDeletionFailed { code, .. }
+            | PrinterError::ServiceFailed { code, .. }
+            | PrinterError::TestPageFailed { code, .. }
+            | PrinterError::EventLogFailed { code } => Some(*code),
+            PrinterError::PortCreationFailed(code) => Some(*code),
+            PrinterError::UnknownPort { .. } => Some(ERROR_UNKNOWN_PORT),
+            _ => None,
+        }
+    }
+}
+
+// Utility function to turn a Windows error code into its system message
+#[cfg(windows)]
+pub fn format_error_code(error_code: DWORD) -> Option<String> {
+    if error_code == 0 {
+        None
+    } else {
+        let mut buffer: Vec<u16> = vec![0; 256];
+        let len = unsafe {
+            winapi::um::winbase::FormatMessageW(
+                winapi::um::winbase::FORMAT_MESSAGE_FROM_SYSTEM
+                    | winapi::um::winbase::FORMAT_MESSAGE_IGNORE_INSERTS,
+                ptr::null(),
+                error_code,
+                0,
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                ptr::null_mut(),
+            )
+        };
+        buffer.resize(len as usize, 0);
+        Some(OsString::from_wide(&buffer).to_string_lossy().into_owned())
+    }
+}
+
+// There is no system message table to look codes up in off Windows
+#[cfg(not(windows))]
+pub fn format_error_code(_error_code: DWORD) -> Option<String> {
+    None
+}
+
+// Suffix appended to error messages so the system text travels with the code
+fn describe(code: u32) -> String {
+    match format_error_code(code) {
+        Some(message) if !message.trim().is_empty() => format!(" ({})", message.trim_end()),
+        _ => String::new(),
+    }
+}
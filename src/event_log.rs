@@ -0,0 +1,103 @@
+use std::ffi::OsStr;
+use std::ptr::null_mut;
+
+use log::{info, warn};
+use winapi::shared::minwindef::{DWORD, WORD};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winbase::{DeregisterEventSource, RegisterEventSourceW, ReportEventW};
+use winapi::um::winnt::{EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, HANDLE, LPCWSTR};
+use winreg::enums::HKEY_LOCAL_MACHINE;
+use winreg::RegKey;
+
+use crate::error::{PrinterError, format_error_code};
+use crate::wide::to_wide_null;
+
+// Source name the entries are filed under in the Application log
+pub const EVENT_SOURCE: &str = "wsd_to_ip";
+
+// Event IDs, for filtering and alerting on
+pub const EVENT_CONVERTED: DWORD = 100;
+pub const EVENT_CONVERSION_FAILED: DWORD = 101;
+
+const APPLICATION_LOG_KEY: &str = r"SYSTEM\CurrentControlSet\Services\EventLog\Application";
+
+// EventCreate.exe ships a message table whose entries 1-1000 print their first insertion string unchanged,
+// so pointing the source at it makes Event Viewer show the text without a message DLL of our own
+const EVENT_MESSAGE_FILE: &str = r"%SystemRoot%\System32\EventCreate.exe";
+
+// Error, warning and information entries
+const TYPES_SUPPORTED: DWORD = 7;
+
+// Register EVENT_SOURCE with the Application log if it is not already. Entries can still be written without
+// this, Event Viewer just cannot render their text
+fn register_event_source() {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let result = hklm.create_subkey(format!(r"{}\{}", APPLICATION_LOG_KEY, EVENT_SOURCE)).and_then(|(key, _)| {
+        key.set_value("EventMessageFile", &EVENT_MESSAGE_FILE)?;
+        key.set_value("TypesSupported", &TYPES_SUPPORTED)
+    });
+
+    if let Err(e) = result {
+        warn!("[{}] Could not register the {} event source: {}", "register_event_source", EVENT_SOURCE, e);
+    }
+}
+
+// A handle to the Application log for writing conversion events, closed when dropped
+pub struct EventLog(HANDLE);
+
+impl EventLog {
+    pub fn open() -> Result<EventLog, PrinterError> {
+        register_event_source();
+
+        let source = to_wide_null(OsStr::new(EVENT_SOURCE));
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), source.as_ptr()) };
+
+        if handle.is_null() {
+            let error_code = unsafe { GetLastError() };
+            warn!("[{}] RegisterEventSourceW failed: {}", "EventLog::open", format_error_code(error_code).unwrap_or_default());
+            return Err(PrinterError::EventLogFailed { code: error_code });
+        }
+
+        info!("[{}] Writing conversion events to the Application log as {}", "EventLog::open", EVENT_SOURCE);
+
+        Ok(EventLog(handle))
+    }
+
+    // Record a printer moved from from_port to to_port
+    pub fn converted(&self, printer_name: &str, from_port: &str, to_port: &str) {
+        let message = format!("Converted printer {} from port {} to port {}", printer_name, from_port, to_port);
+        self.report(EVENTLOG_INFORMATION_TYPE, EVENT_CONVERTED, &message);
+    }
+
+    // Record a printer that could not be moved, with the Windows error code when there is one
+    pub fn failed(&self, printer_name: &str, from_port: &str, to_port: &str, error: &PrinterError) {
+        let message = format!(
+            "Failed to convert printer {} from port {} to port {}: {} (error code {})",
+            printer_name, from_port, to_port, error, error.code().unwrap_or(0)
+        );
+        self.report(EVENTLOG_ERROR_TYPE, EVENT_CONVERSION_FAILED, &message);
+    }
+
+    // A failed write is logged and otherwise ignored, it must never stop a conversion
+    fn report(&self, event_type: WORD, event_id: DWORD, message: &str) {
+        let wide_message = to_wide_null(OsStr::new(message));
+        let mut strings: [LPCWSTR; 1] = [wide_message.as_ptr()];
+
+        let result = unsafe {
+            ReportEventW(self.0, event_type, 0, event_id, null_mut(), 1, 0, strings.as_mut_ptr(), null_mut())
+        };
+
+        if result == 0 {
+            let error_code = unsafe { GetLastError() };
+            warn!("[{}] ReportEventW failed: {}", "EventLog::report", format_error_code(error_code).unwrap_or_default());
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        unsafe {
+            DeregisterEventSource(self.0);
+        }
+    }
+}
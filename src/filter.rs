@@ -0,0 +1,214 @@
+use log::{info, warn};
+use regex::Regex;
+
+use crate::printers::MinimalPrinterInfo;
+
+// Keep the printers whose name matches pattern
+pub fn filter_printers_by_name(printers: &[MinimalPrinterInfo], pattern: &Regex) -> Vec<MinimalPrinterInfo> {
+    let matching: Vec<MinimalPrinterInfo> = printers.iter()
+        .filter(|printer| pattern.is_match(&printer.printer_name.to_string_lossy()))
+        .cloned()
+        .collect();
+
+    info!("[{}] {} of {} printers match {}", "filter_printers_by_name", matching.len(), printers.len(), pattern);
+
+    matching
+}
+
+// Keep the printers whose driver name contains driver_substring, ignoring case
+pub fn filter_printers_by_driver(printers: &[MinimalPrinterInfo], driver_substring: &str) -> Vec<MinimalPrinterInfo> {
+    let needle = driver_substring.to_lowercase();
+    let matching: Vec<MinimalPrinterInfo> = printers.iter()
+        .filter(|printer| printer.driver_name.to_string_lossy().to_lowercase().contains(&needle))
+        .cloned()
+        .collect();
+
+    info!("[{}] {} of {} printers use a driver containing {:?}", "filter_printers_by_driver", matching.len(), printers.len(), driver_substring);
+
+    matching
+}
+
+// Keep the printers the spooler reports as online, or as offline when online is false. is_offline says which
+pub fn filter_printers_by_state(printers: &[MinimalPrinterInfo], online: bool) -> Vec<MinimalPrinterInfo> {
+    let matching: Vec<MinimalPrinterInfo> = printers.iter()
+        .filter(|printer| printer.is_offline() != online)
+        .cloned()
+        .collect();
+
+    info!("[{}] {} of {} printers are {}", "filter_printers_by_state", matching.len(), printers.len(), if online { "online" } else { "offline" });
+
+    matching
+}
+
+// Whether name matches pattern, ignoring case as the spooler does. * stands for any run of characters and ? for any
+// single one, so a pattern with neither only matches that exact name
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Where the last * was, and how much of name it had taken when matching went on past it
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the * take one more character and try again from there
+                Some((star, taken)) => {
+                    backtrack = Some((star, taken + 1));
+                    p = star + 1;
+                    n = taken + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// The first of patterns that name matches, if any
+pub fn matching_exclusion<'a>(name: &str, patterns: &'a [String]) -> Option<&'a str> {
+    patterns.iter().map(String::as_str).find(|pattern| glob_matches(pattern, name))
+}
+
+// Drop the printers whose name matches any of patterns, logging each one left out
+pub fn exclude_printers(printers: &[MinimalPrinterInfo], patterns: &[String]) -> Vec<MinimalPrinterInfo> {
+    let kept: Vec<MinimalPrinterInfo> = printers.iter()
+        .filter(|printer| {
+            let name = printer.printer_name.to_string_lossy();
+            match matching_exclusion(&name, patterns) {
+                Some(pattern) => {
+                    warn!("[{}] Excluding {:?}, which matches {:?}", "exclude_printers", name, pattern);
+                    false
+                }
+                None => true,
+            }
+        })
+        .cloned()
+        .collect();
+
+    info!("[{}] {} of {} printers left after exclusions", "exclude_printers", kept.len(), printers.len());
+
+    kept
+}
+
+// Drivers of printers that are software rather than a device: a deployment tool or a careless restore can leave
+// one on a WSD port, but there is nothing at the other end to give an address to
+pub const DEFAULT_VIRTUAL_DRIVERS: [&str; 6] = [
+    "Microsoft Print To PDF",
+    "Microsoft XPS Document Writer*",
+    "Microsoft Shared Fax Driver",
+    "Microsoft Software Printer Driver",
+    "Send to Microsoft OneNote*",
+    "Microsoft Fax*",
+];
+
+// Drop the printers whose driver name matches any of patterns, logging each one left out
+pub fn exclude_virtual_printers(printers: &[MinimalPrinterInfo], patterns: &[String]) -> Vec<MinimalPrinterInfo> {
+    let kept: Vec<MinimalPrinterInfo> = printers.iter()
+        .filter(|printer| {
+            let driver = printer.driver_name.to_string_lossy();
+            match matching_exclusion(&driver, patterns) {
+                Some(pattern) => {
+                    warn!("[{}] Leaving out {:?}: its driver {:?} matches {:?}, a software printer", "exclude_virtual_printers",
+                        printer.printer_name, driver, pattern);
+                    false
+                }
+                None => true,
+            }
+        })
+        .cloned()
+        .collect();
+
+    info!("[{}] {} of {} printers are not software printers", "exclude_virtual_printers", kept.len(), printers.len());
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sys::PRINTER_STATUS_OFFLINE;
+
+    fn printers() -> Vec<MinimalPrinterInfo> {
+        let mut offline = MinimalPrinterInfo::fabricated("Warehouse Label", "WSD-0a1b2c3e", "ZDesigner ZD420-203dpi ZPL");
+        offline.status = PRINTER_STATUS_OFFLINE;
+
+        vec![
+            MinimalPrinterInfo::fabricated("Front Desk HP", "WSD-0a1b2c3d", "HP LaserJet Pro M404"),
+            offline,
+            MinimalPrinterInfo::fabricated("Microsoft Print to PDF", "PORTPROMPT:", "Microsoft Print To PDF"),
+            MinimalPrinterInfo::fabricated("OneNote (Desktop)", "nul:", "Send to Microsoft OneNote 16 Driver"),
+        ]
+    }
+
+    fn names(printers: &[MinimalPrinterInfo]) -> Vec<String> {
+        printers.iter().map(|printer| printer.printer_name.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn matches_globs_ignoring_case() {
+        assert!(glob_matches("Front*", "front desk hp"));
+        assert!(glob_matches("*HP", "Front Desk HP"));
+        assert!(glob_matches("*desk*", "Front Desk HP"));
+        assert!(glob_matches("WSD-????", "wsd-0a1b"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "aXbYbZc"));
+        assert!(glob_matches("Ünïcode*", "üNÏCODE printer"));
+
+        assert!(!glob_matches("Front", "Front Desk HP"));
+        assert!(!glob_matches("WSD-????", "WSD-0a1"));
+        assert!(!glob_matches("a*b*c", "aXbYbZ"));
+        assert!(!glob_matches("?", ""));
+    }
+
+    #[test]
+    fn finds_the_first_matching_exclusion() {
+        let patterns = vec!["*Label".to_string(), "Warehouse*".to_string()];
+        assert_eq!(matching_exclusion("Warehouse Label", &patterns), Some("*Label"));
+        assert_eq!(matching_exclusion("Warehouse Laser", &patterns), Some("Warehouse*"));
+        assert_eq!(matching_exclusion("Front Desk HP", &patterns), None);
+        assert_eq!(matching_exclusion("Front Desk HP", &[]), None);
+    }
+
+    #[test]
+    fn filters_by_name_pattern() {
+        let pattern = Regex::new("^(Front|Warehouse)").unwrap();
+        assert_eq!(names(&filter_printers_by_name(&printers(), &pattern)), ["Front Desk HP", "Warehouse Label"]);
+    }
+
+    #[test]
+    fn filters_by_driver_substring_ignoring_case() {
+        assert_eq!(names(&filter_printers_by_driver(&printers(), "laserjet")), ["Front Desk HP"]);
+        assert_eq!(names(&filter_printers_by_driver(&printers(), "MICROSOFT")), ["Microsoft Print to PDF", "OneNote (Desktop)"]);
+        assert!(filter_printers_by_driver(&printers(), "Brother").is_empty());
+    }
+
+    #[test]
+    fn filters_by_state() {
+        assert_eq!(names(&filter_printers_by_state(&printers(), false)), ["Warehouse Label"]);
+        assert_eq!(filter_printers_by_state(&printers(), true).len(), 3);
+    }
+
+    #[test]
+    fn excludes_printers_by_name() {
+        let patterns = vec!["warehouse*".to_string(), "*PDF".to_string()];
+        assert_eq!(names(&exclude_printers(&printers(), &patterns)), ["Front Desk HP", "OneNote (Desktop)"]);
+        assert_eq!(exclude_printers(&printers(), &[]).len(), 4);
+    }
+
+    #[test]
+    fn leaves_out_software_printers_by_driver() {
+        let patterns: Vec<String> = DEFAULT_VIRTUAL_DRIVERS.iter().map(|pattern| pattern.to_string()).collect();
+        assert_eq!(names(&exclude_virtual_printers(&printers(), &patterns)), ["Front Desk HP", "Warehouse Label"]);
+    }
+}
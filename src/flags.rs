@@ -0,0 +1,111 @@
+use crate::sys::*;
+
+// PRINTER_INFO_2W.Status bits and the names they are reported under
+const PRINTER_STATUS_NAMES: [(DWORD, &str); 27] = [
+    (PRINTER_STATUS_PAUSED, "Paused"),
+    (PRINTER_STATUS_ERROR, "Error"),
+    (PRINTER_STATUS_PENDING_DELETION, "Pending Deletion"),
+    (PRINTER_STATUS_PAPER_JAM, "Paper Jam"),
+    (PRINTER_STATUS_PAPER_OUT, "Paper Out"),
+    (PRINTER_STATUS_MANUAL_FEED, "Manual Feed"),
+    (PRINTER_STATUS_PAPER_PROBLEM, "Paper Problem"),
+    (PRINTER_STATUS_OFFLINE, "Offline"),
+    (PRINTER_STATUS_IO_ACTIVE, "IO Active"),
+    (PRINTER_STATUS_BUSY, "Busy"),
+    (PRINTER_STATUS_PRINTING, "Printing"),
+    (PRINTER_STATUS_OUTPUT_BIN_FULL, "Output Bin Full"),
+    (PRINTER_STATUS_NOT_AVAILABLE, "Not Available"),
+    (PRINTER_STATUS_WAITING, "Waiting"),
+    (PRINTER_STATUS_PROCESSING, "Processing"),
+    (PRINTER_STATUS_INITIALIZING, "Initializing"),
+    (PRINTER_STATUS_WARMING_UP, "Warming Up"),
+    (PRINTER_STATUS_TONER_LOW, "Toner Low"),
+    (PRINTER_STATUS_NO_TONER, "No Toner"),
+    (PRINTER_STATUS_PAGE_PUNT, "Page Punt"),
+    (PRINTER_STATUS_USER_INTERVENTION, "User Intervention"),
+    (PRINTER_STATUS_OUT_OF_MEMORY, "Out of Memory"),
+    (PRINTER_STATUS_DOOR_OPEN, "Door Open"),
+    (PRINTER_STATUS_SERVER_UNKNOWN, "Server Unknown"),
+    (PRINTER_STATUS_POWER_SAVE, "Power Save"),
+    (PRINTER_STATUS_SERVER_OFFLINE, "Server Offline"),
+    (PRINTER_STATUS_DRIVER_UPDATE_NEEDED, "Driver Update Needed"),
+];
+
+// Map each PRINTER_STATUS_* bit set in status to its name. A ready printer has no bits set
+pub fn decode_printer_status(status: DWORD) -> Vec<&'static str> {
+    PRINTER_STATUS_NAMES.iter()
+        .filter(|(bit, _)| status & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+// PRINTER_INFO_2W.Attributes bits and the names they are reported under
+const PRINTER_ATTRIBUTE_NAMES: [(DWORD, &str); 22] = [
+    (PRINTER_ATTRIBUTE_QUEUED, "Queued"),
+    (PRINTER_ATTRIBUTE_DIRECT, "Direct"),
+    (PRINTER_ATTRIBUTE_DEFAULT, "Default"),
+    (PRINTER_ATTRIBUTE_SHARED, "Shared"),
+    (PRINTER_ATTRIBUTE_NETWORK, "Network"),
+    (PRINTER_ATTRIBUTE_HIDDEN, "Hidden"),
+    (PRINTER_ATTRIBUTE_LOCAL, "Local"),
+    (PRINTER_ATTRIBUTE_ENABLE_DEVQ, "Enable DevQ"),
+    (PRINTER_ATTRIBUTE_KEEPPRINTEDJOBS, "Keep Printed Jobs"),
+    (PRINTER_ATTRIBUTE_DO_COMPLETE_FIRST, "Do Complete First"),
+    (PRINTER_ATTRIBUTE_WORK_OFFLINE, "Work Offline"),
+    (PRINTER_ATTRIBUTE_ENABLE_BIDI, "Enable BiDi"),
+    (PRINTER_ATTRIBUTE_RAW_ONLY, "Raw Only"),
+    (PRINTER_ATTRIBUTE_PUBLISHED, "Published"),
+    (PRINTER_ATTRIBUTE_FAX, "Fax"),
+    (PRINTER_ATTRIBUTE_TS, "Terminal Services"),
+    (PRINTER_ATTRIBUTE_PUSHED_USER, "Pushed User"),
+    (PRINTER_ATTRIBUTE_PUSHED_MACHINE, "Pushed Machine"),
+    (PRINTER_ATTRIBUTE_MACHINE, "Machine"),
+    (PRINTER_ATTRIBUTE_FRIENDLY_NAME, "Friendly Name"),
+    (PRINTER_ATTRIBUTE_TS_GENERIC_DRIVER, "TS Generic Driver"),
+    (PRINTER_ATTRIBUTE_PER_USER, "Per User"),
+];
+
+// Map each PRINTER_ATTRIBUTE_* bit set in attrs to its name
+pub fn decode_printer_attributes(attrs: DWORD) -> Vec<&'static str> {
+    PRINTER_ATTRIBUTE_NAMES.iter()
+        .filter(|(bit, _)| attrs & bit != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_printer_has_no_status() {
+        assert!(decode_printer_status(0).is_empty());
+    }
+
+    #[test]
+    fn decodes_single_status_bits() {
+        assert_eq!(decode_printer_status(PRINTER_STATUS_OFFLINE), ["Offline"]);
+        assert_eq!(decode_printer_status(PRINTER_STATUS_PAPER_JAM), ["Paper Jam"]);
+        assert_eq!(decode_printer_status(PRINTER_STATUS_DRIVER_UPDATE_NEEDED), ["Driver Update Needed"]);
+    }
+
+    #[test]
+    fn decodes_status_combinations_in_bit_order() {
+        assert_eq!(decode_printer_status(PRINTER_STATUS_OFFLINE | PRINTER_STATUS_ERROR), ["Error", "Offline"]);
+        assert_eq!(
+            decode_printer_status(PRINTER_STATUS_PAPER_OUT | PRINTER_STATUS_TONER_LOW | PRINTER_STATUS_DOOR_OPEN),
+            ["Paper Out", "Toner Low", "Door Open"]
+        );
+    }
+
+    #[test]
+    fn ignores_unknown_status_bits() {
+        assert_eq!(decode_printer_status(0x8000_0000 | PRINTER_STATUS_PAUSED), ["Paused"]);
+    }
+
+    #[test]
+    fn decodes_attribute_combinations() {
+        let attributes = PRINTER_ATTRIBUTE_QUEUED | PRINTER_ATTRIBUTE_SHARED | PRINTER_ATTRIBUTE_ENABLE_BIDI;
+        assert_eq!(decode_printer_attributes(attributes), ["Queued", "Shared", "Enable BiDi"]);
+    }
+}
@@ -0,0 +1,244 @@
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::OsStringExt;
+use std::ptr::null_mut;
+
+use log::{info, warn};
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::{GUID, REFIID};
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE};
+use winapi::shared::winerror::{FAILED, RPC_E_CHANGED_MODE, SUCCEEDED};
+use winapi::shared::wtypes::{PROPERTYKEY, VT_LPWSTR, VT_VECTOR};
+use winapi::shared::wtypesbase::CLSCTX_INPROC_SERVER;
+use winapi::um::combaseapi::{CoCreateInstance, CoInitializeEx, CoUninitialize, PropVariantClear};
+use winapi::um::coml2api::STGM_READ;
+use winapi::um::objbase::COINIT_MULTITHREADED;
+use winapi::um::propidl::PROPVARIANT;
+use winapi::um::propsys::IPropertyStore;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winnt::{HRESULT, LPCWSTR, LPWSTR};
+
+use crate::discovery::host_from_url;
+use crate::wide::{to_wide_null, wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+// Function Discovery lives in functiondiscoveryapi.h, which winapi does not bind. Only the leading vtable
+// entries that are actually called are declared; the rest of each table is never touched
+const CLSID_FUNCTION_DISCOVERY: GUID = GUID {
+    Data1: 0xc72be2ec, Data2: 0x8e90, Data3: 0x452c, Data4: [0xb2, 0x9a, 0xab, 0x8f, 0xf1, 0xc0, 0x71, 0xfc],
+};
+const IID_IFUNCTION_DISCOVERY: GUID = GUID {
+    Data1: 0x4df99b70, Data2: 0xe148, Data3: 0x4432, Data4: [0xb0, 0x04, 0x4c, 0x9e, 0xeb, 0x53, 0x5a, 0x5e],
+};
+
+// Devices installed through PnP-X, which is where the OS keeps the WSD printers it has already discovered
+const FCTN_CATEGORY_PNP: &str = "Provider\\Microsoft.Base.PnP";
+
+// PKEY_PNPX_* from functiondiscoverykeys.h
+const PNPX_FMTID: GUID = GUID {
+    Data1: 0x656a3bb3, Data2: 0xecc0, Data3: 0x43fd, Data4: [0x84, 0x77, 0x4a, 0xe0, 0x40, 0x4a, 0x96, 0xcd],
+};
+const PKEY_PNPX_GLOBAL_IDENTITY: PROPERTYKEY = PROPERTYKEY { fmtid: PNPX_FMTID, pid: 0x1000 };
+const PKEY_PNPX_ID: PROPERTYKEY = PROPERTYKEY { fmtid: PNPX_FMTID, pid: 0x1001 };
+const PKEY_PNPX_IP_ADDRESS: PROPERTYKEY = PROPERTYKEY { fmtid: PNPX_FMTID, pid: 0x3009 };
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionDiscoveryVtbl {
+    parent: IUnknownVtbl,
+    GetInstanceCollection: unsafe extern "system" fn(
+        This: *mut IFunctionDiscovery,
+        pszCategory: LPCWSTR,
+        pszSubCategory: LPCWSTR,
+        fIncludeAllSubCategories: BOOL,
+        ppIFunctionInstanceCollection: *mut *mut IFunctionInstanceCollection,
+    ) -> HRESULT,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionDiscovery {
+    lpVtbl: *const IFunctionDiscoveryVtbl,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionInstanceCollectionVtbl {
+    parent: IUnknownVtbl,
+    GetCount: unsafe extern "system" fn(This: *mut IFunctionInstanceCollection, pdwCount: *mut DWORD) -> HRESULT,
+    Get: unsafe extern "system" fn(
+        This: *mut IFunctionInstanceCollection,
+        pszInstanceIdentity: LPCWSTR,
+        pdwIndex: *mut DWORD,
+        ppIFunctionInstance: *mut *mut IFunctionInstance,
+    ) -> HRESULT,
+    Item: unsafe extern "system" fn(
+        This: *mut IFunctionInstanceCollection,
+        dwIndex: DWORD,
+        ppIFunctionInstance: *mut *mut IFunctionInstance,
+    ) -> HRESULT,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionInstanceCollection {
+    lpVtbl: *const IFunctionInstanceCollectionVtbl,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionInstanceVtbl {
+    parent: IUnknownVtbl,
+    // IServiceProvider::QueryService
+    QueryService: unsafe extern "system" fn(
+        This: *mut IFunctionInstance,
+        guidService: REFIID,
+        riid: REFIID,
+        ppvObject: *mut *mut c_void,
+    ) -> HRESULT,
+    GetID: unsafe extern "system" fn(This: *mut IFunctionInstance, ppszCoMemIdentity: *mut LPWSTR) -> HRESULT,
+    GetProviderInstanceID: unsafe extern "system" fn(This: *mut IFunctionInstance, ppszCoMemProviderInstanceIdentity: *mut LPWSTR) -> HRESULT,
+    OpenPropertyStore: unsafe extern "system" fn(
+        This: *mut IFunctionInstance,
+        dwStgAccess: DWORD,
+        ppIPropertyStore: *mut *mut IPropertyStore,
+    ) -> HRESULT,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct IFunctionInstance {
+    lpVtbl: *const IFunctionInstanceVtbl,
+}
+
+// Owns one reference to a COM object and releases it when dropped
+struct ComPtr<T>(*mut T);
+
+impl<T> Drop for ComPtr<T> {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe {
+                (*(self.0 as *mut IUnknown)).Release();
+            }
+        }
+    }
+}
+
+// Keeps COM initialised on the calling thread for as long as it is alive
+struct ComApartment {
+    initialised: bool,
+}
+
+impl ComApartment {
+    fn enter() -> Option<ComApartment> {
+        let hr = unsafe { CoInitializeEx(null_mut(), COINIT_MULTITHREADED) };
+
+        // A thread that already chose a different apartment can still make calls, it just must not uninitialise
+        if hr == RPC_E_CHANGED_MODE {
+            return Some(ComApartment { initialised: false });
+        }
+        if FAILED(hr) {
+            warn!("[{}] CoInitializeEx failed with HRESULT {:#x}", "resolve_via_function_discovery", hr);
+            return None;
+        }
+        Some(ComApartment { initialised: true })
+    }
+}
+
+impl Drop for ComApartment {
+    fn drop(&mut self) {
+        if self.initialised {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+// Read a string or string-vector property, returning every string it holds
+fn string_values(store: &ComPtr<IPropertyStore>, key: &PROPERTYKEY) -> Vec<String> {
+    let mut value: PROPVARIANT = unsafe { std::mem::zeroed() };
+    let hr = unsafe { (*store.0).GetValue(key, &mut value) };
+    if FAILED(hr) {
+        return Vec::new();
+    }
+
+    let to_string = |ptr: LPWSTR| OsString::from_wide(&wide_str_from_raw_ptr(ptr, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned();
+
+    let vt = value.vt as u32;
+    let strings = unsafe {
+        if vt == VT_LPWSTR {
+            vec![to_string(*value.data.pwszVal())]
+        } else if vt == VT_VECTOR | VT_LPWSTR {
+            let array = value.data.calpwstr();
+            std::slice::from_raw_parts(array.pElems, array.cElems as usize).iter().map(|ptr| to_string(*ptr)).collect()
+        } else {
+            Vec::new()
+        }
+    };
+
+    unsafe { PropVariantClear(&mut value) };
+
+    strings
+}
+
+// Look the device with endpoint reference uuid up among the PnP-X devices Windows has already discovered,
+// and return the address Function Discovery recorded for it
+pub fn resolve_via_function_discovery(uuid: &str) -> Option<String> {
+    let uuid = uuid.trim_matches(|c| c == '{' || c == '}').to_lowercase();
+    let _apartment = ComApartment::enter()?;
+
+    let mut discovery: *mut c_void = null_mut();
+    let hr = unsafe {
+        CoCreateInstance(&CLSID_FUNCTION_DISCOVERY, null_mut(), CLSCTX_INPROC_SERVER, &IID_IFUNCTION_DISCOVERY, &mut discovery)
+    };
+    if FAILED(hr) {
+        warn!("[{}] Could not create the Function Discovery object: HRESULT {:#x}", "resolve_via_function_discovery", hr);
+        return None;
+    }
+    let discovery = ComPtr(discovery as *mut IFunctionDiscovery);
+
+    let category = to_wide_null(OsStr::new(FCTN_CATEGORY_PNP));
+    let mut collection: *mut IFunctionInstanceCollection = null_mut();
+    let hr = unsafe {
+        ((*(*discovery.0).lpVtbl).GetInstanceCollection)(discovery.0, category.as_ptr(), std::ptr::null(), FALSE, &mut collection)
+    };
+    if FAILED(hr) {
+        warn!("[{}] GetInstanceCollection failed: HRESULT {:#x}", "resolve_via_function_discovery", hr);
+        return None;
+    }
+    let collection = ComPtr(collection);
+
+    let mut count: DWORD = 0;
+    unsafe { ((*(*collection.0).lpVtbl).GetCount)(collection.0, &mut count) };
+    info!("[{}] Searching {} function instances for {}", "resolve_via_function_discovery", count, uuid);
+
+    for index in 0..count {
+        let mut instance: *mut IFunctionInstance = null_mut();
+        if !SUCCEEDED(unsafe { ((*(*collection.0).lpVtbl).Item)(collection.0, index, &mut instance) }) {
+            continue;
+        }
+        let instance = ComPtr(instance);
+
+        let mut store: *mut IPropertyStore = null_mut();
+        if !SUCCEEDED(unsafe { ((*(*instance.0).lpVtbl).OpenPropertyStore)(instance.0, STGM_READ, &mut store) }) {
+            continue;
+        }
+        let store = ComPtr(store);
+
+        let identities = [string_values(&store, &PKEY_PNPX_GLOBAL_IDENTITY), string_values(&store, &PKEY_PNPX_ID)].concat();
+        if !identities.iter().any(|identity| identity.to_lowercase().contains(&uuid)) {
+            continue;
+        }
+
+        // The address may be stored bare or as a URL, depending on the provider
+        let address = string_values(&store, &PKEY_PNPX_IP_ADDRESS).into_iter()
+            .map(|address| host_from_url(&address).unwrap_or(address))
+            .find(|address| !address.is_empty());
+
+        match &address {
+            Some(address) => info!("[{}] Function Discovery has {} at {}", "resolve_via_function_discovery", uuid, address),
+            None => warn!("[{}] Found {} but it has no IP address recorded", "resolve_via_function_discovery", uuid),
+        }
+        return address;
+    }
+
+    info!("[{}] {} is not among the discovered devices", "resolve_via_function_discovery", uuid);
+    None
+}
@@ -0,0 +1,90 @@
+// The IPv4 interfaces of this machine, for choosing the one WS-Discovery multicasts go out of on a print server
+// with more than one network
+
+use std::ffi::OsString;
+use std::mem::size_of;
+use std::net::Ipv4Addr;
+use std::os::windows::ffi::OsStringExt;
+use std::ptr::null_mut;
+
+use log::{info, warn};
+use serde::Serialize;
+use winapi::shared::ifdef::IfOperStatusUp;
+use winapi::shared::winerror::{ERROR_BUFFER_OVERFLOW, ERROR_NO_DATA, NO_ERROR};
+use winapi::shared::ws2def::{AF_INET, SOCKADDR_IN};
+use winapi::um::iphlpapi::GetAdaptersAddresses;
+use winapi::um::iptypes::{GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER, GAA_FLAG_SKIP_MULTICAST, IP_ADAPTER_ADDRESSES};
+
+use crate::error::format_error_code;
+use crate::wide::{wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NetworkInterface {
+    // The name shown in Network Connections, such as "Ethernet 2"
+    pub name: String,
+    pub description: String,
+    pub addresses: Vec<Ipv4Addr>,
+    pub up: bool,
+}
+
+fn lossy_wide(ptr: *const u16) -> String {
+    OsString::from_wide(&wide_str_from_raw_ptr(ptr, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned()
+}
+
+// Every adapter with an IPv4 address, from GetAdaptersAddresses. The list can grow between sizing the buffer
+// and filling it, so a buffer that turns out too small is grown and tried again
+pub fn list_interfaces() -> Option<Vec<NetworkInterface>> {
+    let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+    let mut size: u32 = 0;
+    // u64s keep the buffer aligned for the pointers in the adapter list
+    let mut buffer: Vec<u64> = Vec::new();
+
+    loop {
+        let adapters = if buffer.is_empty() { null_mut() } else { buffer.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES };
+        let result = unsafe { GetAdaptersAddresses(AF_INET as u32, flags, null_mut(), adapters, &mut size) };
+
+        match result {
+            NO_ERROR if !buffer.is_empty() => break,
+            NO_ERROR | ERROR_NO_DATA => return Some(Vec::new()),
+            ERROR_BUFFER_OVERFLOW => buffer = vec![0u64; (size as usize).div_ceil(size_of::<u64>())],
+            error_code => {
+                warn!("[{}] GetAdaptersAddresses failed: {}", "list_interfaces", format_error_code(error_code).unwrap_or_default());
+                return None;
+            }
+        }
+    }
+
+    let mut interfaces = Vec::new();
+    let mut adapter = buffer.as_ptr() as *const IP_ADAPTER_ADDRESSES;
+
+    while !adapter.is_null() {
+        let current = unsafe { &*adapter };
+        let mut addresses = Vec::new();
+
+        let mut unicast = current.FirstUnicastAddress;
+        while !unicast.is_null() {
+            let address = unsafe { &*unicast };
+            let sockaddr = address.Address.lpSockaddr;
+            // Only IPv4 was asked for, but the family is checked before reading the address as one
+            if !sockaddr.is_null() && unsafe { (*sockaddr).sa_family } == AF_INET as u16 {
+                let sockaddr = unsafe { &*(sockaddr as *const SOCKADDR_IN) };
+                addresses.push(Ipv4Addr::from(u32::from_be(unsafe { *sockaddr.sin_addr.S_un.S_addr() })));
+            }
+            unicast = address.Next;
+        }
+
+        if !addresses.is_empty() {
+            interfaces.push(NetworkInterface {
+                name: lossy_wide(current.FriendlyName),
+                description: lossy_wide(current.Description),
+                addresses,
+                up: current.OperStatus == IfOperStatusUp,
+            });
+        }
+        adapter = current.Next;
+    }
+
+    info!("[{}] Found {} interfaces with IPv4 addresses", "list_interfaces", interfaces.len());
+
+    Some(interfaces)
+}
@@ -0,0 +1,264 @@
+// An append-only record of every change convert makes to the spooler, one JSON object per line, with enough in
+// each entry to reverse it. Unlike a backup, which covers every printer a run might touch, the journal only holds
+// what was actually changed, so undo can take back just the most recent run
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::convert::TcpipPortConfig;
+use crate::error::PrinterError;
+
+// Where convert keeps the journal, in the backup directory
+pub const JOURNAL_FILE: &str = "wsd_to_ip-journal.jsonl";
+
+// One change to the spooler and what it replaced
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Mutation {
+    // A Standard TCP/IP port was created; undone by deleting it
+    PortCreated { port: String, address: String },
+    // An existing Standard TCP/IP port was pointed at another address or given other settings; undone by putting
+    // the old ones back
+    PortReconfigured { port: String, old_address: String, old_config: TcpipPortConfig, new_address: String },
+    // A printer was moved from old_port to new_port; undone by moving it back. raw_printer is its name as the
+    // spooler gave it when that is not valid Unicode, see raw_name
+    PrinterMoved {
+        printer: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        raw_printer: Option<Vec<u16>>,
+        old_port: String,
+        new_port: String,
+    },
+    // An old port nothing used any more was deleted. old_address and old_config are its Standard TCP/IP settings,
+    // read just before; a port of another monitor has none and cannot be created again, see is_reversible
+    PortDeleted {
+        port: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old_address: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old_config: Option<TcpipPortConfig>,
+    },
+    // Every change of an earlier run was undone, so the next undo goes to the run before it
+    RunUndone { undone_run: String },
+}
+
+impl Mutation {
+    // Whether undo can take this change back. A deleted port can only be created again from the settings recorded
+    // with it; the WSD ports cleanup deletes are made by the WSD monitor alone, when it rediscovers their device
+    pub fn is_reversible(&self) -> bool {
+        match self {
+            Mutation::PortDeleted { old_address, old_config, .. } => old_address.is_some() && old_config.is_some(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    // Id shared by every change one convert run made
+    pub run: String,
+    pub timestamp: String,
+    // The print server the change was made on, or None for this machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    #[serde(flatten)]
+    pub mutation: Mutation,
+}
+
+// The journal a run appends its changes to
+pub struct Journal {
+    path: PathBuf,
+    run: String,
+    server: Option<String>,
+}
+
+impl Journal {
+    // Start a new run in the journal at path, creating it if need be. Failing here means nothing has been changed
+    // yet, unlike a failure to record a change that has already been made
+    pub fn open(path: &Path, server: Option<&str>) -> Result<Journal, PrinterError> {
+        OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| PrinterError::FileFailed { path: path.display().to_string(), reason: e.to_string() })?;
+
+        let run = format!("{:08x}", rand::thread_rng().gen::<u32>());
+        info!("[{}] Recording run {} in {}", "Journal::open", run, path.display());
+
+        Ok(Journal { path: path.to_path_buf(), run, server: server.map(str::to_string) })
+    }
+
+    // Append one change. The file is opened for each entry so that everything recorded before a crash is on disk
+    pub fn record(&self, mutation: Mutation) {
+        let entry = JournalEntry {
+            run: self.run.clone(),
+            timestamp: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            server: self.server.clone(),
+            mutation,
+        };
+
+        let result = serde_json::to_string(&entry).map_err(|e| e.to_string()).and_then(|line| {
+            OpenOptions::new().append(true).open(&self.path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|e| e.to_string())
+        });
+
+        // The change has been made either way, so this can only be reported
+        if let Err(e) = result {
+            warn!("[{}] Could not record {:?} in {}: {}", "Journal::record", entry.mutation, self.path.display(), e);
+            eprintln!("Warning: could not record a change in {}, undo will not know about it: {}", self.path.display(), e);
+        }
+    }
+}
+
+// Read every entry in the journal at path. A line that does not parse, such as one cut short by a crash, is skipped
+pub fn load_journal(path: &Path) -> Result<Vec<JournalEntry>, PrinterError> {
+    let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+    let file = File::open(path).map_err(|e| file_error(e.to_string()))?;
+    let mut entries = Vec::new();
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| file_error(e.to_string()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("[{}] Skipping line {} of {}: {}", "load_journal", index + 1, path.display(), e),
+        }
+    }
+
+    Ok(entries)
+}
+
+// The changes of the newest run that has not been undone, in the order they were made
+pub fn last_run(entries: &[JournalEntry]) -> Vec<&JournalEntry> {
+    let undone: Vec<&str> = entries.iter()
+        .filter_map(|entry| match &entry.mutation {
+            Mutation::RunUndone { undone_run } => Some(undone_run.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    let is_change = |entry: &JournalEntry| !matches!(entry.mutation, Mutation::RunUndone { .. });
+    let Some(run) = entries.iter().rev()
+        .find(|entry| is_change(entry) && !undone.contains(&entry.run.as_str()))
+        .map(|entry| entry.run.as_str()) else {
+        return Vec::new();
+    };
+
+    entries.iter().filter(|entry| entry.run == run && is_change(entry)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::PortProtocol;
+    use crate::printers::{name_from_raw, raw_name};
+    use crate::printers::tests::invalid_name;
+
+    #[test]
+    fn keeps_printer_names_that_are_not_valid_unicode() {
+        let name = invalid_name();
+        let entry = JournalEntry {
+            run: "0a1b2c3d".to_string(),
+            timestamp: String::new(),
+            server: None,
+            mutation: Mutation::PrinterMoved {
+                printer: name.to_string_lossy().into_owned(),
+                raw_printer: raw_name(&name),
+                old_port: "WSD-0a1b2c3d".to_string(),
+                new_port: "IP_10.0.0.5".to_string(),
+            },
+        };
+
+        let line = serde_json::to_string(&entry).unwrap();
+        let entry: JournalEntry = serde_json::from_str(&line).unwrap();
+        let Mutation::PrinterMoved { printer, raw_printer, .. } = entry.mutation else {
+            panic!("{}", line);
+        };
+        assert_eq!(name_from_raw(&printer, raw_printer.as_deref()), name);
+    }
+
+    fn port_deleted(port: &str, old_config: Option<(&str, TcpipPortConfig)>) -> Mutation {
+        Mutation::PortDeleted {
+            port: port.to_string(),
+            old_address: old_config.as_ref().map(|(address, _)| address.to_string()),
+            old_config: old_config.map(|(_, config)| config),
+        }
+    }
+
+    #[test]
+    fn only_deleted_ports_with_recorded_settings_can_be_undone() {
+        assert!(!port_deleted("WSD-0a1b2c3d", None).is_reversible());
+        assert!(!port_deleted("WSDPORT_3", None).is_reversible());
+        assert!(!port_deleted("IP_10.0.0.5", None).is_reversible());
+        assert!(port_deleted("IP_10.0.0.5", Some(("10.0.0.5", TcpipPortConfig::default()))).is_reversible());
+        assert!(Mutation::PortCreated { port: "IP_10.0.0.5".to_string(), address: "10.0.0.5".to_string() }.is_reversible());
+        assert!(Mutation::PrinterMoved {
+            printer: "Front desk".to_string(),
+            raw_printer: None,
+            old_port: "WSD-0a1b2c3d".to_string(),
+            new_port: "IP_10.0.0.5".to_string(),
+        }.is_reversible());
+    }
+
+    fn entry(run: &str, mutation: Mutation) -> JournalEntry {
+        JournalEntry { run: run.to_string(), timestamp: String::new(), server: None, mutation }
+    }
+
+    #[test]
+    fn the_last_run_is_the_newest_one_not_undone() {
+        let created = |port: &str| Mutation::PortCreated { port: port.to_string(), address: "10.0.0.5".to_string() };
+        let mut entries = vec![entry("first", created("IP_10.0.0.5")), entry("second", created("IP_10.0.0.6")), entry("second", created("IP_10.0.0.7"))];
+        assert_eq!(last_run(&entries).len(), 2);
+
+        entries.push(entry("undo", Mutation::RunUndone { undone_run: "second".to_string() }));
+        let run = last_run(&entries);
+        assert_eq!(run.len(), 1);
+        assert_eq!(run[0].run, "first");
+
+        entries.push(entry("undo", Mutation::RunUndone { undone_run: "first".to_string() }));
+        assert!(last_run(&entries).is_empty());
+    }
+
+    #[test]
+    fn reads_entries_written_without_a_raw_name() {
+        let line = r#"{"run":"0a1b2c3d","timestamp":"","action":"printer_moved","printer":"Front desk","old_port":"WSD-0a1b2c3d","new_port":"IP_10.0.0.5"}"#;
+        let entry: JournalEntry = serde_json::from_str(line).unwrap();
+        assert!(matches!(entry.mutation, Mutation::PrinterMoved { raw_printer: None, .. }));
+        assert!(!serde_json::to_string(&entry).unwrap().contains("raw_printer"));
+    }
+
+    #[test]
+    fn keeps_the_settings_of_a_deleted_port() {
+        let config = TcpipPortConfig {
+            protocol: PortProtocol::Lpr,
+            port_number: 515,
+            lpr_queue: Some("queue1".to_string()),
+            snmp_community: None,
+        };
+        let line = serde_json::to_string(&entry("0a1b2c3d", port_deleted("Front desk", Some(("printer.example", config))))).unwrap();
+
+        let entry: JournalEntry = serde_json::from_str(&line).unwrap();
+        let Mutation::PortDeleted { port, old_address: Some(address), old_config: Some(config) } = entry.mutation else {
+            panic!("{}", line);
+        };
+        assert_eq!((port.as_str(), address.as_str()), ("Front desk", "printer.example"));
+        assert_eq!((config.protocol, config.port_number, config.lpr_queue.as_deref(), config.snmp_community), (PortProtocol::Lpr, 515, Some("queue1"), None));
+    }
+
+    #[test]
+    fn cannot_undo_a_port_deleted_before_its_settings_were_recorded() {
+        let line = r#"{"run":"0a1b2c3d","timestamp":"","action":"port_deleted","port":"IP_10.0.0.5"}"#;
+        let entry: JournalEntry = serde_json::from_str(line).unwrap();
+        assert!(matches!(entry.mutation, Mutation::PortDeleted { old_address: None, old_config: None, .. }));
+        assert!(!entry.mutation.is_reversible());
+    }
+}
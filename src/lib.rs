@@ -0,0 +1,74 @@
+// Library half of wsd_to_ip: enumerate printers, find the ones on WSD ports and move them to Standard TCP/IP ports.
+// Everything that calls Win32 only builds on Windows; the parsing, filtering and planning logic builds everywhere
+
+extern crate log;
+
+mod convert;
+#[cfg(windows)]
+mod drivers;
+#[cfg(windows)]
+mod elevation;
+mod filter;
+mod flags;
+mod ports;
+mod printers;
+mod sys;
+#[cfg(windows)]
+mod wide;
+
+#[cfg(windows)]
+pub mod arp;
+pub mod backup;
+pub mod cache;
+pub mod correlation;
+pub mod discovery;
+#[cfg(windows)]
+pub mod dns;
+pub mod emit;
+pub mod error;
+#[cfg(windows)]
+pub mod event_log;
+#[cfg(windows)]
+pub mod function_discovery;
+#[cfg(windows)]
+pub mod interfaces;
+pub mod journal;
+pub mod mapping;
+pub mod mdns;
+pub mod metadata;
+pub mod plan;
+pub mod reachability;
+#[cfg(windows)]
+pub mod registry;
+pub mod report;
+pub mod snmp;
+#[cfg(windows)]
+pub mod spooler;
+pub mod spooler_api;
+
+pub use convert::{check_host_address, check_port_name_template, render_port_name, template_uses_model, validate_port_name, DEFAULT_PORT_NAME_TEMPLATE};
+pub use convert::{address_from_ip_port_name, ip_port_name, model_port_name, PortProtocol, TcpipPortConfig, DEFAULT_LPR_PORT_NUMBER, DEFAULT_RAW_PORT_NUMBER, DEFAULT_SNMP_COMMUNITY, MAX_HOST_ADDRESS_LEN};
+#[cfg(windows)]
+pub use convert::{convert_printer_to_ip, create_tcpip_port, create_tcpip_port_on_server, create_tcpip_port_with_config, delete_port};
+#[cfg(windows)]
+pub use convert::{get_tcpip_port_config, reconfigure_tcpip_port};
+#[cfg(windows)]
+pub use convert::{print_test_page, set_printer_port, verify_printer_port};
+#[cfg(windows)]
+pub use drivers::{DriverInfo, attach_driver_versions, get_driver_info, get_installed_drivers, missing_drivers};
+#[cfg(windows)]
+pub use elevation::{is_elevated, relaunch_elevated};
+pub use error::PrinterError;
+pub use filter::{filter_printers_by_driver, filter_printers_by_name, filter_printers_by_state};
+pub use filter::{exclude_printers, exclude_virtual_printers, glob_matches, matching_exclusion, DEFAULT_VIRTUAL_DRIVERS};
+pub use flags::{decode_printer_attributes, decode_printer_status};
+pub use ports::{PortInfo, TCPIP_MONITOR_NAME};
+#[cfg(windows)]
+pub use ports::{get_all_ports, get_print_monitors};
+pub use printers::{EnumScope, MinimalPrinterInfo, get_wsd_printers, get_wsd_printers_refs, is_ip_port, is_wsd_port, name_from_raw, raw_name};
+#[cfg(windows)]
+pub use printers::{get_all_printers, get_all_printers_on_server, get_printers_with_retry, get_printers_with_scope, iter_printers, PrinterIter};
+pub use printers::RetryPolicy;
+pub use printers::{PrinterKind, PrinterSummary};
+#[cfg(windows)]
+pub use printers::{get_default_printer, get_printers_level4, get_printers_level5, set_default_printer};
@@ -0,0 +1,224 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{Log, Metadata, Record};
+use serde::Serialize;
+use simplelog::*;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use time::macros::format_description;
+
+use wsd_to_ip::correlation::current_correlation_id;
+
+use crate::cli::{Cli, LogFormat};
+
+// File name of the log when no --log-file is given
+const LOG_FILE: &str = "wsd_to_ip.log";
+
+// How many rolled logs (wsd_to_ip.log.1 .. wsd_to_ip.log.N) are kept
+const MAX_ROTATED_LOGS: u32 = 5;
+
+fn rotated_path(path: &Path, index: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", index));
+    PathBuf::from(rotated)
+}
+
+// Roll path to path.1 (and path.1 to path.2, ...) once it has grown past max_bytes
+pub fn rotate_if_needed(path: &Path, max_bytes: u64) -> io::Result<()> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    if size < max_bytes {
+        return Ok(());
+    }
+
+    // Shift the older logs up by one, dropping the oldest once MAX_ROTATED_LOGS is reached
+    for index in (1..MAX_ROTATED_LOGS).rev() {
+        let from = rotated_path(path, index);
+        if from.exists() {
+            fs::rename(&from, rotated_path(path, index + 1))?;
+        }
+    }
+
+    fs::rename(path, rotated_path(path, 1))
+}
+
+// The log goes next to the executable so it is easy to find regardless of the working directory
+// (scheduled tasks start in System32)
+fn exe_dir_log_path() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join(LOG_FILE))
+}
+
+// Fallback for when the executable lives somewhere we cannot write, such as Program Files
+fn program_data_log_path() -> Option<PathBuf> {
+    let program_data = std::env::var_os("PROGRAMDATA")?;
+    Some(PathBuf::from(program_data).join("wsd_to_ip").join(LOG_FILE))
+}
+
+// Open the log for appending so previous runs stay available for auditing
+fn open_log_file(path: &Path, max_bytes: u64) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    if let Err(e) = rotate_if_needed(path, max_bytes) {
+        eprintln!("Warning: could not rotate {}: {}", path.display(), e);
+    }
+
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+// Use --log-file if given, otherwise the first default location that can actually be opened
+fn open_default_or_requested_log(requested: Option<&Path>, max_bytes: u64) -> io::Result<File> {
+    if let Some(path) = requested {
+        return open_log_file(path, max_bytes);
+    }
+
+    let candidates = [exe_dir_log_path(), program_data_log_path(), Some(PathBuf::from(LOG_FILE))];
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no usable log location");
+
+    for path in candidates.iter().flatten() {
+        match open_log_file(path, max_bytes) {
+            Ok(file) => return Ok(file),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+// Puts the correlation id of the printer the logging thread is working on in front of each message, so the lines
+// about one printer can be picked out of the interleaved output of parallel discovery
+struct CorrelatedLogger(Box<dyn SharedLogger>);
+
+impl Log for CorrelatedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        match current_correlation_id() {
+            None => self.0.log(record),
+            Some(id) => self.0.log(&Record::builder()
+                .args(format_args!("<{}> {}", id, record.args()))
+                .metadata(record.metadata().clone())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build()),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush()
+    }
+}
+
+impl SharedLogger for CorrelatedLogger {
+    fn level(&self) -> LevelFilter {
+        self.0.level()
+    }
+
+    fn config(&self) -> Option<&Config> {
+        self.0.config()
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+// One line of --log-format json
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    ts: String,
+    level: &'a str,
+    target: &'a str,
+    msg: String,
+    // Kept out of msg so pipelines can filter on it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    correlation_id: Option<String>,
+}
+
+// Writes each record as a JSON object on its own line, for log pipelines that ingest NDJSON
+struct JsonLogger {
+    level: LevelFilter,
+    file: Mutex<File>,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = JsonRecord {
+            ts: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            level: record.level().as_str(),
+            target: record.target(),
+            msg: record.args().to_string(),
+            correlation_id: current_correlation_id(),
+        };
+
+        if let Ok(json) = serde_json::to_string(&line) {
+            let mut file = self.file.lock().unwrap();
+            let _ = writeln!(file, "{}", json);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().flush();
+    }
+}
+
+impl SharedLogger for JsonLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+// Log to wsd_to_ip.log (or --log-file) at --log-level, and to the console as well with --verbose
+pub fn init_logging(cli: &Cli) {
+    // With --quiet the log is all a failed run leaves behind, so errors are kept even at --log-level off
+    let level = if cli.quiet { cli.log_level.max(LevelFilter::Error) } else { cli.log_level };
+
+    let config = ConfigBuilder::new()
+        .set_time_format_custom(format_description!("[hour]:[minute]:[second].[subsecond]"))
+        .build();
+
+    let max_bytes = cli.log_max_size.saturating_mul(1024 * 1024);
+    let log_file = open_default_or_requested_log(cli.log_file.as_deref(), max_bytes).expect("Could not open log file");
+
+    let mut loggers: Vec<Box<dyn SharedLogger>> = Vec::new();
+
+    // The JSON logger records the correlation id as a field of its own; the human formats get it as a prefix
+    match cli.log_format {
+        LogFormat::Text => loggers.push(Box::new(CorrelatedLogger(WriteLogger::new(level, config.clone(), log_file)))),
+        LogFormat::Json => loggers.push(Box::new(JsonLogger { level, file: Mutex::new(log_file) })),
+    }
+
+    if cli.verbose {
+        loggers.push(Box::new(CorrelatedLogger(TermLogger::new(level, config, TerminalMode::Mixed, ColorChoice::Auto))));
+    }
+
+    let _ = CombinedLogger::init(loggers);
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use log::info;
+
+use crate::error::PrinterError;
+
+// Printer name -> address to convert it to, as supplied by the operator
+pub type IpMap = HashMap<String, String>;
+
+// Printer name -> MAC address of the device, such as from a DHCP reservation export
+pub type MacMap = HashMap<String, String>;
+
+fn file_error(path: &Path, reason: String) -> PrinterError {
+    PrinterError::FileFailed { path: path.display().to_string(), reason }
+}
+
+// Read a flat printer name -> string map. Files ending in .toml are read as TOML, anything else as JSON
+fn read_name_map(path: &Path) -> Result<HashMap<String, String>, PrinterError> {
+    let contents = fs::read_to_string(path).map_err(|e| file_error(path, e.to_string()))?;
+
+    let is_toml = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+    if is_toml {
+        toml::from_str(&contents).map_err(|e| file_error(path, e.to_string()))
+    } else {
+        serde_json::from_str(&contents).map_err(|e| file_error(path, e.to_string()))
+    }
+}
+
+// Load a manual printer-to-address map
+pub fn load_ip_map(path: &Path) -> Result<IpMap, PrinterError> {
+    let mut map = read_name_map(path)?;
+
+    // A typo here would otherwise become a garbage port that has to be cleaned up by hand
+    for (printer, address) in map.iter_mut() {
+        let ip: IpAddr = address.trim().parse()
+            .map_err(|_| file_error(path, format!("{}: {:?} is not a valid IPv4 or IPv6 address", printer, address)))?;
+        *address = ip.to_string();
+    }
+
+    info!("[{}] Loaded {} printer mappings from {}", "load_ip_map", map.len(), path.display());
+
+    Ok(map)
+}
+
+// The six bytes of a MAC address written as aa:bb:cc:dd:ee:ff, aa-bb-cc-dd-ee-ff, aabb.ccdd.eeff or aabbccddeeff,
+// in either case
+pub fn parse_mac(mac: &str) -> Option<[u8; 6]> {
+    let digits: String = mac.trim().chars().filter(|c| !matches!(c, ':' | '-' | '.')).collect();
+    if digits.len() != 12 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let mut bytes = [0u8; 6];
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[index * 2..index * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+// A MAC address the way Windows shows them, such as in arp -a: aa-bb-cc-dd-ee-ff
+pub fn format_mac(bytes: &[u8; 6]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join("-")
+}
+
+// Load a printer-to-MAC map, for finding devices in the ARP cache when WS-Discovery cannot reach them
+pub fn load_mac_map(path: &Path) -> Result<MacMap, PrinterError> {
+    let mut map = read_name_map(path)?;
+
+    for (printer, mac) in map.iter_mut() {
+        let bytes = parse_mac(mac).ok_or_else(|| file_error(path, format!("{}: {:?} is not a valid MAC address", printer, mac)))?;
+        *mac = format_mac(&bytes);
+    }
+
+    info!("[{}] Loaded {} printer MAC addresses from {}", "load_mac_map", map.len(), path.display());
+
+    Ok(map)
+}
+
+// The entries of a file listing one thing per line, with their line numbers. Blank lines and lines starting with #
+// are left out
+fn read_list(path: &Path) -> Result<Vec<(usize, String)>, PrinterError> {
+    let contents = fs::read_to_string(path).map_err(|e| file_error(path, e.to_string()))?;
+
+    Ok(contents.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, line.to_string()))
+        .collect())
+}
+
+// Load a list of print servers, one per line, for converting several of them in one run. Names are given the
+// leading \\ if they lack it
+pub fn load_server_list(path: &Path) -> Result<Vec<String>, PrinterError> {
+    let mut servers: Vec<String> = Vec::new();
+    for (number, line) in read_list(path)? {
+        let host = line.trim_start_matches('\\');
+        if host.is_empty() || host.contains(['\\', ' ', '\t']) {
+            return Err(file_error(path, format!("line {}: {:?} is not a server name", number, line)));
+        }
+
+        let server = format!(r"\\{}", host);
+        if !servers.iter().any(|known| known.eq_ignore_ascii_case(&server)) {
+            servers.push(server);
+        }
+    }
+
+    if servers.is_empty() {
+        return Err(file_error(path, "no servers listed".to_string()));
+    }
+
+    info!("[{}] Loaded {} servers from {}", "load_server_list", servers.len(), path.display());
+
+    Ok(servers)
+}
+
+// Load a deny-list of printers convert must never touch, one name or glob pattern per line
+pub fn load_exclude_list(path: &Path) -> Result<Vec<String>, PrinterError> {
+    let patterns: Vec<String> = read_list(path)?.into_iter().map(|(_, pattern)| pattern).collect();
+
+    info!("[{}] Loaded {} exclusions from {}", "load_exclude_list", patterns.len(), path.display());
+
+    Ok(patterns)
+}
@@ -0,0 +1,263 @@
+// Find printers through multicast DNS, the way AirPrint clients do, for devices that advertise _ipp._tcp.local
+// but do not answer WS-Discovery
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::discovery::{discovery_cancelled, multicast_socket};
+
+const MDNS_ADDR: &str = "224.0.0.251:5353";
+
+// Service type AirPrint and other IPP printers register under
+const IPP_SERVICE: &str = "_ipp._tcp.local";
+
+// How long to collect answers to each query
+pub const DEFAULT_MDNS_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How often a wait for answers wakes up to check whether discovery was cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// Top bit of the question class, asking responders to answer straight back rather than to the group
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+
+// A DNS name as its labels, since instance names may themselves contain dots
+type Name = Vec<String>;
+
+fn name_from(text: &str) -> Name {
+    text.split('.').map(str::to_string).collect()
+}
+
+fn same_name(a: &Name, b: &Name) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.eq_ignore_ascii_case(b))
+}
+
+#[derive(Debug)]
+enum RecordData {
+    Ptr(Name),
+    Srv(Name),
+    A(Ipv4Addr),
+    Other,
+}
+
+#[derive(Debug)]
+struct Record {
+    name: Name,
+    data: RecordData,
+}
+
+fn build_query(name: &Name, record_type: u16) -> Vec<u8> {
+    // Id 0 and no flags: a standard query. One question, nothing else
+    let mut query = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&(CLASS_IN | CLASS_UNICAST_RESPONSE).to_be_bytes());
+    query
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*message.get(offset)?, *message.get(offset + 1)?]))
+}
+
+// Read the possibly compressed name at offset, returning it and the offset just past it
+fn read_name(message: &[u8], mut offset: usize) -> Option<(Name, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Each pointer has to go backwards, which also rules out loops
+    let mut limit = offset;
+
+    loop {
+        let length = *message.get(offset)? as usize;
+        match length & 0xc0 {
+            0x00 if length == 0 => {
+                return Some((labels, end.unwrap_or(offset + 1)));
+            }
+            0x00 => {
+                let label = message.get(offset + 1..offset + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+            0xc0 => {
+                let target = (read_u16(message, offset)? & 0x3fff) as usize;
+                if target >= limit {
+                    return None;
+                }
+                end.get_or_insert(offset + 2);
+                limit = target;
+                offset = target;
+            }
+            _ => return None,
+        }
+    }
+}
+
+// Every resource record in a response, from the answer, authority and additional sections alike: responders put
+// the SRV and A records that go with a PTR in whichever section they like
+fn parse_response(message: &[u8]) -> Option<Vec<Record>> {
+    let flags = read_u16(message, 2)?;
+    if flags & 0x8000 == 0 {
+        return None;
+    }
+
+    let questions = read_u16(message, 4)?;
+    let records = read_u16(message, 6)? as usize + read_u16(message, 8)? as usize + read_u16(message, 10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (_, next) = read_name(message, offset)?;
+        offset = next + 4;
+    }
+
+    let mut parsed = Vec::with_capacity(records);
+    for _ in 0..records {
+        let (name, next) = read_name(message, offset)?;
+        let record_type = read_u16(message, next)?;
+        let length = read_u16(message, next + 8)? as usize;
+        let start = next + 10;
+        let rdata = message.get(start..start + length)?;
+
+        let data = match record_type {
+            TYPE_PTR => RecordData::Ptr(read_name(message, start)?.0),
+            // Priority, weight and port come before the target
+            TYPE_SRV => RecordData::Srv(read_name(message, start + 6)?.0),
+            TYPE_A if length == 4 => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+            _ => RecordData::Other,
+        };
+        parsed.push(Record { name, data });
+        offset = start + length;
+    }
+
+    Some(parsed)
+}
+
+// Ask the local link for name and gather what comes back until timeout. The query goes out from an ephemeral
+// port as a one-shot query, so it does not fight the DNS Client service for 5353, and responders answer it
+// directly
+fn query(name: &Name, record_type: u16, timeout: Duration) -> Vec<Record> {
+    let socket = match multicast_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("[{}] Could not open a UDP socket: {}", "mdns::query", e);
+            return Vec::new();
+        }
+    };
+
+    if let Err(e) = socket.send_to(&build_query(name, record_type), MDNS_ADDR) {
+        warn!("[{}] Could not send a query for {}: {}", "mdns::query", name.join("."), e);
+        return Vec::new();
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut records = Vec::new();
+    let mut buffer = vec![0u8; 9000];
+
+    // Several devices may answer, so keep listening for the whole timeout rather than stopping at the first
+    while !discovery_cancelled() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || socket.set_read_timeout(Some(remaining.min(CANCEL_POLL_INTERVAL))).is_err() {
+            break;
+        }
+
+        match socket.recv_from(&mut buffer) {
+            Ok((len, from)) => match parse_response(&buffer[..len]) {
+                Some(answer) => records.extend(answer),
+                None => info!("[{}] Ignoring a malformed response from {}", "mdns::query", from),
+            },
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+            Err(e) => {
+                warn!("[{}] Receiving failed: {}", "mdns::query", e);
+                break;
+            }
+        }
+    }
+
+    records
+}
+
+// Letters and digits only, lowercased, so "HP LaserJet M404 [A1B2C3]" and "HP_LaserJet_M404 (A1B2C3)" compare equal
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+// The advertised instance that is printer_name: the one with the same name, or failing that the only one whose
+// name contains or is contained in it
+fn matching_instance<'a>(instances: &'a [Name], printer_name: &str) -> Option<&'a Name> {
+    let wanted = normalize(printer_name);
+    if wanted.is_empty() {
+        return None;
+    }
+    let label = |instance: &Name| normalize(instance.first().map(String::as_str).unwrap_or_default());
+
+    if let Some(instance) = instances.iter().find(|instance| label(instance) == wanted) {
+        return Some(instance);
+    }
+
+    let partial: Vec<&Name> = instances.iter()
+        .filter(|instance| {
+            let label = label(instance);
+            !label.is_empty() && (label.contains(&wanted) || wanted.contains(&label))
+        })
+        .collect();
+    match partial.as_slice() {
+        [instance] => Some(instance),
+        _ => None,
+    }
+}
+
+// Find the IPv4 address of the IPP printer advertised under the same name as printer_name on the local link
+pub fn resolve_via_mdns(printer_name: &str, timeout: Duration) -> Option<String> {
+    let service = name_from(IPP_SERVICE);
+    let mut records = query(&service, TYPE_PTR, timeout);
+
+    let mut instances: Vec<Name> = Vec::new();
+    for record in &records {
+        if let RecordData::Ptr(instance) = &record.data {
+            if same_name(&record.name, &service) && !instances.iter().any(|known| same_name(known, instance)) {
+                instances.push(instance.clone());
+            }
+        }
+    }
+    info!("[{}] {} IPP printers answered on mDNS", "resolve_via_mdns", instances.len());
+
+    let Some(instance) = matching_instance(&instances, printer_name).cloned() else {
+        info!("[{}] None of them is advertised as {:?}", "resolve_via_mdns", printer_name);
+        return None;
+    };
+
+    // Responders usually send the SRV and A records along with the PTR, and are asked for them only when not
+    let find_target = |records: &[Record]| records.iter().find_map(|record| match &record.data {
+        RecordData::Srv(target) if same_name(&record.name, &instance) => Some(target.clone()),
+        _ => None,
+    });
+    let target = match find_target(&records) {
+        Some(target) => target,
+        None => {
+            records.extend(query(&instance, TYPE_SRV, timeout));
+            find_target(&records)?
+        }
+    };
+
+    let find_address = |records: &[Record]| records.iter().find_map(|record| match &record.data {
+        RecordData::A(address) if same_name(&record.name, &target) => Some(*address),
+        _ => None,
+    });
+    let address = match find_address(&records) {
+        Some(address) => address,
+        None => {
+            records.extend(query(&target, TYPE_A, timeout));
+            find_address(&records)?
+        }
+    };
+
+    info!("[{}] {:?} is advertised as {:?} on {} at {}", "resolve_via_mdns", printer_name, instance.join("."), target.join("."), address);
+    Some(address.to_string())
+}
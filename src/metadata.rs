@@ -0,0 +1,243 @@
+// Ask WSD devices to describe themselves through WS-Transfer Get, the metadata exchange Windows uses to fill in a
+// device's properties page, for matching a WSD port to the asset it belongs to
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::discovery::{endpoint_uuid_from_port, random_uuid, resolve_wsd_xaddrs, DEFAULT_RESOLVE_TIMEOUT};
+use crate::printers::MinimalPrinterInfo;
+
+// How long to wait for a device to accept the connection and to answer the Get
+pub const DEFAULT_METADATA_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How many devices are asked at once
+const METADATA_CONCURRENCY: usize = 16;
+
+// What a device says about itself in its ThisModel and ThisDevice metadata sections
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DeviceInfo {
+    pub manufacturer: Option<String>,
+    pub model: Option<String>,
+    pub serial_number: Option<String>,
+    pub friendly_name: Option<String>,
+    pub firmware_version: Option<String>,
+}
+
+impl DeviceInfo {
+    fn is_empty(&self) -> bool {
+        *self == DeviceInfo::default()
+    }
+}
+
+// SOAP envelope for a WS-Transfer Get of the device behind endpoint_address. The body is empty: the request is
+// for the whole of the device's metadata
+fn build_get_message(message_id: &str, endpoint_address: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>",
+            "<soap:Envelope xmlns:soap=\"http://www.w3.org/2003/05/soap-envelope\" ",
+            "xmlns:wsa=\"http://schemas.xmlsoap.org/ws/2004/08/addressing\">",
+            "<soap:Header>",
+            "<wsa:To>{}</wsa:To>",
+            "<wsa:Action>http://schemas.xmlsoap.org/ws/2004/09/transfer/Get</wsa:Action>",
+            "<wsa:MessageID>urn:uuid:{}</wsa:MessageID>",
+            "<wsa:ReplyTo><wsa:Address>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</wsa:Address></wsa:ReplyTo>",
+            "</soap:Header>",
+            "<soap:Body/>",
+            "</soap:Envelope>"
+        ),
+        endpoint_address, message_id
+    )
+}
+
+// Content of the first element with the given local name, whatever its namespace prefix or attributes. Devices
+// tag their strings with xml:lang, so the exact match discovery uses for XAddrs is not enough here
+fn element_content<'a>(xml: &'a str, local_name: &str) -> Option<&'a str> {
+    let mut search_from = 0;
+
+    while let Some(offset) = xml[search_from..].find('<') {
+        let tag_start = search_from + offset + 1;
+        let tag_end = tag_start + xml[tag_start..].find('>')?;
+        let tag = &xml[tag_start..tag_end];
+        search_from = tag_end;
+
+        // Closing tags, declarations and comments never hold content
+        if tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+
+        let name = tag.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+        if name.rsplit(':').next() != Some(local_name) {
+            continue;
+        }
+        if tag.ends_with('/') {
+            return Some("");
+        }
+
+        let content_start = tag_end + 1;
+        let content_end = content_start + xml[content_start..].find(&format!("</{}>", name))?;
+        return Some(&xml[content_start..content_end]);
+    }
+
+    None
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+fn field(section: Option<&str>, local_name: &str) -> Option<String> {
+    let text = unescape(element_content(section?, local_name)?.trim());
+    (!text.is_empty()).then_some(text)
+}
+
+// Pick the manufacturer, model and serial number out of a GetResponse
+fn parse_metadata(response: &str) -> DeviceInfo {
+    let model = element_content(response, "ThisModel");
+    let device = element_content(response, "ThisDevice");
+
+    DeviceInfo {
+        manufacturer: field(model, "Manufacturer"),
+        model: field(model, "ModelName").or_else(|| field(model, "ModelNumber")),
+        serial_number: field(device, "SerialNumber"),
+        friendly_name: field(device, "FriendlyName"),
+        firmware_version: field(device, "FirmwareVersion"),
+    }
+}
+
+// Undo chunked transfer encoding, which some devices answer with even to a request that closes the connection
+fn dechunk(body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    let mut rest = body;
+
+    loop {
+        let line_end = rest.windows(2).position(|window| window == b"\r\n")?;
+        let size_text = String::from_utf8_lossy(&rest[..line_end]);
+        let size = usize::from_str_radix(size_text.split(';').next()?.trim(), 16).ok()?;
+        rest = &rest[line_end + 2..];
+
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(rest.get(..size)?);
+        rest = rest.get(size + 2..)?;
+    }
+}
+
+// POST a SOAP message to an http:// transport address and return the body of a 200 response
+fn post_soap(url: &str, message: &str, timeout: Duration) -> Option<String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        info!("[{}] Not asking {} for metadata, only http transport addresses are supported", "post_soap", url);
+        return None;
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+
+    // The port follows the last colon, unless that colon is inside a bracketed IPv6 literal
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (host, port.parse().ok()?),
+        _ => (authority, 80),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let address = (host, port).to_socket_addrs().ok()?.next()?;
+    let mut stream = match TcpStream::connect_timeout(&address, timeout) {
+        Ok(stream) => stream,
+        Err(e) => {
+            info!("[{}] Could not connect to {}: {}", "post_soap", address, e);
+            return None;
+        }
+    };
+    stream.set_read_timeout(Some(timeout)).ok()?;
+    stream.set_write_timeout(Some(timeout)).ok()?;
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/soap+xml; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path, authority, message.len(), message
+    );
+    if let Err(e) = stream.write_all(request.as_bytes()) {
+        info!("[{}] Could not send the Get to {}: {}", "post_soap", url, e);
+        return None;
+    }
+
+    let mut response = Vec::new();
+    if let Err(e) = stream.read_to_end(&mut response) {
+        // Whatever arrived before the device stopped talking may still be a whole response
+        if response.is_empty() {
+            info!("[{}] No answer from {}: {}", "post_soap", url, e);
+            return None;
+        }
+    }
+
+    let header_end = response.windows(4).position(|window| window == b"\r\n\r\n")?;
+    let headers = String::from_utf8_lossy(&response[..header_end]).to_ascii_lowercase();
+    let body = &response[header_end + 4..];
+
+    let status = headers.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        // A device without metadata exchange answers with a SOAP fault, usually under a 500
+        info!("[{}] {} answered the Get with HTTP {}", "post_soap", url, status);
+        return None;
+    }
+
+    let body = if headers.contains("transfer-encoding: chunked") { dechunk(body)? } else { body.to_vec() };
+    Some(String::from_utf8_lossy(&body).into_owned())
+}
+
+// Find the WSD device behind printer and ask it for its metadata. None when it cannot be found, does not answer,
+// or does not implement the metadata exchange
+pub fn fetch_device_info(printer: &MinimalPrinterInfo, timeout: Duration) -> Option<DeviceInfo> {
+    let port_name = printer.port_name.to_string_lossy();
+    let uuid = endpoint_uuid_from_port(&port_name)?;
+    let endpoint_address = format!("urn:uuid:{}", uuid);
+
+    let xaddrs = resolve_wsd_xaddrs(printer, DEFAULT_RESOLVE_TIMEOUT)?;
+    for url in &xaddrs {
+        let Some(response) = post_soap(url, &build_get_message(&random_uuid(), &endpoint_address), timeout) else {
+            continue;
+        };
+
+        if response.contains("Fault>") {
+            info!("[{}] {} answered the Get with a fault, it does not share its metadata", "fetch_device_info", url);
+            continue;
+        }
+
+        let device_info = parse_metadata(&response);
+        if device_info.is_empty() {
+            info!("[{}] The metadata from {} names no manufacturer, model or serial number", "fetch_device_info", url);
+            continue;
+        }
+
+        info!("[{}] {} is {:?} {:?}, serial {:?}", "fetch_device_info", port_name,
+            device_info.manufacturer, device_info.model, device_info.serial_number);
+        return Some(device_info);
+    }
+
+    warn!("[{}] Could not get metadata for {} from {}", "fetch_device_info", port_name, xaddrs.join(" "));
+    None
+}
+
+// Fill in the device manufacturer, model and serial number of each printer, asking several devices at once. A
+// printer whose device does not answer is left without them
+pub fn attach_device_info(printers: &mut [MinimalPrinterInfo]) {
+    for chunk in printers.chunks_mut(METADATA_CONCURRENCY) {
+        thread::scope(|scope| {
+            for printer in chunk {
+                scope.spawn(move || {
+                    if let Some(device_info) = fetch_device_info(printer, DEFAULT_METADATA_TIMEOUT) {
+                        printer.device_manufacturer = device_info.manufacturer;
+                        printer.device_model = device_info.model;
+                        printer.device_serial = device_info.serial_number;
+                    }
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,957 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::ops::ControlFlow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::time::Instant;
+
+use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
+
+use crate::convert::TcpipPortConfig;
+use crate::correlation::printer_scope;
+use crate::error::PrinterError;
+use crate::journal::{Journal, Mutation};
+use crate::printers::{name_from_raw, raw_name, MinimalPrinterInfo};
+use crate::report::PrinterConversionOutcome;
+use crate::spooler_api::SpoolerApi;
+use crate::sys::ERROR_ALREADY_EXISTS;
+
+// A port a conversion creates, and the address it prints to
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewPort {
+    pub port: String,
+    pub address: String,
+}
+
+// One printer's planned move from its current port to a Standard TCP/IP port. resolved_ip is the address the
+// new port prints to, which is a host name instead when --prefer-hostname found one, or every member's address
+// for a pool. new_ports holds each port to create with its own address: one for a plain printer, or one per WSD
+// member of a pool
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlannedConversion {
+    pub printer_name: String,
+    // The name as the spooler gave it when that is not valid Unicode, see raw_name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_printer_name: Option<Vec<u16>>,
+    pub from_port: String,
+    pub to_port: String,
+    pub resolved_ip: String,
+    // Plans saved before the ports were recorded fail to load, since nothing else says where each one prints to.
+    // Empty for a pool whose WSD members all turned out to be on ports it already has
+    pub new_ports: Vec<NewPort>,
+}
+
+impl PlannedConversion {
+    // The name to open the printer by, which printer_name is only a display form of when the name is not valid Unicode
+    pub fn printer_os_name(&self) -> OsString {
+        name_from_raw(&self.printer_name, self.raw_printer_name.as_deref())
+    }
+}
+
+// What ConversionPlan::convert needs besides the spooler
+pub struct ConvertOptions<'a> {
+    // The print server to change, or None for this machine
+    pub server: Option<&'a str>,
+    // Settings for every port that is created
+    pub port_config: &'a TcpipPortConfig,
+    // Change a port that already exists but prints elsewhere or with other settings to match, instead of failing
+    // the printer. Every printer already on that port is affected
+    pub reconfigure: bool,
+    // Where every change is recorded so that undo can take the run back, or None not to record them
+    pub journal: Option<&'a Journal>,
+}
+
+impl ConvertOptions<'_> {
+    fn record(&self, mutation: Mutation) {
+        if let Some(journal) = self.journal {
+            journal.record(mutation);
+        }
+    }
+}
+
+// What one conversion changed before it finished or failed
+#[derive(Default)]
+struct Changes {
+    created_ports: Vec<String>,
+    moved: bool,
+}
+
+// Create the new ports of conversion unless they already exist, move its printer onto them and check that the
+// move stuck, noting each change in changes
+fn apply_conversion(
+    spooler: &dyn SpoolerApi,
+    options: &ConvertOptions,
+    conversion: &PlannedConversion,
+    existing_ports: &mut Vec<String>,
+    changes: &mut Changes,
+) -> Result<(), PrinterError> {
+    let server = options.server;
+
+    for NewPort { port, address } in &conversion.new_ports {
+        if existing_ports.iter().any(|existing| existing.eq_ignore_ascii_case(port)) {
+            info!("[{}] Port {} already exists, not creating it", "apply_conversion", port);
+            reuse_port(spooler, options, port, address)?;
+            continue;
+        }
+
+        match spooler.add_port(server, address, port, options.port_config) {
+            // Created since the ports were listed, so it is checked like any other port that was already there
+            Err(PrinterError::PortCreationFailed(ERROR_ALREADY_EXISTS)) => {
+                info!("[{}] Port {} appeared since the ports were listed", "apply_conversion", port);
+                reuse_port(spooler, options, port, address)?;
+            }
+            result => {
+                result?;
+                options.record(Mutation::PortCreated { port: port.to_string(), address: address.to_string() });
+                changes.created_ports.push(port.to_string());
+            }
+        }
+
+        // Later printers on the same device reuse the port instead of asking for it again
+        existing_ports.push(port.to_string());
+    }
+
+    let printer_name = conversion.printer_os_name();
+    spooler.set_printer(&printer_name, &conversion.to_port)?;
+    changes.moved = true;
+    options.record(Mutation::PrinterMoved {
+        printer: conversion.printer_name.clone(),
+        raw_printer: conversion.raw_printer_name.clone(),
+        old_port: conversion.from_port.clone(),
+        new_port: conversion.to_port.clone(),
+    });
+
+    spooler.verify_port(server, &printer_name, &conversion.to_port)
+}
+
+// Check that an existing port prints where and how a new one would, since pointing a printer at a port that
+// speaks LPR to the wrong queue, or reaches another device, breaks it quietly. With reconfigure the port is changed
+// to match instead, which also changes it for every other printer already on it
+fn reuse_port(spooler: &dyn SpoolerApi, options: &ConvertOptions, port: &str, address: &str) -> Result<(), PrinterError> {
+    let (current_address, current) = spooler.port_config(options.server, port).map_err(|e| PrinterError::PortConflict {
+        port: port.to_string(),
+        differences: format!("its Standard TCP/IP settings could not be read ({})", e),
+    })?;
+
+    let differences = current.differences(&current_address, options.port_config, address);
+    if differences.is_empty() {
+        return Ok(());
+    }
+    let differences = differences.join(", ");
+
+    if !options.reconfigure {
+        error!("[{}] {} already exists but {}", "reuse_port", port, differences);
+        return Err(PrinterError::PortConflict { port: port.to_string(), differences });
+    }
+
+    warn!("[{}] {} already exists but {}, reconfiguring it", "reuse_port", port, differences);
+    spooler.reconfigure_port(options.server, address, port, options.port_config)?;
+    options.record(Mutation::PortReconfigured {
+        port: port.to_string(),
+        old_address: current_address,
+        old_config: current,
+        new_address: address.to_string(),
+    });
+
+    Ok(())
+}
+
+// Plan a pool's move off its WSD members. ports are its members in order and addresses the WSD ones among them
+// with the address each was found at. Every WSD member is swapped in place for the port port_name names after its
+// address and the other members are kept. A WSD member whose new port the pool already has is dropped, since
+// that member already prints to the same device. Returns the new member list and the ports to create, or why the
+// pool cannot be planned
+pub fn plan_pool(
+    ports: &[String],
+    addresses: &[(String, String)],
+    mut port_name: impl FnMut(&str) -> Result<String, String>,
+) -> Result<(String, Vec<NewPort>), String> {
+    let wsd_address = |port: &str| addresses.iter().find(|(wsd_port, _)| wsd_port == port).map(|(_, address)| address);
+    let kept: Vec<&String> = ports.iter().filter(|port| wsd_address(port).is_none()).collect();
+
+    let mut members = Vec::new();
+    let mut new_ports: Vec<NewPort> = Vec::new();
+    for port in ports {
+        let Some(address) = wsd_address(port) else {
+            members.push(port.clone());
+            continue;
+        };
+
+        let name = port_name(address)?;
+        if new_ports.iter().any(|other| other.port.eq_ignore_ascii_case(&name)) {
+            return Err(format!("two members of the pool would both be {}", name));
+        }
+        if kept.iter().any(|kept| kept.eq_ignore_ascii_case(&name)) {
+            info!("[{}] The pool already has {}, dropping {} for it", "plan_pool", name, port);
+            continue;
+        }
+
+        members.push(name.clone());
+        new_ports.push(NewPort { port: name, address: address.clone() });
+    }
+
+    Ok((members.join(","), new_ports))
+}
+
+// What the read-only planning phase found out about one printer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Lookup {
+    AlreadyIp,
+    NoAddress,
+    Unreachable(String),
+    Found(String),
+    // An address along with the model its SNMP agent reported, for --name-by-model
+    FoundModel(String, String),
+    // Addresses for each WSD port of a pooled printer, keyed by the port they replace
+    FoundPool(Vec<(String, String)>),
+}
+
+// Why ConversionPlan::build left a printer out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Skip {
+    AlreadyIp,
+    NoAddress,
+    // Nothing answered at the address
+    Unreachable(String),
+    // The new port could not be named, for the reason given
+    PortName(String),
+}
+
+// How the address for a printer was come by, so a dry run can tell the operator's addresses from discovered
+// ones and show which printers cannot be converted at all
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    // Found by discovery, the address cache, the registry or the ARP cache
+    Resolved(String),
+    // Given by --ip or --map
+    Manual(String),
+    // Nothing had an address for it
+    Unresolved,
+}
+
+// Everything a convert run would change, built up front so it can be reviewed before anything is touched.
+// Serializes as a plain array of conversions; the resolutions are only kept for showing the plan
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ConversionPlan {
+    pub conversions: Vec<PlannedConversion>,
+    // Printer name and port alongside how its address was found, for every printer the plan was built for
+    #[serde(skip)]
+    pub resolutions: Vec<(String, String, Resolution)>,
+}
+
+impl ConversionPlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Plan moving a printer with a single port to the new port to_port, which prints to resolved_ip
+    pub fn push(&mut self, printer_name: &OsStr, from_port: String, to_port: String, resolved_ip: String) {
+        let new_ports = vec![NewPort { port: to_port.clone(), address: resolved_ip.clone() }];
+        self.push_pool(printer_name, from_port, to_port, new_ports);
+    }
+
+    // Plan moving a pool to the members to_port, creating new_ports for them
+    pub fn push_pool(&mut self, printer_name: &OsStr, from_port: String, to_port: String, new_ports: Vec<NewPort>) {
+        let resolved_ip = new_ports.iter().map(|new_port| new_port.address.as_str()).collect::<Vec<_>>().join(",");
+        self.conversions.push(PlannedConversion {
+            printer_name: printer_name.to_string_lossy().into_owned(),
+            raw_printer_name: raw_name(printer_name),
+            from_port,
+            to_port,
+            resolved_ip,
+            new_ports,
+        });
+    }
+
+    // Plan a move for each printer from what its lookup found, naming new ports with port_name(printer name, address,
+    // model). manual tells the printers whose address the operator gave apart from discovered ones. Returns the plan,
+    // with printers on the same device sharing one port, and the position of every printer left out with the reason
+    pub fn build<'a>(
+        lookups: impl IntoIterator<Item = (&'a MinimalPrinterInfo, Lookup)>,
+        manual: impl Fn(&MinimalPrinterInfo) -> bool,
+        port_name: impl Fn(&str, &str, Option<&str>) -> Result<String, String>,
+    ) -> (Self, Vec<(usize, Skip)>) {
+        let mut plan = Self::new();
+        let mut skipped = Vec::new();
+
+        for (index, (printer, lookup)) in lookups.into_iter().enumerate() {
+            let printer_name = printer.printer_name.to_string_lossy();
+            let from_port = printer.port_name.to_string_lossy();
+            let _scope = printer_scope(&printer_name);
+
+            let resolution = |address: &str| match manual(printer) {
+                true => Resolution::Manual(address.to_string()),
+                false => Resolution::Resolved(address.to_string()),
+            };
+            let model = match &lookup {
+                Lookup::FoundModel(_, model) => Some(model.clone()),
+                _ => None,
+            };
+
+            match lookup {
+                Lookup::AlreadyIp => skipped.push((index, Skip::AlreadyIp)),
+                Lookup::NoAddress => {
+                    plan.record_resolution(&printer_name, &from_port, Resolution::Unresolved);
+                    skipped.push((index, Skip::NoAddress));
+                }
+                Lookup::Unreachable(ip) => {
+                    plan.record_resolution(&printer_name, &from_port, resolution(&ip));
+                    skipped.push((index, Skip::Unreachable(ip)));
+                }
+                Lookup::Found(ip) | Lookup::FoundModel(ip, _) => {
+                    if let Some(model) = &model {
+                        info!("[{}] {:?} reports its model as {:?}", "ConversionPlan::build", printer.printer_name, model);
+                    }
+
+                    match port_name(&printer_name, &ip, model.as_deref()) {
+                        Ok(to_port) => {
+                            plan.record_resolution(&printer_name, &from_port, resolution(&ip));
+                            plan.push(&printer.printer_name, from_port.into_owned(), to_port, ip);
+                        }
+                        Err(reason) => skipped.push((index, Skip::PortName(reason))),
+                    }
+                }
+                Lookup::FoundPool(addresses) => {
+                    match plan_pool(&printer.ports(), &addresses, |address| port_name(&printer_name, address, None)) {
+                        Ok((to_port, new_ports)) => {
+                            let resolved = addresses.iter().map(|(_, address)| address.as_str()).collect::<Vec<_>>().join(",");
+                            plan.record_resolution(&printer_name, &from_port, resolution(&resolved));
+                            plan.push_pool(&printer.printer_name, from_port.into_owned(), to_port, new_ports);
+                        }
+                        Err(reason) => skipped.push((index, Skip::PortName(reason))),
+                    }
+                }
+            }
+        }
+
+        plan.share_ports();
+        (plan, skipped)
+    }
+
+    pub fn record_resolution(&mut self, printer_name: &str, from_port: &str, resolution: Resolution) {
+        self.resolutions.push((printer_name.to_string(), from_port.to_string(), resolution));
+    }
+
+    // How the address of printer_name was found, if the plan was built here rather than loaded
+    pub fn resolution(&self, printer_name: &str) -> Option<&Resolution> {
+        self.resolutions.iter().find(|(name, _, _)| name == printer_name).map(|(_, _, resolution)| resolution)
+    }
+
+    // Printers left out of the plan because no address was found for them, with their ports
+    pub fn unresolved(&self) -> Vec<(&str, &str)> {
+        self.resolutions.iter()
+            .filter(|(_, _, resolution)| *resolution == Resolution::Unresolved)
+            .map(|(name, port, _)| (name.as_str(), port.as_str()))
+            .collect()
+    }
+
+    // Several WSD ports sometimes lead to the same device. Spell every port the same way the first conversion to
+    // it does, since port names are case-insensitive, so that only one port is created and the rest share it
+    pub fn share_ports(&mut self) {
+        let mut seen: Vec<String> = Vec::new();
+        let mut spell = |port: &str| match seen.iter().find(|seen| seen.eq_ignore_ascii_case(port)) {
+            Some(seen) => seen.clone(),
+            None => {
+                seen.push(port.to_string());
+                port.to_string()
+            }
+        };
+
+        for conversion in &mut self.conversions {
+            conversion.to_port = conversion.to_port.split(',')
+                .map(|port| spell(port.trim()))
+                .collect::<Vec<_>>()
+                .join(",");
+            for new_port in &mut conversion.new_ports {
+                new_port.port = spell(&new_port.port);
+            }
+        }
+    }
+
+    // Every new port that more than one printer is moved to, with the printers that share it, in plan order
+    pub fn shared_ports(&self) -> Vec<(String, Vec<String>)> {
+        let mut ports: Vec<(String, Vec<String>)> = Vec::new();
+
+        for conversion in &self.conversions {
+            for NewPort { port, .. } in &conversion.new_ports {
+                match ports.iter_mut().find(|(shared, _)| shared.eq_ignore_ascii_case(port)) {
+                    Some((_, printers)) => printers.push(conversion.printer_name.clone()),
+                    None => ports.push((port.clone(), vec![conversion.printer_name.clone()])),
+                }
+            }
+        }
+
+        ports.retain(|(_, printers)| printers.len() > 1);
+        ports
+    }
+
+    // Carry out every conversion in the plan through spooler and return how each one went, in plan order. The new
+    // ports are created first, except for those that already exist, which must already print where and how the new
+    // ones would; then the printer is moved and read back to check the move stuck. Each change is recorded in the
+    // journal as it is made. A failure is recorded against its printer and the rest carry on, and on_outcome sees
+    // each outcome as soon as it is known and can stop the run by breaking. Only listing the ports fails the whole
+    // run, before anything has been changed
+    pub fn convert(
+        &self,
+        spooler: &dyn SpoolerApi,
+        options: &ConvertOptions,
+        mut on_outcome: impl FnMut(&PrinterConversionOutcome) -> ControlFlow<()>,
+    ) -> Result<Vec<PrinterConversionOutcome>, PrinterError> {
+        // Ports that already exist do not need another AddPort round trip
+        let mut existing_ports = spooler.port_names(options.server)?;
+        let mut outcomes = Vec::new();
+
+        for conversion in &self.conversions {
+            let _scope = printer_scope(&conversion.printer_name);
+            let started = Instant::now();
+            let mut changes = Changes::default();
+
+            let outcome = match apply_conversion(spooler, options, conversion, &mut existing_ports, &mut changes) {
+                Ok(()) => {
+                    info!("[{}] Moved {:?} to {}", "ConversionPlan::convert", conversion.printer_name, conversion.to_port);
+                    PrinterConversionOutcome::converted(conversion, started.elapsed())
+                }
+                Err(e) => {
+                    error!("[{}] Could not move {:?} to {}: {}", "ConversionPlan::convert", conversion.printer_name, conversion.to_port, e);
+                    PrinterConversionOutcome::failed(conversion, e, started.elapsed())
+                }
+            };
+            let outcome = PrinterConversionOutcome { created_ports: changes.created_ports, moved: changes.moved, ..outcome };
+
+            let flow = on_outcome(&outcome);
+            outcomes.push(outcome);
+            if flow.is_break() {
+                info!("[{}] Stopping after {} of {} conversions", "ConversionPlan::convert", outcomes.len(), self.len());
+                break;
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    pub fn len(&self) -> usize {
+        self.conversions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conversions.is_empty()
+    }
+
+    // Write the plan as JSON so it can be reviewed and applied later with --plan-in
+    pub fn save(&self, path: &Path) -> Result<(), PrinterError> {
+        let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+        let file = File::create(path).map_err(|e| file_error(e.to_string()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| file_error(e.to_string()))?;
+
+        info!("[{}] Wrote {} planned conversions to {}", "save", self.len(), path.display());
+
+        Ok(())
+    }
+
+    // Read a plan previously written by save
+    pub fn load(path: &Path) -> Result<Self, PrinterError> {
+        let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+        let file = File::open(path).map_err(|e| file_error(e.to_string()))?;
+        let plan: Self = serde_json::from_reader(BufReader::new(file)).map_err(|e| file_error(e.to_string()))?;
+
+        info!("[{}] Loaded {} planned conversions from {}", "load", plan.len(), path.display());
+
+        Ok(plan)
+    }
+}
+
+// Turn an operator's answer such as "1,3-5" or "all" into sorted zero-based indices into a list of count
+// entries. Entries are numbered from 1, the way they are shown
+pub fn parse_selection(input: &str, count: usize) -> Result<Vec<usize>, String> {
+    let mut selected = Vec::new();
+
+    for part in input.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        if part.eq_ignore_ascii_case("all") {
+            return Ok((0..count).collect());
+        }
+
+        let number = |text: &str| match text.trim().parse::<usize>() {
+            Ok(n) if (1..=count).contains(&n) => Ok(n),
+            _ => Err(format!("{:?} is not a number between 1 and {}", text.trim(), count)),
+        };
+
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(part)?, number(part)?),
+        };
+        if first > last {
+            return Err(format!("{:?} is backwards, write it as {}-{}", part, last, first));
+        }
+
+        selected.extend(first - 1..last);
+    }
+
+    selected.sort_unstable();
+    selected.dedup();
+
+    Ok(selected)
+}
+
+// Lay the plan out as an aligned table, one "name: from -> to" row per printer, marking the addresses the
+// operator gave. Printers no address was found for follow under a heading of their own
+impl fmt::Display for ConversionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unresolved = self.unresolved();
+        let indent = if unresolved.is_empty() { "" } else { "  " };
+        if !unresolved.is_empty() {
+            writeln!(f, "Will convert:")?;
+        }
+
+        let name_width = self.conversions.iter().map(|c| c.printer_name.len()).max().unwrap_or(0);
+        let from_width = self.conversions.iter().map(|c| c.from_port.len()).max().unwrap_or(0);
+
+        for conversion in &self.conversions {
+            let manual = match self.resolution(&conversion.printer_name) {
+                Some(Resolution::Manual(_)) => " (manual)",
+                _ => "",
+            };
+            writeln!(
+                f,
+                "{}{:<name_width$}  {:<from_width$} -> {}{}",
+                indent,
+                format!("{}:", conversion.printer_name),
+                conversion.from_port,
+                conversion.to_port,
+                manual,
+                name_width = name_width + 1,
+                from_width = from_width,
+            )?;
+        }
+        if self.conversions.is_empty() && !unresolved.is_empty() {
+            writeln!(f, "  (none)")?;
+        }
+
+        if !unresolved.is_empty() {
+            writeln!(f, "Cannot convert \u{2014} no IP found:")?;
+            for (printer_name, from_port) in &unresolved {
+                writeln!(f, "  {} ({})", printer_name, from_port)?;
+            }
+        }
+
+        for (port, printers) in self.shared_ports() {
+            writeln!(f, "{} is shared by {}", port, printers.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::ip_port_name;
+    use crate::filter::{exclude_printers, exclude_virtual_printers, DEFAULT_VIRTUAL_DRIVERS};
+    use crate::journal::load_journal;
+    use crate::printers::EnumScope;
+    use crate::printers::tests::invalid_name;
+    use crate::report::OutcomeStatus;
+    use crate::spooler_api::MockSpooler;
+
+    fn office() -> MockSpooler {
+        MockSpooler::new(vec![
+            MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "HP LaserJet Pro M404"),
+            MinimalPrinterInfo::fabricated("Front desk (color)", "WSD-0a1b2c3e", "HP Color LaserJet Pro M454"),
+            MinimalPrinterInfo::fabricated("Plotter", "WSD-0a1b2c3f", "HP DesignJet T230"),
+        ])
+    }
+
+    fn office_plan() -> ConversionPlan {
+        let mut plan = plan(&[
+            ("Front desk", "WSD-0a1b2c3d", "IP_10.0.0.5", "10.0.0.5"),
+            ("Front desk (color)", "WSD-0a1b2c3e", "IP_10.0.0.5", "10.0.0.5"),
+            ("Plotter", "WSD-0a1b2c3f", "IP_10.0.0.7", "10.0.0.7"),
+        ]);
+        plan.share_ports();
+        plan
+    }
+
+    fn options(config: &TcpipPortConfig) -> ConvertOptions<'_> {
+        ConvertOptions { server: None, port_config: config, reconfigure: false, journal: None }
+    }
+
+    fn port_of(spooler: &MockSpooler, name: &str) -> String {
+        spooler.printers().iter()
+            .find(|printer| printer.printer_name == name)
+            .map(|printer| printer.port_name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    fn new_port(port: &str, address: &str) -> NewPort {
+        NewPort { port: port.to_string(), address: address.to_string() }
+    }
+
+    fn plan(conversions: &[(&str, &str, &str, &str)]) -> ConversionPlan {
+        let mut plan = ConversionPlan::new();
+        for (printer_name, from_port, to_port, resolved_ip) in conversions {
+            plan.push(OsStr::new(printer_name), from_port.to_string(), to_port.to_string(), resolved_ip.to_string());
+        }
+        plan
+    }
+
+    #[test]
+    fn a_plain_printer_gets_one_new_port() {
+        let plan = plan(&[("Front desk", "WSD-0a1b2c3d", "IP_10.0.0.5", "10.0.0.5")]);
+        assert_eq!(plan.len(), 1);
+        assert_eq!(plan.conversions[0].new_ports, [new_port("IP_10.0.0.5", "10.0.0.5")]);
+    }
+
+    #[test]
+    fn a_pool_keeps_the_address_of_each_new_port() {
+        // Named by a template the address cannot be read back out of
+        let mut plan = ConversionPlan::new();
+        plan.push_pool(
+            OsStr::new("Pool"),
+            "WSD-0a1b2c3d, IP_10.0.0.9, WSD-0a1b2c3e".to_string(),
+            "Pool_frontdesk,IP_10.0.0.9,Pool_annex".to_string(),
+            vec![new_port("Pool_frontdesk", "frontdesk.corp.example"), new_port("Pool_annex", "10.0.0.6")],
+        );
+
+        let conversion = &plan.conversions[0];
+        assert_eq!(conversion.new_ports, [new_port("Pool_frontdesk", "frontdesk.corp.example"), new_port("Pool_annex", "10.0.0.6")]);
+        assert_eq!(conversion.resolved_ip, "frontdesk.corp.example,10.0.0.6");
+    }
+
+    fn pool_members(ports: &[&str]) -> Vec<String> {
+        ports.iter().map(|port| port.to_string()).collect()
+    }
+
+    fn found(addresses: &[(&str, &str)]) -> Vec<(String, String)> {
+        addresses.iter().map(|(port, address)| (port.to_string(), address.to_string())).collect()
+    }
+
+    #[test]
+    fn swaps_each_wsd_member_of_a_pool_in_place() {
+        let ports = pool_members(&["WSD-0a1b2c3d", "IP_10.0.0.9", "WSD-0a1b2c3e"]);
+        let addresses = found(&[("WSD-0a1b2c3d", "10.0.0.5"), ("WSD-0a1b2c3e", "10.0.0.6")]);
+
+        let (to_port, new_ports) = plan_pool(&ports, &addresses, |address| Ok(ip_port_name(address))).unwrap();
+
+        assert_eq!(to_port, "IP_10.0.0.5,IP_10.0.0.9,IP_10.0.0.6");
+        assert_eq!(new_ports, [new_port("IP_10.0.0.5", "10.0.0.5"), new_port("IP_10.0.0.6", "10.0.0.6")]);
+    }
+
+    #[test]
+    fn drops_a_wsd_member_whose_port_the_pool_already_has() {
+        let ports = pool_members(&["WSD-0a1b2c3d", "WSD-0a1b2c3e", "IP_10.0.0.6"]);
+        let addresses = found(&[("WSD-0a1b2c3d", "10.0.0.5"), ("WSD-0a1b2c3e", "10.0.0.6")]);
+
+        let (to_port, new_ports) = plan_pool(&ports, &addresses, |address| Ok(ip_port_name(address).to_lowercase())).unwrap();
+
+        assert_eq!(to_port, "ip_10.0.0.5,IP_10.0.0.6");
+        assert_eq!(new_ports, [new_port("ip_10.0.0.5", "10.0.0.5")]);
+    }
+
+    #[test]
+    fn refuses_a_pool_two_of_whose_members_would_share_a_port() {
+        let ports = pool_members(&["WSD-0a1b2c3d", "WSD-0a1b2c3e"]);
+        let addresses = found(&[("WSD-0a1b2c3d", "10.0.0.5"), ("WSD-0a1b2c3e", "10.0.0.5")]);
+        assert_eq!(plan_pool(&ports, &addresses, |address| Ok(ip_port_name(address))), Err("two members of the pool would both be IP_10.0.0.5".to_string()));
+
+        let addresses = found(&[("WSD-0a1b2c3d", "10.0.0.5"), ("WSD-0a1b2c3e", "10.0.0.6")]);
+        assert_eq!(plan_pool(&ports, &addresses, |_| Err("too long".to_string())), Err("too long".to_string()));
+    }
+
+    #[test]
+    fn a_host_name_is_used_as_the_address() {
+        let plan = plan(&[("Front desk", "WSD-0a1b2c3d", "IP_frontdesk.corp.example", "frontdesk.corp.example")]);
+        assert_eq!(plan.conversions[0].new_ports, [new_port("IP_frontdesk.corp.example", "frontdesk.corp.example")]);
+    }
+
+    #[test]
+    fn refuses_plans_saved_without_their_new_ports() {
+        let path = std::env::temp_dir().join(format!("wsd_to_ip-test-plan-{}.json", std::process::id()));
+        let old = r#"[{"printer_name": "Front desk", "from_port": "WSD-0a1b2c3d", "to_port": "IP_10.0.0.5", "resolved_ip": "10.0.0.5"}]"#;
+        std::fs::write(&path, old).unwrap();
+
+        let loaded = ConversionPlan::load(&path);
+        plan(&[("Front desk", "WSD-0a1b2c3d", "IP_10.0.0.5", "10.0.0.5")]).save(&path).unwrap();
+        let saved = ConversionPlan::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(loaded, Err(PrinterError::FileFailed { ref reason, .. }) if reason.contains("new_ports")), "{:?}", loaded);
+        assert_eq!(saved.unwrap().conversions[0].new_ports, [new_port("IP_10.0.0.5", "10.0.0.5")]);
+    }
+
+    #[test]
+    fn printers_on_the_same_device_share_one_port() {
+        let mut plan = plan(&[
+            ("Front desk", "WSD-0a1b2c3d", "IP_10.0.0.5", "10.0.0.5"),
+            ("Front desk (color)", "WSD-0a1b2c3e", "ip_10.0.0.5", "10.0.0.5"),
+            ("Plotter", "WSD-0a1b2c3f", "IP_10.0.0.7", "10.0.0.7"),
+        ]);
+        plan.share_ports();
+
+        let ports: Vec<&str> = plan.conversions.iter().map(|conversion| conversion.to_port.as_str()).collect();
+        assert_eq!(ports, ["IP_10.0.0.5", "IP_10.0.0.5", "IP_10.0.0.7"]);
+        assert_eq!(plan.conversions[1].new_ports, [new_port("IP_10.0.0.5", "10.0.0.5")]);
+        assert_eq!(plan.shared_ports(), [("IP_10.0.0.5".to_string(), vec!["Front desk".to_string(), "Front desk (color)".to_string()])]);
+    }
+
+    #[test]
+    fn keeps_track_of_how_addresses_were_found() {
+        let mut plan = plan(&[("Front desk", "WSD-0a1b2c3d", "IP_10.0.0.5", "10.0.0.5")]);
+        plan.record_resolution("Front desk", "WSD-0a1b2c3d", Resolution::Manual("10.0.0.5".to_string()));
+        plan.record_resolution("Basement", "WSD-0a1b2c3e", Resolution::Unresolved);
+
+        assert_eq!(plan.resolution("Front desk"), Some(&Resolution::Manual("10.0.0.5".to_string())));
+        assert_eq!(plan.resolution("Plotter"), None);
+        assert_eq!(plan.unresolved(), [("Basement", "WSD-0a1b2c3e")]);
+
+        let shown = plan.to_string();
+        assert!(shown.contains("Front desk:  WSD-0a1b2c3d -> IP_10.0.0.5 (manual)"), "{}", shown);
+        assert!(shown.contains("Basement (WSD-0a1b2c3e)"), "{}", shown);
+    }
+
+    #[test]
+    fn plans_keep_names_that_are_not_valid_unicode() {
+        let name = invalid_name();
+        let mut plan = ConversionPlan::new();
+        plan.push(&name, "WSD-0a1b2c3d".to_string(), "IP_10.0.0.5".to_string(), "10.0.0.5".to_string());
+        plan.push(OsStr::new("Plotter"), "WSD-0a1b2c3e".to_string(), "IP_10.0.0.7".to_string(), "10.0.0.7".to_string());
+
+        // Through a saved plan and back, as --plan-out and --plan-in do
+        let saved = serde_json::to_string(&plan).unwrap();
+        let loaded: ConversionPlan = serde_json::from_str(&saved).unwrap();
+
+        assert_eq!(loaded.conversions[0].printer_name, name.to_string_lossy());
+        assert_eq!(loaded.conversions[0].printer_os_name(), name);
+        assert_eq!(loaded.conversions[1].raw_printer_name, None);
+        assert_eq!(loaded.conversions[1].printer_os_name(), "Plotter");
+    }
+
+    #[test]
+    fn moves_printers_with_names_that_are_not_valid_unicode() {
+        let name = invalid_name();
+        let mut printer = MinimalPrinterInfo::fabricated("", "WSD-0a1b2c3d", "HP LaserJet Pro M404");
+        printer.printer_name = name.clone();
+        let spooler = MockSpooler::new(vec![printer]);
+
+        let mut plan = ConversionPlan::new();
+        plan.push(&name, "WSD-0a1b2c3d".to_string(), "IP_10.0.0.5".to_string(), "10.0.0.5".to_string());
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &TcpipPortConfig::default()).unwrap();
+
+        // The lossy name opens nothing, the kept one finds the printer
+        let lossy = OsString::from(&plan.conversions[0].printer_name);
+        assert!(spooler.set_printer(&lossy, "IP_10.0.0.5").is_err());
+        spooler.set_printer(&plan.conversions[0].printer_os_name(), "IP_10.0.0.5").unwrap();
+        assert_eq!(spooler.printers()[0].port_name, "IP_10.0.0.5");
+    }
+
+    // Plan the WSD printers spooler reports the way a convert run does: software printers and exclusions left out,
+    // then ConversionPlan::build with the address each printer was found at standing in for discovery
+    fn plan_from(spooler: &dyn SpoolerApi, exclusions: &[String], lookups: &[(&str, Lookup)]) -> (ConversionPlan, Vec<(usize, Skip)>, Vec<MinimalPrinterInfo>) {
+        let virtual_drivers: Vec<String> = DEFAULT_VIRTUAL_DRIVERS.iter().map(|driver| driver.to_string()).collect();
+        let printers = spooler.wsd_printers(None, EnumScope::All).unwrap();
+        let printers = exclude_printers(&exclude_virtual_printers(&printers, &virtual_drivers), exclusions);
+
+        let found = printers.iter().map(|printer| {
+            let lookup = lookups.iter().find(|(name, _)| printer.printer_name == *name).map_or(Lookup::NoAddress, |(_, lookup)| lookup.clone());
+            (printer, lookup)
+        });
+        let (plan, skipped) = ConversionPlan::build(found, |_| false, |_, address, _| Ok(ip_port_name(address)));
+        (plan, skipped, printers)
+    }
+
+    #[test]
+    fn plans_the_wsd_printers_the_spooler_reports() {
+        let spooler = MockSpooler::new(vec![
+            MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "HP LaserJet Pro M404"),
+            MinimalPrinterInfo::fabricated("Front desk (color)", "wsd-0a1b2c3e.0036", "HP Color LaserJet Pro M454"),
+            MinimalPrinterInfo::fabricated("Plotter", "WSD-0a1b2c3f", "HP DesignJet T230"),
+            MinimalPrinterInfo::fabricated("Basement", "WSD-0a1b2c40", "Brother HL-L2350DW"),
+            MinimalPrinterInfo::fabricated("Warehouse", "IP_10.0.0.9", "ZDesigner ZD420-203dpi ZPL"),
+            MinimalPrinterInfo::fabricated("PDF", "WSD-0a1b2c41", "Microsoft Print To PDF"),
+            MinimalPrinterInfo::fabricated("Annex", "WSD-0a1b2c42", "Brother HL-L2350DW"),
+            MinimalPrinterInfo::fabricated("Pool", "WSD-0a1b2c43, IP_10.0.0.9, WSD-0a1b2c44", "HP LaserJet Pro M404"),
+        ]);
+        let lookups = [
+            ("Front desk", Lookup::Found("10.0.0.5".to_string())),
+            ("Front desk (color)", Lookup::Found("10.0.0.5".to_string())),
+            ("Plotter", Lookup::Found("10.0.0.7".to_string())),
+            ("Annex", Lookup::Unreachable("10.0.0.8".to_string())),
+            ("Pool", Lookup::FoundPool(vec![("WSD-0a1b2c43".to_string(), "10.0.0.5".to_string()), ("WSD-0a1b2c44".to_string(), "10.0.0.6".to_string())])),
+        ];
+
+        let (plan, skipped, printers) = plan_from(&spooler, &["plot*".to_string()], &lookups);
+
+        let planned: Vec<(&str, &str)> = plan.conversions.iter()
+            .map(|conversion| (conversion.printer_name.as_str(), conversion.to_port.as_str()))
+            .collect();
+        assert_eq!(planned, [
+            ("Front desk", "IP_10.0.0.5"),
+            ("Front desk (color)", "IP_10.0.0.5"),
+            ("Pool", "IP_10.0.0.5,IP_10.0.0.9,IP_10.0.0.6"),
+        ]);
+        let skipped: Vec<(&OsStr, &Skip)> = skipped.iter().map(|(index, skip)| (printers[*index].printer_name.as_os_str(), skip)).collect();
+        assert_eq!(skipped, [(OsStr::new("Basement"), &Skip::NoAddress), (OsStr::new("Annex"), &Skip::Unreachable("10.0.0.8".to_string()))]);
+        assert_eq!(plan.unresolved(), [("Basement", "WSD-0a1b2c40")]);
+        assert_eq!(plan.resolution("Annex"), Some(&Resolution::Resolved("10.0.0.8".to_string())));
+        assert_eq!(plan.shared_ports(), [("IP_10.0.0.5".to_string(), vec!["Front desk".to_string(), "Front desk (color)".to_string(), "Pool".to_string()])]);
+
+        let config = TcpipPortConfig::default();
+        let outcomes = plan.convert(&spooler, &options(&config), |_| ControlFlow::Continue(())).unwrap();
+        assert!(outcomes.iter().all(PrinterConversionOutcome::is_converted), "{:?}", outcomes);
+        assert_eq!(port_of(&spooler, "Pool"), "IP_10.0.0.5,IP_10.0.0.9,IP_10.0.0.6");
+        assert_eq!(outcomes[2].created_ports, ["IP_10.0.0.6"]);
+        assert_eq!(spooler.wsd_printers(None, EnumScope::All).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn names_ports_and_marks_manual_addresses_while_building() {
+        let printers = office().printers();
+        let lookups = vec![
+            (&printers[0], Lookup::FoundModel("10.0.0.5".to_string(), "HP LaserJet Pro M404".to_string())),
+            (&printers[1], Lookup::AlreadyIp),
+            (&printers[2], Lookup::Found("10.0.0.7".to_string())),
+        ];
+        let port_name = |printer_name: &str, address: &str, model: Option<&str>| match (printer_name, model) {
+            ("Plotter", _) => Err("too long".to_string()),
+            (_, Some(model)) => Ok(format!("{}_{}", model, address)),
+            (_, None) => Ok(ip_port_name(address)),
+        };
+
+        let (plan, skipped) = ConversionPlan::build(lookups, |printer| printer.printer_name == "Front desk", port_name);
+
+        assert_eq!(plan.conversions[0].new_ports, [new_port("HP LaserJet Pro M404_10.0.0.5", "10.0.0.5")]);
+        assert_eq!(plan.resolution("Front desk"), Some(&Resolution::Manual("10.0.0.5".to_string())));
+        assert_eq!(skipped, [(1, Skip::AlreadyIp), (2, Skip::PortName("too long".to_string()))]);
+        assert_eq!(plan.len(), 1);
+    }
+
+    #[test]
+    fn converts_every_printer_creating_each_port_once() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+
+        let outcomes = office_plan().convert(&spooler, &options(&config), |_| ControlFlow::Continue(())).unwrap();
+
+        assert!(outcomes.iter().all(|outcome| outcome.is_converted() && outcome.moved), "{:?}", outcomes);
+        let created: Vec<&[String]> = outcomes.iter().map(|outcome| outcome.created_ports.as_slice()).collect();
+        assert_eq!(created, [&["IP_10.0.0.5".to_string()][..], &[], &["IP_10.0.0.7".to_string()]]);
+        assert_eq!(port_of(&spooler, "Front desk (color)"), "IP_10.0.0.5");
+        assert_eq!(port_of(&spooler, "Plotter"), "IP_10.0.0.7");
+    }
+
+    #[test]
+    fn reuses_a_port_that_already_prints_to_the_device() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &config).unwrap();
+
+        let outcomes = office_plan().convert(&spooler, &options(&config), |_| ControlFlow::Continue(())).unwrap();
+
+        assert!(outcomes.iter().all(PrinterConversionOutcome::is_converted), "{:?}", outcomes);
+        assert!(outcomes[0].created_ports.is_empty());
+        assert_eq!(port_of(&spooler, "Front desk"), "IP_10.0.0.5");
+    }
+
+    #[test]
+    fn fails_a_printer_whose_port_prints_elsewhere() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        spooler.add_port(None, "10.0.0.9", "IP_10.0.0.5", &config).unwrap();
+
+        let outcomes = office_plan().convert(&spooler, &options(&config), |_| ControlFlow::Continue(())).unwrap();
+
+        assert!(matches!(&outcomes[0].status, OutcomeStatus::Failed(PrinterError::PortConflict { port, .. }) if port == "IP_10.0.0.5"), "{:?}", outcomes[0]);
+        assert!(!outcomes[0].moved);
+        assert_eq!(port_of(&spooler, "Front desk"), "WSD-0a1b2c3d");
+        // The rest carry on
+        assert!(outcomes[2].is_converted());
+        assert_eq!(spooler.port_config(None, "IP_10.0.0.5").unwrap().0, "10.0.0.9");
+    }
+
+    #[test]
+    fn reconfigures_a_port_that_prints_elsewhere_when_asked() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        spooler.add_port(None, "10.0.0.9", "IP_10.0.0.5", &config).unwrap();
+        let options = ConvertOptions { reconfigure: true, ..options(&config) };
+
+        let outcomes = office_plan().convert(&spooler, &options, |_| ControlFlow::Continue(())).unwrap();
+
+        assert!(outcomes.iter().all(PrinterConversionOutcome::is_converted), "{:?}", outcomes);
+        assert_eq!(spooler.port_config(None, "IP_10.0.0.5").unwrap().0, "10.0.0.5");
+    }
+
+    #[test]
+    fn keeps_the_ports_a_failed_printer_created() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        let plan = plan(&[("Basement", "WSD-0a1b2c40", "IP_10.0.0.8", "10.0.0.8")]);
+
+        let outcomes = plan.convert(&spooler, &options(&config), |_| ControlFlow::Continue(())).unwrap();
+
+        assert!(matches!(outcomes[0].status, OutcomeStatus::Failed(PrinterError::OpenPrinterFailed { .. })), "{:?}", outcomes[0]);
+        assert_eq!(outcomes[0].created_ports, ["IP_10.0.0.8"]);
+        assert!(!outcomes[0].moved);
+    }
+
+    #[test]
+    fn stops_when_told_to() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        let mut seen = Vec::new();
+
+        let outcomes = office_plan().convert(&spooler, &options(&config), |outcome| {
+            seen.push(outcome.printer_name.clone());
+            ControlFlow::Break(())
+        }).unwrap();
+
+        assert_eq!(seen, ["Front desk"]);
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(port_of(&spooler, "Plotter"), "WSD-0a1b2c3f");
+    }
+
+    #[test]
+    fn journals_every_change() {
+        let spooler = office();
+        let config = TcpipPortConfig::default();
+        let path = std::env::temp_dir().join(format!("wsd_to_ip-test-journal-{}.jsonl", std::process::id()));
+        let journal = Journal::open(&path, None).unwrap();
+        let options = ConvertOptions { journal: Some(&journal), ..options(&config) };
+
+        office_plan().convert(&spooler, &options, |_| ControlFlow::Continue(())).unwrap();
+
+        let entries = load_journal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let actions: Vec<String> = entries.iter()
+            .map(|entry| match &entry.mutation {
+                Mutation::PortCreated { port, .. } => format!("create {}", port),
+                Mutation::PrinterMoved { printer, new_port, .. } => format!("move {} to {}", printer, new_port),
+                other => format!("{:?}", other),
+            })
+            .collect();
+        assert_eq!(actions, [
+            "create IP_10.0.0.5",
+            "move Front desk to IP_10.0.0.5",
+            "move Front desk (color) to IP_10.0.0.5",
+            "create IP_10.0.0.7",
+            "move Plotter to IP_10.0.0.7",
+        ]);
+    }
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!(parse_selection("1,3-5", 6), Ok(vec![0, 2, 3, 4]));
+        assert_eq!(parse_selection(" 2 , 2, 1-2 ", 3), Ok(vec![0, 1]));
+        assert_eq!(parse_selection("ALL", 3), Ok(vec![0, 1, 2]));
+        assert_eq!(parse_selection("", 3), Ok(vec![]));
+
+        assert!(parse_selection("0", 3).is_err());
+        assert!(parse_selection("4", 3).is_err());
+        assert!(parse_selection("3-1", 3).is_err());
+        assert!(parse_selection("two", 3).is_err());
+    }
+}
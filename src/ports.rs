@@ -0,0 +1,145 @@
+use std::ffi::OsString;
+#[cfg(windows)]
+use std::ffi::OsStr;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+#[cfg(windows)]
+use std::ptr::null_mut;
+
+#[cfg(windows)]
+use log::{info, warn, error};
+use serde::Serialize;
+#[cfg(windows)]
+use winapi::shared::minwindef::DWORD;
+#[cfg(windows)]
+use winapi::um::errhandlingapi::GetLastError;
+#[cfg(windows)]
+use winapi::um::winspool::{PORT_INFO_2W, EnumPortsW, MONITOR_INFO_1W, EnumMonitorsW};
+
+#[cfg(windows)]
+use crate::error::{PrinterError, format_error_code};
+use crate::printers::serialize_os_string_lossy;
+#[cfg(windows)]
+use crate::printers::unc_server_name;
+#[cfg(windows)]
+use crate::wide::{to_wide_null, wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+// Name of the monitor that owns Standard TCP/IP ports and answers the XcvData AddPort call
+pub const TCPIP_MONITOR_NAME: &str = "Standard TCP/IP Port";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PortInfo {
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub port_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub monitor_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub description: OsString,
+}
+
+// Enumerate the ports known to the spooler on a print server, or on this machine when server is None
+#[cfg(windows)]
+pub fn get_all_ports(server: Option<&str>) -> Result<Vec<PortInfo>, PrinterError> {
+    let mut wide_server = server.map(|server| to_wide_null(OsStr::new(&unc_server_name(server))));
+    let server_ptr = wide_server.as_mut().map_or(null_mut(), |name| name.as_mut_ptr());
+
+    let mut ports: Vec<PortInfo> = Vec::new();
+
+    let mut bytes_needed: DWORD = 0;
+    let mut num_ports: DWORD = 0;
+
+    // First call to EnumPortsW is to get the number of bytes needed
+    info!("[{}] First call to EnumPortsW to determine bytes_needed on {}", "get_all_ports", server.unwrap_or("the local machine"));
+    let enum_ports_result1 = unsafe {
+        EnumPortsW(server_ptr, 2, null_mut(), 0, &mut bytes_needed, &mut num_ports)
+    };
+
+    if enum_ports_result1 == 0 && bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumPortsW failed to set bytes_needed: {}", "get_all_ports", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumPortsFailed { code: error_code });
+    } else if bytes_needed == 0 {
+        warn!("[{}] No ports found", "get_all_ports");
+        return Ok(ports);
+    }
+
+    let mut buffer = vec![0u8; bytes_needed as usize];
+
+    // Second call to EnumPortsW fills the buffer with PORT_INFO_2W structs
+    info!("[{}] Second call to EnumPortsW to populate buffer with PORT_INFO_2W structs", "get_all_ports");
+    let enum_ports_result2 = unsafe {
+        EnumPortsW(server_ptr, 2, buffer.as_mut_ptr(), bytes_needed, &mut bytes_needed, &mut num_ports)
+    };
+
+    if enum_ports_result2 == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumPortsW failed to populate buffer: {}", "get_all_ports", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumPortsFailed { code: error_code });
+    }
+
+    let port_info = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const PORT_INFO_2W, num_ports as usize)
+    };
+
+    for port in port_info {
+        ports.push(PortInfo {
+            port_name: OsString::from_wide(&wide_str_from_raw_ptr(port.pPortName, MAX_WIDE_STR_LEN)),
+            monitor_name: OsString::from_wide(&wide_str_from_raw_ptr(port.pMonitorName, MAX_WIDE_STR_LEN)),
+            description: OsString::from_wide(&wide_str_from_raw_ptr(port.pDescription, MAX_WIDE_STR_LEN)),
+        });
+    }
+
+    info!("[{}] Found {} ports", "get_all_ports", ports.len());
+
+    Ok(ports)
+}
+
+// Names of the port monitors installed on a print server, or on this machine when server is None
+#[cfg(windows)]
+pub fn get_print_monitors(server: Option<&str>) -> Result<Vec<String>, PrinterError> {
+    let mut wide_server = server.map(|server| to_wide_null(OsStr::new(&unc_server_name(server))));
+    let server_ptr = wide_server.as_mut().map_or(null_mut(), |name| name.as_mut_ptr());
+
+    let mut bytes_needed: DWORD = 0;
+    let mut num_monitors: DWORD = 0;
+
+    // First call to EnumMonitorsW is to get the number of bytes needed
+    info!("[{}] First call to EnumMonitorsW to determine bytes_needed on {}", "get_print_monitors", server.unwrap_or("the local machine"));
+    let enum_monitors_result1 = unsafe {
+        EnumMonitorsW(server_ptr, 1, null_mut(), 0, &mut bytes_needed, &mut num_monitors)
+    };
+
+    if enum_monitors_result1 == 0 && bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumMonitorsW failed to set bytes_needed: {}", "get_print_monitors", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumMonitorsFailed { code: error_code });
+    } else if bytes_needed == 0 {
+        warn!("[{}] No monitors found", "get_print_monitors");
+        return Ok(Vec::new());
+    }
+
+    let mut buffer = vec![0u8; bytes_needed as usize];
+
+    // Second call to EnumMonitorsW fills the buffer with MONITOR_INFO_1W structs
+    let enum_monitors_result2 = unsafe {
+        EnumMonitorsW(server_ptr, 1, buffer.as_mut_ptr(), bytes_needed, &mut bytes_needed, &mut num_monitors)
+    };
+
+    if enum_monitors_result2 == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumMonitorsW failed to populate buffer: {}", "get_print_monitors", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::EnumMonitorsFailed { code: error_code });
+    }
+
+    let monitor_info = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const MONITOR_INFO_1W, num_monitors as usize)
+    };
+
+    let monitors: Vec<String> = monitor_info.iter()
+        .map(|monitor| OsString::from_wide(&wide_str_from_raw_ptr(monitor.pName, MAX_WIDE_STR_LEN)).to_string_lossy().into_owned())
+        .collect();
+
+    info!("[{}] Found {} monitors", "get_print_monitors", monitors.len());
+
+    Ok(monitors)
+}
@@ -0,0 +1,739 @@
+use std::ffi::{OsStr, OsString};
+use std::net::IpAddr;
+#[cfg(not(windows))]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(windows)]
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+#[cfg(windows)]
+use std::ptr::null_mut;
+#[cfg(windows)]
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+#[cfg(windows)]
+use log::error;
+use serde::{Serialize, Serializer};
+#[cfg(windows)]
+use winapi::shared::winerror::{ERROR_BUSY, ERROR_INSUFFICIENT_BUFFER, ERROR_INVALID_HANDLE, RPC_S_CALL_FAILED, RPC_S_CALL_FAILED_DNE, RPC_S_SERVER_TOO_BUSY, RPC_S_SERVER_UNAVAILABLE};
+#[cfg(windows)]
+use winapi::um::errhandlingapi::GetLastError;
+#[cfg(windows)]
+use winapi::um::winspool::{PRINTER_ENUM_LOCAL, PRINTER_ENUM_NAME, PRINTER_ENUM_CONNECTIONS};
+#[cfg(windows)]
+use winapi::um::winspool::{PRINTER_INFO_2W, PRINTER_INFO_4W, PRINTER_INFO_5W, EnumPrintersW, GetDefaultPrinterW, SetDefaultPrinterW};
+
+#[cfg(windows)]
+use crate::error::{PrinterError, format_error_code};
+use crate::discovery::parse_wsd_port;
+use crate::flags::{decode_printer_attributes, decode_printer_status};
+use crate::sys::{DWORD, PRINTER_ATTRIBUTE_NETWORK, PRINTER_ATTRIBUTE_SHARED};
+use crate::sys::{PRINTER_STATUS_NOT_AVAILABLE, PRINTER_STATUS_OFFLINE, PRINTER_STATUS_SERVER_OFFLINE};
+#[cfg(windows)]
+use crate::wide::{to_wide_null, wide_str_from_raw_ptr, MAX_WIDE_STR_LEN};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MinimalPrinterInfo {
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub printer_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub port_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub driver_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub share_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub location: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub comment: OsString,
+    #[serde(serialize_with = "serialize_status")]
+    pub status: DWORD,
+    #[serde(serialize_with = "serialize_attributes")]
+    pub attributes: DWORD,
+    // Driver version, environment and date, only looked up when asked for since it costs a call per printer
+    pub driver_version: Option<String>,
+    // What the WSD device reports about itself over metadata exchange, only asked for with --device-info
+    pub device_manufacturer: Option<String>,
+    pub device_model: Option<String>,
+    pub device_serial: Option<String>,
+}
+
+impl MinimalPrinterInfo {
+    // Names of the PRINTER_STATUS_* bits currently set on this printer
+    pub fn status_flags(&self) -> Vec<&'static str> {
+        decode_printer_status(self.status)
+    }
+
+    // Status flags as one comma separated string, or "Ready" when none are set
+    pub fn status_text(&self) -> String {
+        status_text(self.status)
+    }
+
+    // Names of the PRINTER_ATTRIBUTE_* bits set on this printer
+    pub fn attribute_flags(&self) -> Vec<&'static str> {
+        decode_printer_attributes(self.attributes)
+    }
+
+    // Every port the printer prints to. With printer pooling on there are several, which the spooler reports
+    // as a single comma separated list
+    pub fn ports(&self) -> Vec<String> {
+        self.port_name.to_string_lossy()
+            .split(',')
+            .map(str::trim)
+            .filter(|port| !port.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn is_pooled(&self) -> bool {
+        self.ports().len() > 1
+    }
+
+    // Whether the spooler reports the printer, or the server it is shared from, as unreachable
+    pub fn is_offline(&self) -> bool {
+        self.status & (PRINTER_STATUS_OFFLINE | PRINTER_STATUS_NOT_AVAILABLE | PRINTER_STATUS_SERVER_OFFLINE) != 0
+    }
+
+    // Shared printers only pick up a new port for remote clients once the spooler restarts
+    pub fn is_shared(&self) -> bool {
+        self.attributes & PRINTER_ATTRIBUTE_SHARED != 0
+    }
+}
+
+// A printer as enumeration would report it, for tests that run the filtering and planning logic without a spooler
+#[cfg(test)]
+impl MinimalPrinterInfo {
+    pub(crate) fn fabricated(printer_name: &str, port_name: &str, driver_name: &str) -> Self {
+        MinimalPrinterInfo {
+            printer_name: printer_name.into(),
+            port_name: port_name.into(),
+            driver_name: driver_name.into(),
+            share_name: OsString::new(),
+            location: OsString::new(),
+            comment: OsString::new(),
+            status: 0,
+            attributes: 0,
+            driver_version: None,
+            device_manufacturer: None,
+            device_model: None,
+            device_serial: None,
+        }
+    }
+}
+
+fn status_text(status: DWORD) -> String {
+    let flags = decode_printer_status(status);
+    if flags.is_empty() {
+        "Ready".to_string()
+    } else {
+        flags.join(", ")
+    }
+}
+
+// Report status as its decoded flag names rather than the raw bitfield. A flat string keeps CSV output valid
+fn serialize_status<S: Serializer>(status: &DWORD, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&status_text(*status))
+}
+
+fn serialize_attributes<S: Serializer>(attributes: &DWORD, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&decode_printer_attributes(*attributes).join(", "))
+}
+
+// OsString has no portable text form, so serialize it the same way it is displayed: lossily as UTF-8
+pub(crate) fn serialize_os_string_lossy<S: Serializer>(value: &OsString, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string_lossy())
+}
+
+// Which printers EnumPrintersW is asked for on the local machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnumScope {
+    // Printers installed on this machine (PRINTER_ENUM_LOCAL)
+    Local,
+    // Per-user connections to printers shared by other machines (PRINTER_ENUM_CONNECTIONS). On Remote Desktop
+    // Session Hosts this is where per-user WSD printers show up
+    Connections,
+    // Local printers and connections that print over the network (PRINTER_ATTRIBUTE_NETWORK). EnumPrintersW only
+    // honours PRINTER_ENUM_NETWORK at level 1, so this filters a level-2 enumeration instead
+    Network,
+    // Local printers and connections together
+    All,
+}
+
+#[cfg(windows)]
+impl EnumScope {
+    fn flags(self) -> DWORD {
+        match self {
+            EnumScope::Local => PRINTER_ENUM_LOCAL,
+            EnumScope::Connections => PRINTER_ENUM_CONNECTIONS,
+            EnumScope::Network | EnumScope::All => PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS,
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn get_all_printers() -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+    get_all_printers_on_server(None)
+}
+
+// Print server names are passed to the spooler in UNC form, so accept both HOST and \\HOST
+#[cfg(windows)]
+pub(crate) fn unc_server_name(server: &str) -> String {
+    if server.starts_with(r"\\") {
+        server.to_string()
+    } else {
+        format!(r"\\{}", server)
+    }
+}
+
+// Enumerate the printers on a print server, or on this machine when server is None
+#[cfg(windows)]
+pub fn get_all_printers_on_server(server: Option<&str>) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+    get_printers_with_scope(server, EnumScope::Local)
+}
+
+// How often a failed enumeration is retried and how long to wait before the first retry. Each further retry
+// waits twice as long as the one before
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 3, base_delay: Duration::from_millis(200) }
+    }
+}
+
+// Errors seen while the spooler is restarting or a print server is overloaded, which go away on their own.
+// Anything else, such as access denied, will fail the same way every time
+#[cfg(windows)]
+const TRANSIENT_ERROR_CODES: [DWORD; 6] = [
+    ERROR_INVALID_HANDLE,
+    ERROR_BUSY,
+    RPC_S_SERVER_UNAVAILABLE,
+    RPC_S_SERVER_TOO_BUSY,
+    RPC_S_CALL_FAILED,
+    RPC_S_CALL_FAILED_DNE,
+];
+
+#[cfg(windows)]
+fn is_transient(error: &PrinterError) -> bool {
+    error.code().is_some_and(|code| TRANSIENT_ERROR_CODES.contains(&code))
+}
+
+// Enumerate printers in the given scope. A print server is always asked for its own printers, since
+// connections are a per-user property of the machine doing the asking
+#[cfg(windows)]
+pub fn get_printers_with_scope(server: Option<&str>, scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+    get_printers_with_retry(server, scope, &RetryPolicy::default())
+}
+
+// Same as get_printers_with_scope, retrying transient failures according to policy
+#[cfg(windows)]
+pub fn get_printers_with_retry(server: Option<&str>, scope: EnumScope, policy: &RetryPolicy) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+    Ok(enum_printers_with_retry(server, scope, policy)?.collect())
+}
+
+// Printers in the given scope, one at a time, for callers that only keep a few of them. Each one is copied out
+// of the enumeration buffer as it is reached, so nothing is collected up front
+#[cfg(windows)]
+pub fn iter_printers(server: Option<&str>, scope: EnumScope) -> Result<PrinterIter, PrinterError> {
+    enum_printers_with_retry(server, scope, &RetryPolicy::default())
+}
+
+#[cfg(windows)]
+fn enum_printers_with_retry(server: Option<&str>, scope: EnumScope, policy: &RetryPolicy) -> Result<PrinterIter, PrinterError> {
+    let mut attempt = 1;
+    let mut printers = loop {
+        match enum_printers_level2(server, scope) {
+            Ok(printers) => break printers,
+            Err(e) if attempt < policy.attempts && is_transient(&e) => {
+                let delay = policy.base_delay * 2u32.pow(attempt - 1);
+                warn!("[{}] Attempt {} of {} failed ({}), retrying in {:?}", "get_printers_with_retry", attempt, policy.attempts, e, delay);
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    printers.network_only = scope == EnumScope::Network && server.is_none();
+
+    Ok(printers)
+}
+
+// The PRINTER_INFO_2W structs one EnumPrintersW call filled in, read out as MinimalPrinterInfo one at a time.
+// The structs sit at the front of the buffer and every string they point at sits behind them in the same
+// allocation, so the pointers stay valid for as long as the iterator owns the buffer. The buffer is never
+// grown or replaced once filled, and moving the iterator moves only the Vec, not the bytes it points to. Each
+// item copies its strings out, so nothing it yields borrows from the buffer
+#[cfg(windows)]
+pub struct PrinterIter {
+    buffer: Vec<u8>,
+    count: usize,
+    next: usize,
+    // Only yield printers that print over the network, for EnumScope::Network
+    network_only: bool,
+}
+
+#[cfg(windows)]
+impl PrinterIter {
+    fn empty() -> Self {
+        PrinterIter { buffer: Vec::new(), count: 0, next: 0, network_only: false }
+    }
+}
+
+#[cfg(windows)]
+impl Iterator for PrinterIter {
+    type Item = MinimalPrinterInfo;
+
+    fn next(&mut self) -> Option<MinimalPrinterInfo> {
+        while self.next < self.count {
+            // A Vec<u8> promises no alignment, so the struct is read unaligned rather than referenced in place
+            let printer = unsafe { (self.buffer.as_ptr() as *const PRINTER_INFO_2W).add(self.next).read_unaligned() };
+            self.next += 1;
+
+            if self.network_only && printer.Attributes & PRINTER_ATTRIBUTE_NETWORK == 0 {
+                continue;
+            }
+
+            // Any of these fields may be null, which comes back as an empty OsString
+            return Some(MinimalPrinterInfo {
+                printer_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pPrinterName, MAX_WIDE_STR_LEN)),
+                port_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pPortName, MAX_WIDE_STR_LEN)),
+                driver_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pDriverName, MAX_WIDE_STR_LEN)),
+                share_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pShareName, MAX_WIDE_STR_LEN)),
+                location: OsString::from_wide(&wide_str_from_raw_ptr(printer.pLocation, MAX_WIDE_STR_LEN)),
+                comment: OsString::from_wide(&wide_str_from_raw_ptr(printer.pComment, MAX_WIDE_STR_LEN)),
+                status: printer.Status,
+                attributes: printer.Attributes,
+                driver_version: None,
+                device_manufacturer: None,
+                device_model: None,
+                device_serial: None,
+            });
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.count - self.next;
+        (if self.network_only { 0 } else { remaining }, Some(remaining))
+    }
+}
+
+// How many times the populate call is repeated with a bigger buffer before giving up on a spooler that
+// keeps gaining printers
+#[cfg(windows)]
+const MAX_BUFFER_ATTEMPTS: u32 = 5;
+
+// Fill a buffer with every printer EnumPrintersW reports at level on server, or in local_flags on this machine,
+// returning it with the number of structs at its start. The first call only asks how big the buffer must be. A
+// printer installed between that call and the one that fills the buffer makes it too small, in which case it is
+// grown and filled again
+#[cfg(windows)]
+fn enum_printers_buffer(server: Option<&str>, local_flags: DWORD, level: DWORD, call_site: &'static str) -> Result<(Vec<u8>, usize), PrinterError> {
+    // EnumPrintersW only looks at the Name parameter when PRINTER_ENUM_NAME is set
+    let (flags, mut wide_server) = match server {
+        Some(server) => (PRINTER_ENUM_NAME, Some(to_wide_null(OsStr::new(&unc_server_name(server))))),
+        None => (local_flags, None),
+    };
+    let server_ptr = wide_server.as_mut().map_or(null_mut(), |name| name.as_mut_ptr());
+
+    let mut bytes_needed: DWORD = 0;
+    let mut num_printers: DWORD = 0;
+
+    // First call to EnumPrintersW is to get the number of bytes needed
+    info!("[{}] First call to EnumPrintersW to determine bytes_needed at level {} on {}", call_site, level, server.unwrap_or("the local machine"));
+    let enum_printer_result1 = unsafe {
+        EnumPrintersW(flags, server_ptr, level, null_mut(), 0, &mut bytes_needed, &mut num_printers)
+    };
+
+    if enum_printer_result1 == 0 && bytes_needed == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] EnumPrintersW failed to set bytes_needed", call_site);
+        if let Some(win_error) = format_error_code(error_code) {
+            error!("[{}] EnumPrintersW failed with error code: {}", call_site, win_error);
+        }
+        return Err(PrinterError::EnumFailed { call_site, code: error_code });
+    } else if bytes_needed == 0 {
+        // EnumPrintersW succeeding without asking for any buffer means there is genuinely nothing to enumerate
+        return Ok((Vec::new(), 0));
+    }
+    info!("[{}] Bytes needed: {}", call_site, bytes_needed);
+
+    // Allocate a contiguous block of memory that's large enough to hold all the PRINTER_INFO_* structs
+    let mut buffer = vec![0u8; bytes_needed as usize];
+    let mut attempt = 1;
+
+    // Second call to EnumPrintersW receives a pointer to the buffer which EnumPrintersW uses to fill the buffer
+    loop {
+        info!("[{}] Second call to EnumPrintersW to populate buffer with level {} structs", call_site, level);
+        let enum_printer_result2 = unsafe {
+            EnumPrintersW(flags, server_ptr, level, buffer.as_mut_ptr(), buffer.len() as DWORD, &mut bytes_needed, &mut num_printers)
+        };
+
+        if enum_printer_result2 != 0 {
+            info!("[{}] Successfully filled buffer at {:?}", call_site, buffer.as_mut_ptr());
+            break;
+        }
+
+        let error_code = unsafe { GetLastError() };
+        if error_code == ERROR_INSUFFICIENT_BUFFER && bytes_needed as usize > buffer.len() && attempt < MAX_BUFFER_ATTEMPTS {
+            warn!("[{}] Printers were added during enumeration, growing buffer from {} to {} bytes", call_site, buffer.len(), bytes_needed);
+            buffer = vec![0u8; bytes_needed as usize];
+            attempt += 1;
+            continue;
+        }
+
+        error!("[{}] EnumPrintersW failed to populate buffer with level {} structs", call_site, level);
+        if let Some(win_error) = format_error_code(error_code) {
+            error!("[{}] EnumPrintersW failed with error code: {}", call_site, win_error);
+        }
+        return Err(PrinterError::EnumFailed { call_site, code: error_code });
+    }
+
+    Ok((buffer, num_printers as usize))
+}
+
+#[cfg(windows)]
+fn enum_printers_level2(server: Option<&str>, scope: EnumScope) -> Result<PrinterIter, PrinterError> {
+    let (buffer, count) = enum_printers_buffer(server, scope.flags(), 2, "get_all_printers_on_server")?;
+
+    if count == 0 {
+        warn!("[{}] No printers found", "get_all_printers_on_server");
+        return Ok(PrinterIter::empty());
+    }
+    info!("[{}] Buffer holds {} PRINTER_INFO_2W structs", "get_all_printers_on_server", count);
+
+    Ok(PrinterIter { buffer, count, next: 0, network_only: false })
+}
+
+// How a printer is attached to this machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum PrinterKind {
+    // Installed on this machine and only used here
+    Local,
+    // Installed on this machine and shared with others
+    Shared,
+    // A per-user connection to a printer on another machine
+    Connection,
+}
+
+// The little EnumPrintersW hands back at level 4: enough to tell local, shared and connection printers apart
+#[derive(Clone, Debug, Serialize)]
+pub struct PrinterSummary {
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub printer_name: OsString,
+    #[serde(serialize_with = "serialize_os_string_lossy")]
+    pub server_name: OsString,
+    #[serde(serialize_with = "serialize_attributes")]
+    pub attributes: DWORD,
+}
+
+impl PrinterSummary {
+    pub fn kind(&self) -> PrinterKind {
+        if self.attributes & PRINTER_ATTRIBUTE_NETWORK != 0 || !self.server_name.is_empty() {
+            PrinterKind::Connection
+        } else if self.attributes & PRINTER_ATTRIBUTE_SHARED != 0 {
+            PrinterKind::Shared
+        } else {
+            PrinterKind::Local
+        }
+    }
+}
+
+// List printers at level 4: on server, or this machine's local printers and per-user connections when server is
+// None. Level 4 is served from the registry without contacting any printer or remote server, so it is fast even
+// where level 2 is slow, e.g. on RDS hosts
+#[cfg(windows)]
+pub fn get_printers_level4(server: Option<&str>) -> Result<Vec<PrinterSummary>, PrinterError> {
+    let (buffer, count) = enum_printers_buffer(server, PRINTER_ENUM_LOCAL | PRINTER_ENUM_CONNECTIONS, 4, "get_printers_level4")?;
+    if count == 0 {
+        warn!("[{}] No printers found", "get_printers_level4");
+        return Ok(Vec::new());
+    }
+
+    let printer_info = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const PRINTER_INFO_4W, count)
+    };
+
+    let printers: Vec<PrinterSummary> = printer_info.iter()
+        .map(|printer| PrinterSummary {
+            printer_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pPrinterName, MAX_WIDE_STR_LEN)),
+            server_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pServerName, MAX_WIDE_STR_LEN)),
+            attributes: printer.Attributes,
+        })
+        .collect();
+
+    info!("[{}] Found {} printers", "get_printers_level4", printers.len());
+
+    Ok(printers)
+}
+
+// Enumerate printers at level 5, which holds little more than each printer's name and ports. The spooler answers
+// it from what it has stored, without asking drivers or devices for status, so it stays fast on servers with
+// thousands of queues. Everything but the name, port and attributes is left empty
+#[cfg(windows)]
+pub fn get_printers_level5(server: Option<&str>, scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+    let (buffer, count) = enum_printers_buffer(server, scope.flags(), 5, "get_printers_level5")?;
+    if count == 0 {
+        warn!("[{}] No printers found", "get_printers_level5");
+        return Ok(Vec::new());
+    }
+
+    let printer_info = unsafe {
+        std::slice::from_raw_parts(buffer.as_ptr() as *const PRINTER_INFO_5W, count)
+    };
+
+    let mut printers: Vec<MinimalPrinterInfo> = printer_info.iter()
+        .map(|printer| MinimalPrinterInfo {
+            printer_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pPrinterName, MAX_WIDE_STR_LEN)),
+            port_name: OsString::from_wide(&wide_str_from_raw_ptr(printer.pPortName, MAX_WIDE_STR_LEN)),
+            driver_name: OsString::new(),
+            share_name: OsString::new(),
+            location: OsString::new(),
+            comment: OsString::new(),
+            status: 0,
+            attributes: printer.Attributes,
+            driver_version: None,
+            device_manufacturer: None,
+            device_model: None,
+            device_serial: None,
+        })
+        .collect();
+
+    if scope == EnumScope::Network && server.is_none() {
+        printers.retain(|printer| printer.attributes & PRINTER_ATTRIBUTE_NETWORK != 0);
+    }
+
+    info!("[{}] Found {} printers", "get_printers_level5", printers.len());
+
+    Ok(printers)
+}
+
+// The current user's default printer, or None if there is no default
+#[cfg(windows)]
+pub fn get_default_printer() -> Option<OsString> {
+    // First call to GetDefaultPrinterW is to get the buffer size in characters, including the terminator
+    let mut chars_needed: DWORD = 0;
+    unsafe {
+        GetDefaultPrinterW(null_mut(), &mut chars_needed);
+    }
+
+    if chars_needed == 0 {
+        info!("[{}] No default printer is set", "get_default_printer");
+        return None;
+    }
+
+    let mut buffer = vec![0u16; chars_needed as usize];
+    let result = unsafe { GetDefaultPrinterW(buffer.as_mut_ptr(), &mut chars_needed) };
+
+    if result == 0 {
+        let error_code = unsafe { GetLastError() };
+        warn!("[{}] GetDefaultPrinterW failed: {}", "get_default_printer", format_error_code(error_code).unwrap_or_default());
+        return None;
+    }
+
+    let default_printer = OsString::from_wide(&wide_str_from_raw_ptr(buffer.as_ptr(), buffer.len()));
+    info!("[{}] Default printer is {:?}", "get_default_printer", default_printer);
+
+    Some(default_printer)
+}
+
+// Make printer_name the current user's default printer
+#[cfg(windows)]
+pub fn set_default_printer(printer_name: &OsStr) -> Result<(), PrinterError> {
+    let wide_name = to_wide_null(printer_name);
+    let result = unsafe { SetDefaultPrinterW(wide_name.as_ptr()) };
+
+    if result == 0 {
+        let error_code = unsafe { GetLastError() };
+        error!("[{}] SetDefaultPrinterW failed: {}", "set_default_printer", format_error_code(error_code).unwrap_or_default());
+        return Err(PrinterError::SetDefaultFailed { name: printer_name.to_string_lossy().into_owned(), code: error_code });
+    }
+
+    info!("[{}] Set default printer to {:?}", "set_default_printer", printer_name);
+
+    Ok(())
+}
+
+// A printer name exactly as the spooler gave it, kept for names that are not valid Unicode such as one with a lone
+// surrogate. Their lossy form has U+FFFD in place of the bad units and opens no printer, so anything that has to
+// find the printer again later carries this alongside it. None for valid names, whose String form round-trips.
+// Elsewhere than Windows names are bytes, kept one to a unit
+pub fn raw_name(name: &OsStr) -> Option<Vec<u16>> {
+    if name.to_str().is_some() {
+        return None;
+    }
+
+    #[cfg(windows)]
+    let raw = name.encode_wide().collect();
+    #[cfg(not(windows))]
+    let raw = name.as_bytes().iter().map(|&byte| u16::from(byte)).collect();
+    Some(raw)
+}
+
+// The name raw came from, or name itself when there was no raw form to keep
+pub fn name_from_raw(name: &str, raw: Option<&[u16]>) -> OsString {
+    match raw {
+        #[cfg(windows)]
+        Some(raw) => OsString::from_wide(raw),
+        #[cfg(not(windows))]
+        Some(raw) => OsString::from_vec(raw.iter().map(|&unit| unit as u8).collect()),
+        None => name.into(),
+    }
+}
+
+// Whether a port name belongs to the WSD port monitor, which is whenever parse_wsd_port can take it apart
+pub fn is_wsd_port(port_name: &str) -> bool {
+    parse_wsd_port(port_name).is_some()
+}
+
+// Whether a port name belongs to a Standard TCP/IP port: the IP_<addr> names this tool and the Add Printer
+// wizard create, the <model>_<addr> names of --name-by-model, TCPIP-prefixed names from older tools, or a bare
+// address optionally followed by _<n>
+pub fn is_ip_port(port_name: &str) -> bool {
+    if port_name.starts_with("IP_") || port_name.to_ascii_uppercase().starts_with("TCPIP") {
+        return true;
+    }
+
+    let address = match port_name.rsplit_once('_') {
+        Some((address, suffix)) if !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()) => address,
+        _ => port_name,
+    };
+    address.parse::<IpAddr>().is_ok() || crate::convert::address_from_ip_port_name(port_name).is_some()
+}
+
+pub fn get_wsd_printers(all_printers: &[MinimalPrinterInfo]) -> Vec<MinimalPrinterInfo> {
+    get_wsd_printers_refs(all_printers).into_iter().cloned().collect()
+}
+
+// The printers in all_printers on at least one WSD port, borrowed rather than copied for callers that only read them
+pub fn get_wsd_printers_refs(all_printers: &[MinimalPrinterInfo]) -> Vec<&MinimalPrinterInfo> {
+    if all_printers.is_empty() {
+        warn!("[{}] Received empty set of printers", "get_wsd_printers");
+        return Vec::new();
+    }
+
+    // Filter through all_printers and select those whose ports start with WSD
+    info!("[{}] Searching through {} printers", "get_wsd_printers", all_printers.len());
+    let wsd_printers: Vec<&MinimalPrinterInfo> = all_printers.iter()
+        .filter(|printer| {
+            // A null pPortName comes through as an empty name; such a printer has no port to convert
+            if printer.port_name.is_empty() {
+                warn!("[{}] {:?} has no port, skipping it", "get_wsd_printers", printer.printer_name);
+                return false;
+            }
+
+            // Windows hands back UTF-16 that need not be valid, such as a name with a lone surrogate. The WSD prefix
+            // is ASCII, so it survives the lossy conversion ports() makes and such a printer is still found; say so,
+            // since its name then shows with U+FFFD in its place
+            if printer.port_name.to_str().is_none() || printer.printer_name.to_str().is_none() {
+                warn!("[{}] {:?} on {:?} has a name that is not valid Unicode, matching on {:?}", "get_wsd_printers",
+                    printer.printer_name, printer.port_name, printer.port_name.to_string_lossy());
+            }
+
+            // In a pool any one WSD member is enough, wherever it sits in the list
+            let ports = printer.ports();
+            let wsd_ports = ports.iter().filter(|port| is_wsd_port(port)).count();
+            if wsd_ports > 0 && ports.len() > 1 {
+                info!("[{}] {:?} is a pool of {} ports, {} of them WSD", "get_wsd_printers", printer.printer_name, ports.len(), wsd_ports);
+            }
+
+            wsd_ports > 0
+        })
+        .collect();
+
+    info!("[{}] Successfully found {} WSD connected printers", "get_wsd_printers", wsd_printers.len());
+
+    wsd_printers
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_wsd_ports() {
+        for port in ["WSD-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f", "WSD-6b3c9d4e-1f2a-4b5c-8d7e-0a1b2c3d4e5f.0036", "WSD_0a1b2c3d", "WSDPORT_3"] {
+            assert!(is_wsd_port(port), "{}", port);
+        }
+        for port in ["IP_192.168.1.20", "USB001", "LPT1:", "nul:", "WS-0a1b2c3d", ""] {
+            assert!(!is_wsd_port(port), "{}", port);
+        }
+    }
+
+    #[test]
+    fn recognizes_ip_ports() {
+        for port in ["IP_192.168.1.20", "IP_[2001:db8::20]", "TCPIP_10.0.0.5", "tcpip-printer", "192.168.1.20", "192.168.1.20_1", "2001:db8::20"] {
+            assert!(is_ip_port(port), "{}", port);
+        }
+        for port in ["WSD-0a1b2c3d", "USB001", "LPT1:", "192.168.1.20_a", "printer_2", ""] {
+            assert!(!is_ip_port(port), "{}", port);
+        }
+    }
+
+    #[test]
+    fn splits_pooled_ports() {
+        let printer = MinimalPrinterInfo::fabricated("Pool", "WSD-0a1b2c3d, IP_10.0.0.5,", "Driver");
+        assert_eq!(printer.ports(), ["WSD-0a1b2c3d", "IP_10.0.0.5"]);
+        assert!(printer.is_pooled());
+        assert!(!MinimalPrinterInfo::fabricated("Single", "WSD-0a1b2c3d", "Driver").is_pooled());
+    }
+
+    #[test]
+    fn finds_printers_on_any_wsd_port() {
+        let printers = [
+            MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "Driver"),
+            MinimalPrinterInfo::fabricated("Plotter", "IP_10.0.0.5", "Driver"),
+            MinimalPrinterInfo::fabricated("Pool", "IP_10.0.0.6,WSD-0a1b2c3e", "Driver"),
+            MinimalPrinterInfo::fabricated("Portless", "", "Driver"),
+        ];
+
+        let names: Vec<_> = get_wsd_printers(&printers).into_iter().map(|printer| printer.printer_name).collect();
+        assert_eq!(names, ["Front desk", "Pool"]);
+        assert!(get_wsd_printers(&[]).is_empty());
+    }
+
+    // A name the spooler could hand back that is not valid Unicode: a lone surrogate on Windows, a stray byte elsewhere
+    pub(crate) fn invalid_name() -> OsString {
+        #[cfg(windows)]
+        let name = OsString::from_wide(&[0x0050, 0x0072, 0xd800, 0x006e, 0x0074]);
+        #[cfg(not(windows))]
+        let name = OsString::from_vec(vec![b'P', b'r', 0xff, b'n', b't']);
+        name
+    }
+
+    #[test]
+    fn keeps_names_that_are_not_valid_unicode() {
+        let name = invalid_name();
+        let lossy = name.to_string_lossy().into_owned();
+        assert_eq!(lossy, "Pr\u{fffd}nt");
+        assert_ne!(OsString::from(&lossy), name);
+
+        let raw = raw_name(&name);
+        assert!(raw.is_some());
+        assert_eq!(name_from_raw(&lossy, raw.as_deref()), name);
+    }
+
+    #[test]
+    fn valid_names_need_no_raw_form() {
+        assert_eq!(raw_name(OsStr::new("Front desk \u{00e9}")), None);
+        assert_eq!(name_from_raw("Front desk", None), "Front desk");
+    }
+
+    #[test]
+    fn reads_offline_and_shared_from_the_bits() {
+        let mut printer = MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "Driver");
+        assert!(!printer.is_offline());
+        assert_eq!(printer.status_text(), "Ready");
+
+        printer.status = PRINTER_STATUS_OFFLINE;
+        printer.attributes = PRINTER_ATTRIBUTE_SHARED;
+        assert!(printer.is_offline());
+        assert!(printer.is_shared());
+    }
+}
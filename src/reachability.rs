@@ -0,0 +1,40 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use log::{info, warn};
+
+use crate::convert::DEFAULT_RAW_PORT_NUMBER;
+
+// How long convert waits for a printer to accept a connection before skipping it
+pub const DEFAULT_REACHABILITY_TIMEOUT_MS: u32 = 2000;
+
+// Whether anything accepts a TCP connection on the Raw printing port (9100) at ip within timeout_ms.
+// This is what the new Standard TCP/IP port will talk to, so it is a better check than ICMP, which is often filtered
+pub fn is_reachable(ip: &str, timeout_ms: u32) -> bool {
+    is_reachable_on_port(ip, DEFAULT_RAW_PORT_NUMBER as u16, timeout_ms)
+}
+
+// Same as is_reachable, for devices printed to on some other TCP port
+pub fn is_reachable_on_port(ip: &str, port: u16, timeout_ms: u32) -> bool {
+    let timeout = Duration::from_millis(timeout_ms as u64);
+
+    let addresses = match (ip, port).to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(e) => {
+            warn!("[{}] Could not resolve {}: {}", "is_reachable", ip, e);
+            return false;
+        }
+    };
+
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(_) => {
+                info!("[{}] {} accepted a connection", "is_reachable", address);
+                return true;
+            }
+            Err(e) => info!("[{}] {} did not accept a connection: {}", "is_reachable", address, e),
+        }
+    }
+
+    false
+}
@@ -0,0 +1,97 @@
+use log::{info, warn};
+use winreg::enums::{HKEY_LOCAL_MACHINE, KEY_READ};
+use winreg::RegKey;
+
+use crate::discovery::{endpoint_uuid_from_port, host_from_url};
+
+// Keys under HKLM where the WSD stack caches what it learned about each device
+const WSD_REGISTRY_ROOTS: [&str; 3] = [
+    r"SYSTEM\CurrentControlSet\Enum\SWD\DAFWSDProvider",
+    r"SYSTEM\CurrentControlSet\Control\Print\Monitors\WSD Port\Ports",
+    r"SYSTEM\CurrentControlSet\Control\DeviceClasses",
+];
+
+// DeviceClasses nests interface GUID -> device instance -> properties, so a few levels is enough
+const MAX_SEARCH_DEPTH: usize = 3;
+
+// Look for a string value holding an http(s) URL in key or any of its subkeys
+fn find_url_in_key(key: &RegKey, depth: usize) -> Option<String> {
+    for (name, value) in key.enum_values().flatten() {
+        let text = value.to_string();
+        if let Some(url) = text.split_whitespace().find(|s| s.starts_with("http://") || s.starts_with("https://")) {
+            info!("[{}] Found URL {} in value {}", "find_url_in_key", url, name);
+            return Some(url.to_string());
+        }
+    }
+
+    if depth == 0 {
+        return None;
+    }
+
+    for subkey_name in key.enum_keys().flatten() {
+        if let Ok(subkey) = key.open_subkey_with_flags(&subkey_name, KEY_READ) {
+            if let Some(url) = find_url_in_key(&subkey, depth - 1) {
+                return Some(url);
+            }
+        }
+    }
+
+    None
+}
+
+// Walk down from key looking for a subkey whose name mentions uuid, then search it for a URL
+fn find_device_key_url(key: &RegKey, uuid: &str, depth: usize) -> Option<String> {
+    for subkey_name in key.enum_keys().flatten() {
+        let subkey = match key.open_subkey_with_flags(&subkey_name, KEY_READ) {
+            Ok(subkey) => subkey,
+            Err(_) => continue,
+        };
+
+        if subkey_name.to_lowercase().contains(uuid) {
+            if let Some(url) = find_url_in_key(&subkey, MAX_SEARCH_DEPTH) {
+                return Some(url);
+            }
+        } else if depth > 0 {
+            if let Some(url) = find_device_key_url(&subkey, uuid, depth - 1) {
+                return Some(url);
+            }
+        }
+    }
+
+    None
+}
+
+// Read the cached device address for a WSD port from the registry instead of probing the network
+pub fn read_wsd_address_from_registry(port_name: &str) -> Option<String> {
+    let uuid = match endpoint_uuid_from_port(port_name) {
+        Some(uuid) => uuid,
+        None => {
+            warn!("[{}] Port {} does not carry a WSD device id", "read_wsd_address_from_registry", port_name);
+            return None;
+        }
+    };
+
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+
+    for root in WSD_REGISTRY_ROOTS {
+        let root_key = match hklm.open_subkey_with_flags(root, KEY_READ) {
+            Ok(root_key) => root_key,
+            Err(e) => {
+                info!("[{}] Could not open HKLM\\{}: {}", "read_wsd_address_from_registry", root, e);
+                continue;
+            }
+        };
+
+        info!("[{}] Searching HKLM\\{} for {}", "read_wsd_address_from_registry", root, uuid);
+        if let Some(url) = find_device_key_url(&root_key, &uuid, MAX_SEARCH_DEPTH) {
+            if let Some(host) = host_from_url(&url) {
+                info!("[{}] Resolved {} to {} from the registry", "read_wsd_address_from_registry", port_name, host);
+                return Some(host);
+            }
+        }
+    }
+
+    warn!("[{}] No cached address found for {}", "read_wsd_address_from_registry", port_name);
+
+    None
+}
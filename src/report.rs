@@ -0,0 +1,362 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::correlation::existing_correlation_id;
+use crate::error::PrinterError;
+use crate::plan::PlannedConversion;
+use crate::printers::name_from_raw;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConversionStatus {
+    Converted,
+    Skipped,
+    Failed,
+}
+
+// What happened to one printer during a convert run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PrinterResult {
+    // The print server the printer is on, in a report covering several of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub printer_name: String,
+    pub from_port: String,
+    pub to_port: Option<String>,
+    pub status: ConversionStatus,
+    pub error: Option<String>,
+    pub error_code: Option<u32>,
+    pub duration_ms: u64,
+    // Prefix of this printer's lines in the log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    // With --test-page, the job the spooler queued for the test page, or why it would not queue one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_page_job: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_page_error: Option<String>,
+    // The address the new port prints to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_ip: Option<String>,
+}
+
+// How one printer's conversion ended, keeping the error itself rather than its text
+#[derive(Debug)]
+pub enum OutcomeStatus {
+    Converted,
+    Skipped(String),
+    Failed(PrinterError),
+}
+
+// What converting one printer came to, for callers that want to act on each result rather than read the report
+#[derive(Debug)]
+pub struct PrinterConversionOutcome {
+    pub printer_name: String,
+    // The name as the spooler gave it when that is not valid Unicode, see raw_name
+    pub raw_printer_name: Option<Vec<u16>>,
+    pub from_port: String,
+    pub to_port: Option<String>,
+    pub resolved_ip: Option<String>,
+    pub status: OutcomeStatus,
+    pub duration: Duration,
+    // Ports the conversion created, including for a printer that then failed, so a rollback can remove them
+    pub created_ports: Vec<String>,
+    // Whether the printer was moved to to_port, which a printer that failed verification also was
+    pub moved: bool,
+}
+
+impl PrinterConversionOutcome {
+    pub fn converted(conversion: &PlannedConversion, duration: Duration) -> Self {
+        Self::planned(conversion, OutcomeStatus::Converted, duration)
+    }
+
+    pub fn failed(conversion: &PlannedConversion, error: PrinterError, duration: Duration) -> Self {
+        Self::planned(conversion, OutcomeStatus::Failed(error), duration)
+    }
+
+    pub fn skipped(printer_name: &str, from_port: &str, reason: &str, duration: Duration) -> Self {
+        PrinterConversionOutcome {
+            printer_name: printer_name.to_string(),
+            raw_printer_name: None,
+            from_port: from_port.to_string(),
+            to_port: None,
+            resolved_ip: None,
+            status: OutcomeStatus::Skipped(reason.to_string()),
+            duration,
+            created_ports: Vec::new(),
+            moved: false,
+        }
+    }
+
+    fn planned(conversion: &PlannedConversion, status: OutcomeStatus, duration: Duration) -> Self {
+        // Only a converted printer is known to have moved; convert says so for the ones that failed afterwards
+        let moved = matches!(status, OutcomeStatus::Converted);
+        PrinterConversionOutcome {
+            printer_name: conversion.printer_name.clone(),
+            raw_printer_name: conversion.raw_printer_name.clone(),
+            from_port: conversion.from_port.clone(),
+            to_port: Some(conversion.to_port.clone()),
+            resolved_ip: Some(conversion.resolved_ip.clone()),
+            status,
+            duration,
+            created_ports: Vec::new(),
+            moved,
+        }
+    }
+
+    // The name to open the printer by, which printer_name is only a display form of when the name is not valid Unicode
+    pub fn printer_os_name(&self) -> OsString {
+        name_from_raw(&self.printer_name, self.raw_printer_name.as_deref())
+    }
+
+    pub fn is_converted(&self) -> bool {
+        matches!(self.status, OutcomeStatus::Converted)
+    }
+}
+
+// Outcome of a whole convert run, written with --report so failures can be picked up by monitoring
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConversionReport {
+    pub started: String,
+    pub converted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub printers: Vec<PrinterResult>,
+    // New ports that several printers were planned onto because their WSD ports lead to the same device
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shared_ports: Vec<SharedPort>,
+    // How each server went, in a report covering several of them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub servers: Vec<ServerResult>,
+    // How long enumeration, filtering, discovery and conversion each took, in the order they ran
+    pub phases: Vec<PhaseDuration>,
+    // Set by finish
+    pub total_ms: u64,
+    #[serde(skip, default = "Instant::now")]
+    clock: Instant,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PhaseDuration {
+    pub phase: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SharedPort {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    pub port_name: String,
+    pub printers: Vec<String>,
+}
+
+// One server's part in a run over several, with the exit code its convert run ended with
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ServerResult {
+    pub server: String,
+    pub exit_code: i32,
+    pub converted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    // Why the server has no results, when its run ended before it could write any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl Default for ConversionReport {
+    fn default() -> Self {
+        ConversionReport {
+            started: OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default(),
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            printers: Vec::new(),
+            shared_ports: Vec::new(),
+            servers: Vec::new(),
+            phases: Vec::new(),
+            total_ms: 0,
+            clock: Instant::now(),
+        }
+    }
+}
+
+impl ConversionReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_converted(&mut self, printer_name: &str, from_port: &str, to_port: &str, duration: Duration) {
+        self.converted += 1;
+        self.push(printer_name, from_port, Some(to_port), ConversionStatus::Converted, None, duration);
+    }
+
+    pub fn record_skipped(&mut self, printer_name: &str, from_port: &str, reason: &str, duration: Duration) {
+        self.skipped += 1;
+        self.printers.push(PrinterResult {
+            printer_name: printer_name.to_string(),
+            from_port: from_port.to_string(),
+            to_port: None,
+            status: ConversionStatus::Skipped,
+            error: Some(reason.to_string()),
+            error_code: None,
+            duration_ms: duration.as_millis() as u64,
+            correlation_id: existing_correlation_id(printer_name),
+            server: None,
+            test_page_job: None,
+            test_page_error: None,
+            resolved_ip: None,
+        });
+    }
+
+    pub fn record_failed(&mut self, printer_name: &str, from_port: &str, to_port: &str, error: &PrinterError, duration: Duration) {
+        self.failed += 1;
+        self.push(printer_name, from_port, Some(to_port), ConversionStatus::Failed, Some(error), duration);
+    }
+
+    pub fn record_phase(&mut self, phase: &str, duration: Duration) {
+        self.phases.push(PhaseDuration { phase: phase.to_string(), duration_ms: duration.as_millis() as u64 });
+    }
+
+    // Stop the clock on the whole run, which started when the report was created
+    pub fn finish(&mut self) -> Duration {
+        let total = self.clock.elapsed();
+        self.total_ms = total.as_millis() as u64;
+        total
+    }
+
+    pub fn record_shared_port(&mut self, port_name: &str, printers: &[String]) {
+        self.shared_ports.push(SharedPort { server: None, port_name: port_name.to_string(), printers: printers.to_vec() });
+    }
+
+    fn push(&mut self, printer_name: &str, from_port: &str, to_port: Option<&str>, status: ConversionStatus, error: Option<&PrinterError>, duration: Duration) {
+        self.printers.push(PrinterResult {
+            printer_name: printer_name.to_string(),
+            from_port: from_port.to_string(),
+            to_port: to_port.map(str::to_string),
+            status,
+            error: error.map(|e| e.to_string()),
+            error_code: error.and_then(PrinterError::code),
+            duration_ms: duration.as_millis() as u64,
+            correlation_id: existing_correlation_id(printer_name),
+            server: None,
+            test_page_job: None,
+            test_page_error: None,
+            resolved_ip: None,
+        });
+    }
+
+    // Record an outcome under its status, with the address it resolved to
+    pub fn record_outcome(&mut self, outcome: &PrinterConversionOutcome) {
+        let to_port = outcome.to_port.as_deref().unwrap_or_default();
+        match &outcome.status {
+            OutcomeStatus::Converted => self.record_converted(&outcome.printer_name, &outcome.from_port, to_port, outcome.duration),
+            OutcomeStatus::Skipped(reason) => self.record_skipped(&outcome.printer_name, &outcome.from_port, reason, outcome.duration),
+            OutcomeStatus::Failed(e) => self.record_failed(&outcome.printer_name, &outcome.from_port, to_port, e, outcome.duration),
+        }
+        if let Some(printer) = self.printers.last_mut() {
+            printer.resolved_ip = outcome.resolved_ip.clone();
+        }
+    }
+
+    // Note how the test page for the printer converted last under printer_name went
+    pub fn record_test_page(&mut self, printer_name: &str, result: &Result<u32, PrinterError>) {
+        let Some(printer) = self.printers.iter_mut().rev().find(|printer| printer.printer_name == printer_name) else {
+            return;
+        };
+        match result {
+            Ok(job_id) => printer.test_page_job = Some(*job_id),
+            Err(e) => printer.test_page_error = Some(e.to_string()),
+        }
+    }
+
+    // Fold the report of one server's run into this one, tagging its printers and ports with the server. result
+    // is None when the run ended before writing a report, and error then says why
+    pub fn record_server(&mut self, server: &str, exit_code: i32, result: Option<ConversionReport>, error: Option<String>, duration: Duration) {
+        let mut outcome = ServerResult {
+            server: server.to_string(),
+            exit_code,
+            converted: 0,
+            skipped: 0,
+            failed: 0,
+            error,
+            duration_ms: duration.as_millis() as u64,
+        };
+
+        if let Some(result) = result {
+            outcome.converted = result.converted;
+            outcome.skipped = result.skipped;
+            outcome.failed = result.failed;
+
+            self.converted += result.converted;
+            self.skipped += result.skipped;
+            self.failed += result.failed;
+            self.printers.extend(result.printers.into_iter().map(|printer| PrinterResult { server: Some(server.to_string()), ..printer }));
+            self.shared_ports.extend(result.shared_ports.into_iter().map(|port| SharedPort { server: Some(server.to_string()), ..port }));
+        }
+
+        self.servers.push(outcome);
+    }
+
+    // Read back a report written by save
+    pub fn load(path: &Path) -> Result<Self, PrinterError> {
+        let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+        let contents = fs::read_to_string(path).map_err(|e| file_error(e.to_string()))?;
+        serde_json::from_str(&contents).map_err(|e| file_error(e.to_string()))
+    }
+
+    // Write the report as JSON
+    pub fn save(&self, path: &Path) -> Result<(), PrinterError> {
+        let file_error = |reason: String| PrinterError::FileFailed { path: path.display().to_string(), reason };
+
+        let file = File::create(path).map_err(|e| file_error(e.to_string()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).map_err(|e| file_error(e.to_string()))?;
+
+        info!("[{}] Wrote report for {} printers to {}", "save", self.printers.len(), path.display());
+
+        Ok(())
+    }
+}
+
+// End-of-run summary: the counts, then one line for anything that did not convert
+impl fmt::Display for ConversionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Converted: {}, skipped: {}, failed: {}", self.converted, self.skipped, self.failed)?;
+
+        for server in self.servers.iter().filter(|server| server.exit_code != 0) {
+            match &server.error {
+                Some(error) => writeln!(f, " server {}: {}", server.server, error)?,
+                None => writeln!(f, " server {}: exited with {}", server.server, server.exit_code)?,
+            }
+        }
+
+        for result in self.printers.iter().filter(|result| result.status != ConversionStatus::Converted) {
+            let status = match result.status {
+                ConversionStatus::Skipped => "skipped",
+                _ => "failed",
+            };
+            match &result.server {
+                Some(server) => writeln!(f, " {} {}\\{}: {}", status, server, result.printer_name, result.error.as_deref().unwrap_or_default())?,
+                None => writeln!(f, " {} {}: {}", status, result.printer_name, result.error.as_deref().unwrap_or_default())?,
+            }
+        }
+
+        for result in &self.printers {
+            if let Some(error) = &result.test_page_error {
+                writeln!(f, " no test page for {}: {}", result.printer_name, error)?;
+            }
+        }
+
+        Ok(())
+    }
+}
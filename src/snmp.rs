@@ -0,0 +1,318 @@
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use log::{info, warn};
+use rand::Rng;
+
+// hrDeviceDescr of the first device in the Host Resources MIB, which printers fill in with their model
+pub const HR_DEVICE_DESCR_OID: &str = "1.3.6.1.2.1.25.3.2.1.3.1";
+// sysDescr, the fallback: every agent has it, though printers often put firmware details there too
+pub const SYS_DESCR_OID: &str = "1.3.6.1.2.1.1.1.0";
+
+const SNMP_PORT: u16 = 161;
+
+// How long to wait for a printer's agent to answer a GET
+pub const DEFAULT_SNMP_TIMEOUT: Duration = Duration::from_secs(2);
+
+// BER tags used by an SNMPv1 GET and its response
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_GET_REQUEST: u8 = 0xa0;
+const TAG_GET_RESPONSE: u8 = 0xa2;
+
+const SNMP_VERSION_1: i64 = 0;
+
+fn encode_length(length: usize, out: &mut Vec<u8>) {
+    if length < 0x80 {
+        out.push(length as u8);
+        return;
+    }
+
+    let bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|byte| *byte == 0).collect();
+    out.push(0x80 | bytes.len() as u8);
+    out.extend(bytes);
+}
+
+fn encode_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    encode_length(content.len(), &mut out);
+    out.extend_from_slice(content);
+    out
+}
+
+// Two's complement, big-endian, in as few bytes as keep the sign
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let mut start = 0;
+    while start < bytes.len() - 1
+        && ((bytes[start] == 0x00 && bytes[start + 1] & 0x80 == 0) || (bytes[start] == 0xff && bytes[start + 1] & 0x80 != 0))
+    {
+        start += 1;
+    }
+    encode_tlv(TAG_INTEGER, &bytes[start..])
+}
+
+// The first two arcs share a byte and every arc after them is base 128, high bit set on all but the last byte
+fn encode_oid(oid: &str) -> Option<Vec<u8>> {
+    let arcs: Vec<u64> = oid.split('.').map(|arc| arc.parse().ok()).collect::<Option<_>>()?;
+    if arcs.len() < 2 {
+        return None;
+    }
+
+    let mut content = Vec::new();
+    for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+        let mut groups = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            groups.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        content.extend(groups.into_iter().rev());
+    }
+
+    Some(encode_tlv(TAG_OID, &content))
+}
+
+fn build_get_request(community: &str, oid: &str, request_id: i64) -> Option<Vec<u8>> {
+    let varbind = encode_tlv(TAG_SEQUENCE, &[encode_oid(oid)?, encode_tlv(TAG_NULL, &[])].concat());
+    let varbinds = encode_tlv(TAG_SEQUENCE, &varbind);
+
+    let pdu = encode_tlv(TAG_GET_REQUEST, &[encode_integer(request_id), encode_integer(0), encode_integer(0), varbinds].concat());
+
+    Some(encode_tlv(TAG_SEQUENCE, &[encode_integer(SNMP_VERSION_1), encode_tlv(TAG_OCTET_STRING, community.as_bytes()), pdu].concat()))
+}
+
+// Split the TLV at the front of data into its tag, its content and whatever follows it
+fn read_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (length, rest) = if first & 0x80 == 0 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > std::mem::size_of::<usize>() || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count].iter().fold(0usize, |length, byte| (length << 8) | *byte as usize);
+        (length, &rest[count..])
+    };
+
+    if rest.len() < length {
+        return None;
+    }
+    Some((tag, &rest[..length], &rest[length..]))
+}
+
+fn read_expected(data: &[u8], expected: u8) -> Option<(&[u8], &[u8])> {
+    match read_tlv(data)? {
+        (tag, content, rest) if tag == expected => Some((content, rest)),
+        _ => None,
+    }
+}
+
+fn decode_integer(content: &[u8]) -> Option<i64> {
+    if content.is_empty() || content.len() > 8 {
+        return None;
+    }
+    let sign = if content[0] & 0x80 != 0 { -1i64 } else { 0 };
+    Some(content.iter().fold(sign, |value, byte| (value << 8) | *byte as i64))
+}
+
+// The string value of the single varbind in a GetResponse to request_id, or None for anything else, including an
+// agent that reported an error or returned something other than a string
+fn parse_get_response(data: &[u8], request_id: i64) -> Option<String> {
+    let (message, _) = read_expected(data, TAG_SEQUENCE)?;
+    let (_version, rest) = read_expected(message, TAG_INTEGER)?;
+    let (_community, rest) = read_expected(rest, TAG_OCTET_STRING)?;
+    let (pdu, _) = read_expected(rest, TAG_GET_RESPONSE)?;
+
+    let (id, rest) = read_expected(pdu, TAG_INTEGER)?;
+    if decode_integer(id)? != request_id {
+        return None;
+    }
+    let (error_status, rest) = read_expected(rest, TAG_INTEGER)?;
+    if decode_integer(error_status)? != 0 {
+        return None;
+    }
+    let (_error_index, rest) = read_expected(rest, TAG_INTEGER)?;
+
+    let (varbinds, _) = read_expected(rest, TAG_SEQUENCE)?;
+    let (varbind, _) = read_expected(varbinds, TAG_SEQUENCE)?;
+    let (_oid, rest) = read_expected(varbind, TAG_OID)?;
+    let (value, _) = read_expected(rest, TAG_OCTET_STRING)?;
+
+    let value = String::from_utf8_lossy(value).trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+// SNMPv1 GET of a single string OID from the agent at address, using community
+pub fn snmp_get_string(address: &str, community: &str, oid: &str, timeout: Duration) -> Option<String> {
+    let request_id = rand::thread_rng().gen_range(1..i32::MAX as i64);
+    let request = build_get_request(community, oid, request_id)?;
+
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .and_then(|socket| socket.set_read_timeout(Some(timeout)).map(|_| socket))
+        .map_err(|e| warn!("[{}] Could not open a UDP socket: {}", "snmp_get_string", e))
+        .ok()?;
+
+    let host = address.trim_start_matches('[').trim_end_matches(']');
+    if let Err(e) = socket.send_to(&request, (host, SNMP_PORT)) {
+        warn!("[{}] Could not send a GET for {} to {}: {}", "snmp_get_string", oid, address, e);
+        return None;
+    }
+
+    let mut buffer = vec![0u8; 65536];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) => {
+                info!("[{}] No answer from {} for {}: {}", "snmp_get_string", address, oid, e);
+                return None;
+            }
+        };
+
+        // A late answer to some earlier request on the same port carries another id and is ignored
+        match parse_get_response(&buffer[..len], request_id) {
+            Some(value) => {
+                info!("[{}] {} from {} is {:?}", "snmp_get_string", oid, from, value);
+                return Some(value);
+            }
+            None => info!("[{}] Ignoring a response from {} that does not answer {}", "snmp_get_string", from, oid),
+        }
+    }
+}
+
+// The device's model as its SNMP agent describes it: hrDeviceDescr when the agent has it, otherwise sysDescr
+pub fn query_device_model(address: &str, community: &str, timeout: Duration) -> Option<String> {
+    snmp_get_string(address, community, HR_DEVICE_DESCR_OID, timeout)
+        .or_else(|| snmp_get_string(address, community, SYS_DESCR_OID, timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A GetResponse to request_id carrying value for oid, laid out the way an agent sends it
+    fn get_response(request_id: i64, error_status: i64, oid: &str, value: Vec<u8>) -> Vec<u8> {
+        let varbind = encode_tlv(TAG_SEQUENCE, &[encode_oid(oid).unwrap(), value].concat());
+        let varbinds = encode_tlv(TAG_SEQUENCE, &varbind);
+        let pdu = encode_tlv(
+            TAG_GET_RESPONSE,
+            &[encode_integer(request_id), encode_integer(error_status), encode_integer(0), varbinds].concat(),
+        );
+        encode_tlv(TAG_SEQUENCE, &[encode_integer(SNMP_VERSION_1), encode_tlv(TAG_OCTET_STRING, b"public"), pdu].concat())
+    }
+
+    #[test]
+    fn encodes_a_get_request() {
+        let request = build_get_request("public", SYS_DESCR_OID, 1).unwrap();
+        assert_eq!(request, [
+            0x30, 0x26,
+            0x02, 0x01, 0x00,
+            0x04, 0x06, b'p', b'u', b'b', b'l', b'i', b'c',
+            0xa0, 0x19,
+            0x02, 0x01, 0x01,
+            0x02, 0x01, 0x00,
+            0x02, 0x01, 0x00,
+            0x30, 0x0e, 0x30, 0x0c,
+            0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x01, 0x00,
+            0x05, 0x00,
+        ]);
+
+        assert_eq!(build_get_request("public", "1.3.x", 1), None);
+        assert_eq!(build_get_request("public", "1", 1), None);
+    }
+
+    #[test]
+    fn reads_back_the_request_it_encodes() {
+        let request = build_get_request("private", HR_DEVICE_DESCR_OID, 0x5a5a5a5a).unwrap();
+
+        let (message, rest) = read_expected(&request, TAG_SEQUENCE).unwrap();
+        assert!(rest.is_empty());
+        let (version, rest) = read_expected(message, TAG_INTEGER).unwrap();
+        assert_eq!(decode_integer(version), Some(SNMP_VERSION_1));
+        let (community, rest) = read_expected(rest, TAG_OCTET_STRING).unwrap();
+        assert_eq!(community, b"private");
+        let (pdu, _) = read_expected(rest, TAG_GET_REQUEST).unwrap();
+        let (id, _) = read_expected(pdu, TAG_INTEGER).unwrap();
+        assert_eq!(decode_integer(id), Some(0x5a5a5a5a));
+    }
+
+    #[test]
+    fn integers_keep_their_sign_in_as_few_bytes_as_possible() {
+        for (value, encoded) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x00, 0x80]),
+            (256, vec![0x01, 0x00]),
+            (-1, vec![0xff]),
+            (-128, vec![0x80]),
+            (-129, vec![0xff, 0x7f]),
+            (i32::MAX as i64, vec![0x7f, 0xff, 0xff, 0xff]),
+        ] {
+            assert_eq!(encode_integer(value), [vec![TAG_INTEGER, encoded.len() as u8], encoded.clone()].concat(), "{}", value);
+            assert_eq!(decode_integer(&encoded), Some(value), "{}", value);
+        }
+        assert_eq!(decode_integer(&[]), None);
+        assert_eq!(decode_integer(&[0; 9]), None);
+    }
+
+    #[test]
+    fn encodes_large_arcs_in_base_128() {
+        assert_eq!(encode_oid("1.3.6.1.4.1.2699").unwrap(), [0x06, 0x07, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x95, 0x0b]);
+    }
+
+    #[test]
+    fn long_lengths_round_trip() {
+        let content = vec![b'x'; 300];
+        let encoded = encode_tlv(TAG_OCTET_STRING, &content);
+        assert_eq!(encoded[..4], [TAG_OCTET_STRING, 0x82, 0x01, 0x2c]);
+        assert_eq!(read_tlv(&[encoded.as_slice(), &[0x05, 0x00]].concat()), Some((TAG_OCTET_STRING, content.as_slice(), [0x05, 0x00].as_slice())));
+    }
+
+    #[test]
+    fn parses_the_string_in_a_get_response() {
+        let response = get_response(42, 0, SYS_DESCR_OID, encode_tlv(TAG_OCTET_STRING, b" HP LaserJet M404\0"));
+        assert_eq!(parse_get_response(&response, 42).as_deref(), Some("HP LaserJet M404"));
+    }
+
+    #[test]
+    fn ignores_a_response_to_another_request() {
+        let response = get_response(42, 0, SYS_DESCR_OID, encode_tlv(TAG_OCTET_STRING, b"HP LaserJet M404"));
+        assert_eq!(parse_get_response(&response, 43), None);
+    }
+
+    #[test]
+    fn ignores_a_response_that_reports_an_error() {
+        // noSuchName, which v1 agents answer for an OID they do not have
+        let response = get_response(42, 2, SYS_DESCR_OID, encode_tlv(TAG_NULL, &[]));
+        assert_eq!(parse_get_response(&response, 42), None);
+
+        let response = get_response(42, 2, SYS_DESCR_OID, encode_tlv(TAG_OCTET_STRING, b"HP LaserJet M404"));
+        assert_eq!(parse_get_response(&response, 42), None);
+    }
+
+    #[test]
+    fn ignores_a_value_that_is_not_a_string() {
+        let response = get_response(42, 0, SYS_DESCR_OID, encode_integer(7));
+        assert_eq!(parse_get_response(&response, 42), None);
+        let response = get_response(42, 0, SYS_DESCR_OID, encode_tlv(TAG_OCTET_STRING, b" \0"));
+        assert_eq!(parse_get_response(&response, 42), None);
+    }
+
+    #[test]
+    fn rejects_a_truncated_response() {
+        let response = get_response(42, 0, SYS_DESCR_OID, encode_tlv(TAG_OCTET_STRING, b"HP LaserJet M404"));
+        for length in 0..response.len() {
+            assert_eq!(parse_get_response(&response[..length], 42), None, "{} bytes", length);
+        }
+
+        // A length that claims more bytes than a usize holds
+        assert_eq!(read_tlv(&[TAG_OCTET_STRING, 0x89, 1, 0, 0, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!(read_tlv(&[TAG_OCTET_STRING, 0x80]), None);
+    }
+}
@@ -0,0 +1,159 @@
+use std::ffi::OsStr;
+use std::ptr::null_mut;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn, error};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::ERROR_SERVICE_NOT_ACTIVE;
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::winsvc::{SC_HANDLE, SERVICE_STATUS, SC_MANAGER_CONNECT, SERVICE_QUERY_STATUS, SERVICE_START, SERVICE_STOP};
+use winapi::um::winsvc::{SERVICE_CONTROL_STOP, SERVICE_RUNNING, SERVICE_STOPPED};
+use winapi::um::winsvc::{SERVICE_START_PENDING, SERVICE_STOP_PENDING, SERVICE_CONTINUE_PENDING, SERVICE_PAUSE_PENDING, SERVICE_PAUSED};
+use winapi::um::winsvc::{OpenSCManagerW, OpenServiceW, ControlService, StartServiceW, QueryServiceStatus, CloseServiceHandle};
+
+use crate::error::{PrinterError, format_error_code};
+use crate::printers::unc_server_name;
+use crate::wide::to_wide_null;
+
+// How long restart_spooler waits for each of the stop and start transitions
+pub const DEFAULT_SPOOLER_TIMEOUT: Duration = Duration::from_secs(30);
+
+const SPOOLER_SERVICE_NAME: &str = "Spooler";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Owns a handle from OpenSCManagerW or OpenServiceW and closes it when dropped
+struct ServiceHandle(SC_HANDLE);
+
+impl Drop for ServiceHandle {
+    fn drop(&mut self) {
+        unsafe {
+            CloseServiceHandle(self.0);
+        }
+    }
+}
+
+fn service_error(operation: &'static str) -> PrinterError {
+    let error_code = unsafe { GetLastError() };
+    error!("[{}] {} failed: {}", "restart_spooler", operation, format_error_code(error_code).unwrap_or_default());
+    PrinterError::ServiceFailed { operation, code: error_code }
+}
+
+fn current_state(service: &ServiceHandle) -> Result<DWORD, PrinterError> {
+    let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { QueryServiceStatus(service.0, &mut status) } == 0 {
+        return Err(service_error("QueryServiceStatus"));
+    }
+    Ok(status.dwCurrentState)
+}
+
+// Poll until the service reaches wanted, or give up after timeout
+fn wait_for_state(service: &ServiceHandle, wanted: DWORD, state_name: &'static str, timeout: Duration) -> Result<(), PrinterError> {
+    let started = Instant::now();
+
+    loop {
+        let state = current_state(service)?;
+        if state == wanted {
+            info!("[{}] Spooler is {} after {:?}", "restart_spooler", state_name, started.elapsed());
+            return Ok(());
+        }
+
+        if started.elapsed() >= timeout {
+            error!("[{}] Spooler did not become {} within {:?} (state {})", "restart_spooler", state_name, timeout, state);
+            return Err(PrinterError::ServiceTimeout { state: state_name });
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Open the Spooler service on server, or on this machine when server is None, with the given access rights
+fn open_spooler_service(server: Option<&str>, access: DWORD) -> Result<ServiceHandle, PrinterError> {
+    let wide_server = server.map(|server| to_wide_null(OsStr::new(&unc_server_name(server))));
+    let server_ptr = wide_server.as_ref().map_or(std::ptr::null(), |name| name.as_ptr());
+
+    let manager = unsafe { OpenSCManagerW(server_ptr, null_mut(), SC_MANAGER_CONNECT) };
+    if manager.is_null() {
+        return Err(service_error("OpenSCManagerW"));
+    }
+    let manager = ServiceHandle(manager);
+
+    let service_name = to_wide_null(OsStr::new(SPOOLER_SERVICE_NAME));
+    let service = unsafe { OpenServiceW(manager.0, service_name.as_ptr(), access) };
+    if service.is_null() {
+        return Err(service_error("OpenServiceW"));
+    }
+
+    // The service handle stays usable after the manager handle is closed
+    Ok(ServiceHandle(service))
+}
+
+// The state of the Print Spooler on server, or on this machine when server is None, as the Services console names it
+pub fn spooler_state(server: Option<&str>) -> Result<&'static str, PrinterError> {
+    let state = open_spooler_service(server, SERVICE_QUERY_STATUS).and_then(|service| current_state(&service))?;
+
+    Ok(match state {
+        SERVICE_RUNNING => "Running",
+        SERVICE_STOPPED => "Stopped",
+        SERVICE_START_PENDING => "Starting",
+        SERVICE_STOP_PENDING => "Stopping",
+        SERVICE_CONTINUE_PENDING => "Resuming",
+        SERVICE_PAUSE_PENDING => "Pausing",
+        SERVICE_PAUSED => "Paused",
+        _ => "Unknown",
+    })
+}
+
+// Whether the Print Spooler is running on server, or on this machine when server is None. Every spooler call
+// fails with an unhelpful error while it is stopped. When the state cannot be read at all the answer is true,
+// so the spooler calls themselves get to report what is wrong
+pub fn spooler_is_running(server: Option<&str>) -> bool {
+    let state = open_spooler_service(server, SERVICE_QUERY_STATUS).and_then(|service| current_state(&service));
+
+    match state {
+        Ok(state) => {
+            info!("[{}] Spooler state is {}", "spooler_is_running", state);
+            state == SERVICE_RUNNING
+        }
+        Err(e) => {
+            warn!("[{}] Could not query the Spooler service, assuming it is running: {}", "spooler_is_running", e);
+            true
+        }
+    }
+}
+
+// Start the Print Spooler on server, or on this machine when server is None, and wait up to timeout for it to run
+pub fn start_spooler(server: Option<&str>, timeout: Duration) -> Result<(), PrinterError> {
+    let service = open_spooler_service(server, SERVICE_START | SERVICE_QUERY_STATUS)?;
+
+    info!("[{}] Starting the Print Spooler on {}", "start_spooler", server.unwrap_or("the local machine"));
+    if unsafe { StartServiceW(service.0, 0, null_mut()) } == 0 {
+        return Err(service_error("StartServiceW"));
+    }
+    wait_for_state(&service, SERVICE_RUNNING, "running", timeout)
+}
+
+// Stop and start the Print Spooler on a print server, or on this machine when server is None, so that changes
+// to shared printers reach clients. Each transition is given timeout to complete
+pub fn restart_spooler(server: Option<&str>, timeout: Duration) -> Result<(), PrinterError> {
+    let service = open_spooler_service(server, SERVICE_STOP | SERVICE_START | SERVICE_QUERY_STATUS)?;
+
+    info!("[{}] Stopping the Print Spooler on {}", "restart_spooler", server.unwrap_or("the local machine"));
+    let mut status: SERVICE_STATUS = unsafe { std::mem::zeroed() };
+    if unsafe { ControlService(service.0, SERVICE_CONTROL_STOP, &mut status) } == 0 {
+        // Already stopped is fine, there is just nothing to wait for
+        if unsafe { GetLastError() } != ERROR_SERVICE_NOT_ACTIVE {
+            return Err(service_error("ControlService"));
+        }
+        info!("[{}] Spooler was not running", "restart_spooler");
+    }
+    wait_for_state(&service, SERVICE_STOPPED, "stopped", timeout)?;
+
+    info!("[{}] Starting the Print Spooler", "restart_spooler");
+    if unsafe { StartServiceW(service.0, 0, null_mut()) } == 0 {
+        return Err(service_error("StartServiceW"));
+    }
+    wait_for_state(&service, SERVICE_RUNNING, "running", timeout)?;
+
+    Ok(())
+}
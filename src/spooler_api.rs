@@ -0,0 +1,282 @@
+use std::ffi::OsStr;
+use std::sync::Mutex;
+
+use log::info;
+
+#[cfg(windows)]
+use crate::convert::{create_tcpip_port_with_config, delete_port, get_tcpip_port_config, reconfigure_tcpip_port, set_printer_port};
+use crate::convert::{check_host_address, check_printer_port, TcpipPortConfig};
+use crate::error::PrinterError;
+#[cfg(windows)]
+use crate::ports::get_all_ports;
+#[cfg(windows)]
+use crate::printers::{get_printers_with_retry, RetryPolicy};
+use crate::printers::{get_wsd_printers, EnumScope, MinimalPrinterInfo};
+use crate::sys::{ERROR_ALREADY_EXISTS, ERROR_BUSY, ERROR_INVALID_PRINTER_NAME, ERROR_NOT_SUPPORTED, ERROR_UNKNOWN_PORT};
+
+// The spooler calls convert is built on. Going through this instead of the Win32 functions directly lets the
+// enumeration, filtering and planning logic run against a fabricated set of printers
+pub trait SpoolerApi {
+    // Printers in scope on server, or on this machine when server is None
+    fn enum_printers(&self, server: Option<&str>, scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError>;
+
+    // Move printer_name to the existing port port_name
+    fn set_printer(&self, printer_name: &OsStr, port_name: &str) -> Result<(), PrinterError>;
+
+    // Create a Standard TCP/IP port called port_name that prints to ip
+    fn add_port(&self, server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError>;
+
+    // The address the existing Standard TCP/IP port port_name prints to, and its settings
+    fn port_config(&self, server: Option<&str>, port_name: &str) -> Result<(String, TcpipPortConfig), PrinterError>;
+
+    // Point the existing Standard TCP/IP port port_name at ip with config
+    fn reconfigure_port(&self, server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError>;
+
+    // Names of every port on server, whatever its monitor
+    fn port_names(&self, server: Option<&str>) -> Result<Vec<String>, PrinterError>;
+
+    // Remove the port port_name, which no printer may still be using
+    fn delete_port(&self, server: Option<&str>, port_name: &str) -> Result<(), PrinterError>;
+
+    // SetPrinterW can succeed while the spooler keeps serving the old settings, so read printer_name back from a
+    // fresh enumeration of every scope and make sure it really is on expected_port now
+    fn verify_port(&self, server: Option<&str>, printer_name: &OsStr, expected_port: &str) -> Result<(), PrinterError> {
+        let printers = self.enum_printers(server, EnumScope::All)?;
+        check_printer_port(&printers, printer_name, expected_port)
+    }
+
+    // Printers in scope that are currently on WSD ports
+    fn wsd_printers(&self, server: Option<&str>, scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+        self.enum_printers(server, scope).map(|printers| get_wsd_printers(&printers))
+    }
+}
+
+// The real spooler, reached through winspool
+#[cfg(windows)]
+#[derive(Clone, Debug, Default)]
+pub struct WinSpooler {
+    pub retry: RetryPolicy,
+}
+
+#[cfg(windows)]
+impl WinSpooler {
+    pub fn new(retry: RetryPolicy) -> Self {
+        WinSpooler { retry }
+    }
+}
+
+#[cfg(windows)]
+impl SpoolerApi for WinSpooler {
+    fn enum_printers(&self, server: Option<&str>, scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+        get_printers_with_retry(server, scope, &self.retry)
+    }
+
+    fn set_printer(&self, printer_name: &OsStr, port_name: &str) -> Result<(), PrinterError> {
+        set_printer_port(printer_name, port_name)
+    }
+
+    fn add_port(&self, server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+        create_tcpip_port_with_config(server, ip, port_name, config)
+    }
+
+    fn port_config(&self, server: Option<&str>, port_name: &str) -> Result<(String, TcpipPortConfig), PrinterError> {
+        get_tcpip_port_config(server, port_name)
+    }
+
+    fn reconfigure_port(&self, server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+        reconfigure_tcpip_port(server, ip, port_name, config)
+    }
+
+    fn port_names(&self, server: Option<&str>) -> Result<Vec<String>, PrinterError> {
+        get_all_ports(server).map(|ports| ports.iter().map(|port| port.port_name.to_string_lossy().into_owned()).collect())
+    }
+
+    fn delete_port(&self, server: Option<&str>, port_name: &str) -> Result<(), PrinterError> {
+        delete_port(server, port_name)
+    }
+}
+
+// An in-memory spooler for tests. It holds a fixed list of printers and the ports they use, and fails the
+// same way the real spooler does for unknown printers, unknown ports, ports that already exist and ports that are
+// deleted while in use. Only ports added through add_port have TCP/IP settings, the ones the printers started on
+// answer port_config as WSD ports do. Server and scope are ignored: every printer it was given is returned
+#[derive(Debug, Default)]
+pub struct MockSpooler {
+    printers: Mutex<Vec<MinimalPrinterInfo>>,
+    ports: Mutex<Vec<String>>,
+    // Port name, address and settings of every port added
+    configs: Mutex<Vec<(String, String, TcpipPortConfig)>>,
+}
+
+impl MockSpooler {
+    // Start with printers installed, and with every port they are on already existing
+    pub fn new(printers: Vec<MinimalPrinterInfo>) -> Self {
+        let mut ports: Vec<String> = printers.iter().flat_map(MinimalPrinterInfo::ports).collect();
+        ports.sort();
+        ports.dedup();
+
+        MockSpooler { printers: Mutex::new(printers), ports: Mutex::new(ports), configs: Mutex::default() }
+    }
+
+    // The printers as they stand now, including any port changes made through set_printer
+    pub fn printers(&self) -> Vec<MinimalPrinterInfo> {
+        self.printers.lock().unwrap().clone()
+    }
+
+    // Every port that exists, including the ones added through add_port
+    pub fn ports(&self) -> Vec<String> {
+        self.ports.lock().unwrap().clone()
+    }
+}
+
+impl SpoolerApi for MockSpooler {
+    fn enum_printers(&self, _server: Option<&str>, _scope: EnumScope) -> Result<Vec<MinimalPrinterInfo>, PrinterError> {
+        Ok(self.printers())
+    }
+
+    fn set_printer(&self, printer_name: &OsStr, port_name: &str) -> Result<(), PrinterError> {
+        // A pool is given as its members, each of which must exist
+        let ports = self.ports.lock().unwrap();
+        let missing = port_name.split(',').map(str::trim).find(|member| !ports.iter().any(|port| port.eq_ignore_ascii_case(member)));
+        if let Some(member) = missing {
+            return Err(PrinterError::UnknownPort { port: member.to_string() });
+        }
+        drop(ports);
+
+        let mut printers = self.printers.lock().unwrap();
+        let printer = printers.iter_mut()
+            .find(|printer| printer.printer_name == printer_name)
+            .ok_or_else(|| PrinterError::OpenPrinterFailed { name: printer_name.to_string_lossy().into_owned(), code: ERROR_INVALID_PRINTER_NAME })?;
+
+        info!("[{}] Moving {:?} to {}", "MockSpooler::set_printer", printer_name, port_name);
+        printer.port_name = port_name.into();
+
+        Ok(())
+    }
+
+    fn add_port(&self, _server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+        check_host_address(ip)?;
+        let mut ports = self.ports.lock().unwrap();
+        if ports.iter().any(|port| port.eq_ignore_ascii_case(port_name)) {
+            return Err(PrinterError::PortCreationFailed(ERROR_ALREADY_EXISTS));
+        }
+
+        info!("[{}] Adding {} for {}", "MockSpooler::add_port", port_name, ip);
+        ports.push(port_name.to_string());
+        self.configs.lock().unwrap().push((port_name.to_string(), ip.to_string(), config.clone()));
+
+        Ok(())
+    }
+
+    fn port_config(&self, _server: Option<&str>, port_name: &str) -> Result<(String, TcpipPortConfig), PrinterError> {
+        if !self.ports.lock().unwrap().iter().any(|port| port.eq_ignore_ascii_case(port_name)) {
+            return Err(PrinterError::UnknownPort { port: port_name.to_string() });
+        }
+
+        self.configs.lock().unwrap().iter()
+            .find(|(port, _, _)| port.eq_ignore_ascii_case(port_name))
+            .map(|(_, ip, config)| (ip.clone(), config.clone()))
+            .ok_or_else(|| PrinterError::PortConfigFailed { command: "GetConfigInfo", port: port_name.to_string(), code: ERROR_NOT_SUPPORTED })
+    }
+
+    fn reconfigure_port(&self, server: Option<&str>, ip: &str, port_name: &str, config: &TcpipPortConfig) -> Result<(), PrinterError> {
+        check_host_address(ip)?;
+        self.port_config(server, port_name)?;
+
+        info!("[{}] Pointing {} at {}", "MockSpooler::reconfigure_port", port_name, ip);
+        let mut configs = self.configs.lock().unwrap();
+        if let Some(entry) = configs.iter_mut().find(|(port, _, _)| port.eq_ignore_ascii_case(port_name)) {
+            *entry = (entry.0.clone(), ip.to_string(), config.clone());
+        }
+
+        Ok(())
+    }
+
+    fn port_names(&self, _server: Option<&str>) -> Result<Vec<String>, PrinterError> {
+        Ok(self.ports())
+    }
+
+    fn delete_port(&self, _server: Option<&str>, port_name: &str) -> Result<(), PrinterError> {
+        let deletion_failed = |code| PrinterError::PortDeletionFailed { port: port_name.to_string(), code };
+
+        let mut ports = self.ports.lock().unwrap();
+        let index = ports.iter().position(|port| port.eq_ignore_ascii_case(port_name)).ok_or_else(|| deletion_failed(ERROR_UNKNOWN_PORT))?;
+        let in_use = self.printers.lock().unwrap().iter()
+            .any(|printer| printer.ports().iter().any(|port| port.eq_ignore_ascii_case(port_name)));
+        if in_use {
+            return Err(deletion_failed(ERROR_BUSY));
+        }
+
+        info!("[{}] Removing {}", "MockSpooler::delete_port", port_name);
+        ports.remove(index);
+        self.configs.lock().unwrap().retain(|(port, _, _)| !port.eq_ignore_ascii_case(port_name));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_the_printers_on_wsd_ports() {
+        let spooler = MockSpooler::new(vec![
+            MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "HP LaserJet Pro M404"),
+            MinimalPrinterInfo::fabricated("Warehouse", "IP_10.0.0.9", "ZDesigner ZD420-203dpi ZPL"),
+            MinimalPrinterInfo::fabricated("Pool", "IP_10.0.0.6, WSDPORT_3", "HP LaserJet Pro M404"),
+            MinimalPrinterInfo::fabricated("Label", "USB001", "ZDesigner ZD420-203dpi ZPL"),
+        ]);
+
+        let names: Vec<_> = spooler.wsd_printers(None, EnumScope::All).unwrap().into_iter().map(|printer| printer.printer_name).collect();
+        assert_eq!(names, ["Front desk", "Pool"]);
+
+        // Moving one off its WSD port takes it out of the list
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &TcpipPortConfig::default()).unwrap();
+        spooler.set_printer(OsStr::new("Front desk"), "IP_10.0.0.5").unwrap();
+        let names: Vec<_> = spooler.wsd_printers(None, EnumScope::All).unwrap().into_iter().map(|printer| printer.printer_name).collect();
+        assert_eq!(names, ["Pool"]);
+    }
+
+    #[test]
+    fn moves_a_printer_onto_a_pool_of_existing_ports() {
+        let spooler = MockSpooler::new(vec![MinimalPrinterInfo::fabricated("Pool", "WSD-0a1b2c3d, IP_10.0.0.9", "HP LaserJet Pro M404")]);
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &TcpipPortConfig::default()).unwrap();
+
+        let refused = spooler.set_printer(OsStr::new("Pool"), "IP_10.0.0.5, IP_10.0.0.6");
+        assert!(matches!(refused, Err(PrinterError::UnknownPort { ref port }) if port == "IP_10.0.0.6"), "{:?}", refused);
+
+        spooler.set_printer(OsStr::new("Pool"), "IP_10.0.0.5,ip_10.0.0.9").unwrap();
+        assert_eq!(spooler.printers()[0].ports(), ["IP_10.0.0.5", "ip_10.0.0.9"]);
+    }
+
+    #[test]
+    fn refuses_what_the_real_spooler_refuses() {
+        let spooler = MockSpooler::new(vec![MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "HP LaserJet Pro M404")]);
+        let config = TcpipPortConfig::default();
+
+        assert!(matches!(spooler.set_printer(OsStr::new("Front desk"), "IP_10.0.0.5"), Err(PrinterError::UnknownPort { .. })));
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &config).unwrap();
+        assert!(matches!(spooler.add_port(None, "10.0.0.5", "ip_10.0.0.5", &config), Err(PrinterError::PortCreationFailed(ERROR_ALREADY_EXISTS))));
+        assert!(matches!(spooler.set_printer(OsStr::new("Basement"), "IP_10.0.0.5"), Err(PrinterError::OpenPrinterFailed { .. })));
+        assert!(matches!(spooler.port_config(None, "WSD-0a1b2c3d"), Err(PrinterError::PortConfigFailed { .. })));
+        assert_eq!(spooler.port_config(None, "IP_10.0.0.5").unwrap().0, "10.0.0.5");
+    }
+
+    #[test]
+    fn deletes_only_ports_nothing_uses() {
+        let spooler = MockSpooler::new(vec![MinimalPrinterInfo::fabricated("Front desk", "WSD-0a1b2c3d", "HP LaserJet Pro M404")]);
+        let config = TcpipPortConfig::default();
+        spooler.add_port(None, "10.0.0.5", "IP_10.0.0.5", &config).unwrap();
+        spooler.add_port(None, "10.0.0.7", "IP_10.0.0.7", &config).unwrap();
+        spooler.set_printer(OsStr::new("Front desk"), "IP_10.0.0.5").unwrap();
+
+        assert!(matches!(spooler.delete_port(None, "IP_10.0.0.5"), Err(PrinterError::PortDeletionFailed { code: ERROR_BUSY, .. })));
+        assert!(matches!(spooler.delete_port(None, "IP_10.0.0.9"), Err(PrinterError::PortDeletionFailed { code: ERROR_UNKNOWN_PORT, .. })));
+
+        spooler.delete_port(None, "ip_10.0.0.7").unwrap();
+        spooler.delete_port(None, "WSD-0a1b2c3d").unwrap();
+        assert_eq!(spooler.ports(), ["IP_10.0.0.5"]);
+        assert!(spooler.port_config(None, "IP_10.0.0.7").is_err());
+    }
+}
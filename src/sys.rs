@@ -0,0 +1,139 @@
+// Windows definitions that the platform independent parts of the crate need. They come from winapi on Windows and
+// are spelled out here elsewhere, where winapi is empty, so the same code builds and tests on every platform
+
+#[cfg(windows)]
+pub(crate) use winapi::shared::minwindef::DWORD;
+#[cfg(windows)]
+pub(crate) use winapi::shared::winerror::{ERROR_ALREADY_EXISTS, ERROR_BUSY, ERROR_UNKNOWN_PORT, ERROR_INVALID_PRINTER_NAME, ERROR_NOT_SUPPORTED};
+#[cfg(windows)]
+pub(crate) use winapi::um::winspool::{
+    PRINTER_STATUS_PAUSED, PRINTER_STATUS_ERROR, PRINTER_STATUS_PENDING_DELETION, PRINTER_STATUS_PAPER_JAM,
+    PRINTER_STATUS_PAPER_OUT, PRINTER_STATUS_MANUAL_FEED, PRINTER_STATUS_PAPER_PROBLEM, PRINTER_STATUS_OFFLINE,
+    PRINTER_STATUS_IO_ACTIVE, PRINTER_STATUS_BUSY, PRINTER_STATUS_PRINTING, PRINTER_STATUS_OUTPUT_BIN_FULL,
+    PRINTER_STATUS_NOT_AVAILABLE, PRINTER_STATUS_WAITING, PRINTER_STATUS_PROCESSING, PRINTER_STATUS_INITIALIZING,
+    PRINTER_STATUS_WARMING_UP, PRINTER_STATUS_TONER_LOW, PRINTER_STATUS_NO_TONER, PRINTER_STATUS_PAGE_PUNT,
+    PRINTER_STATUS_USER_INTERVENTION, PRINTER_STATUS_OUT_OF_MEMORY, PRINTER_STATUS_DOOR_OPEN,
+    PRINTER_STATUS_SERVER_UNKNOWN, PRINTER_STATUS_POWER_SAVE, PRINTER_STATUS_SERVER_OFFLINE,
+    PRINTER_STATUS_DRIVER_UPDATE_NEEDED, PRINTER_ATTRIBUTE_QUEUED, PRINTER_ATTRIBUTE_DIRECT,
+    PRINTER_ATTRIBUTE_DEFAULT, PRINTER_ATTRIBUTE_SHARED, PRINTER_ATTRIBUTE_NETWORK, PRINTER_ATTRIBUTE_HIDDEN,
+    PRINTER_ATTRIBUTE_LOCAL, PRINTER_ATTRIBUTE_ENABLE_DEVQ, PRINTER_ATTRIBUTE_KEEPPRINTEDJOBS,
+    PRINTER_ATTRIBUTE_DO_COMPLETE_FIRST, PRINTER_ATTRIBUTE_WORK_OFFLINE, PRINTER_ATTRIBUTE_ENABLE_BIDI,
+    PRINTER_ATTRIBUTE_RAW_ONLY, PRINTER_ATTRIBUTE_PUBLISHED, PRINTER_ATTRIBUTE_FAX, PRINTER_ATTRIBUTE_TS,
+    PRINTER_ATTRIBUTE_PUSHED_USER, PRINTER_ATTRIBUTE_PUSHED_MACHINE, PRINTER_ATTRIBUTE_MACHINE,
+    PRINTER_ATTRIBUTE_FRIENDLY_NAME, PRINTER_ATTRIBUTE_TS_GENERIC_DRIVER, PRINTER_ATTRIBUTE_PER_USER,
+};
+#[cfg(windows)]
+pub(crate) use winapi::um::winspool::{PRINTER_INFO_2W, PRINTER_INFO_5W};
+
+// Named after the Windows type it stands in for, as winapi does
+#[cfg(not(windows))]
+#[allow(clippy::upper_case_acronyms)]
+pub(crate) type DWORD = u32;
+
+#[cfg(not(windows))]
+mod definitions {
+    use super::DWORD;
+
+    pub(crate) const ERROR_NOT_SUPPORTED: DWORD = 50;
+    pub(crate) const ERROR_BUSY: DWORD = 170;
+    pub(crate) const ERROR_ALREADY_EXISTS: DWORD = 183;
+    pub(crate) const ERROR_UNKNOWN_PORT: DWORD = 1796;
+    pub(crate) const ERROR_INVALID_PRINTER_NAME: DWORD = 1801;
+
+    pub(crate) const PRINTER_STATUS_PAUSED: DWORD = 0x00000001;
+    pub(crate) const PRINTER_STATUS_ERROR: DWORD = 0x00000002;
+    pub(crate) const PRINTER_STATUS_PENDING_DELETION: DWORD = 0x00000004;
+    pub(crate) const PRINTER_STATUS_PAPER_JAM: DWORD = 0x00000008;
+    pub(crate) const PRINTER_STATUS_PAPER_OUT: DWORD = 0x00000010;
+    pub(crate) const PRINTER_STATUS_MANUAL_FEED: DWORD = 0x00000020;
+    pub(crate) const PRINTER_STATUS_PAPER_PROBLEM: DWORD = 0x00000040;
+    pub(crate) const PRINTER_STATUS_OFFLINE: DWORD = 0x00000080;
+    pub(crate) const PRINTER_STATUS_IO_ACTIVE: DWORD = 0x00000100;
+    pub(crate) const PRINTER_STATUS_BUSY: DWORD = 0x00000200;
+    pub(crate) const PRINTER_STATUS_PRINTING: DWORD = 0x00000400;
+    pub(crate) const PRINTER_STATUS_OUTPUT_BIN_FULL: DWORD = 0x00000800;
+    pub(crate) const PRINTER_STATUS_NOT_AVAILABLE: DWORD = 0x00001000;
+    pub(crate) const PRINTER_STATUS_WAITING: DWORD = 0x00002000;
+    pub(crate) const PRINTER_STATUS_PROCESSING: DWORD = 0x00004000;
+    pub(crate) const PRINTER_STATUS_INITIALIZING: DWORD = 0x00008000;
+    pub(crate) const PRINTER_STATUS_WARMING_UP: DWORD = 0x00010000;
+    pub(crate) const PRINTER_STATUS_TONER_LOW: DWORD = 0x00020000;
+    pub(crate) const PRINTER_STATUS_NO_TONER: DWORD = 0x00040000;
+    pub(crate) const PRINTER_STATUS_PAGE_PUNT: DWORD = 0x00080000;
+    pub(crate) const PRINTER_STATUS_USER_INTERVENTION: DWORD = 0x00100000;
+    pub(crate) const PRINTER_STATUS_OUT_OF_MEMORY: DWORD = 0x00200000;
+    pub(crate) const PRINTER_STATUS_DOOR_OPEN: DWORD = 0x00400000;
+    pub(crate) const PRINTER_STATUS_SERVER_UNKNOWN: DWORD = 0x00800000;
+    pub(crate) const PRINTER_STATUS_POWER_SAVE: DWORD = 0x01000000;
+    pub(crate) const PRINTER_STATUS_SERVER_OFFLINE: DWORD = 0x02000000;
+    pub(crate) const PRINTER_STATUS_DRIVER_UPDATE_NEEDED: DWORD = 0x04000000;
+    pub(crate) const PRINTER_ATTRIBUTE_QUEUED: DWORD = 0x00000001;
+    pub(crate) const PRINTER_ATTRIBUTE_DIRECT: DWORD = 0x00000002;
+    pub(crate) const PRINTER_ATTRIBUTE_DEFAULT: DWORD = 0x00000004;
+    pub(crate) const PRINTER_ATTRIBUTE_SHARED: DWORD = 0x00000008;
+    pub(crate) const PRINTER_ATTRIBUTE_NETWORK: DWORD = 0x00000010;
+    pub(crate) const PRINTER_ATTRIBUTE_HIDDEN: DWORD = 0x00000020;
+    pub(crate) const PRINTER_ATTRIBUTE_LOCAL: DWORD = 0x00000040;
+    pub(crate) const PRINTER_ATTRIBUTE_ENABLE_DEVQ: DWORD = 0x00000080;
+    pub(crate) const PRINTER_ATTRIBUTE_KEEPPRINTEDJOBS: DWORD = 0x00000100;
+    pub(crate) const PRINTER_ATTRIBUTE_DO_COMPLETE_FIRST: DWORD = 0x00000200;
+    pub(crate) const PRINTER_ATTRIBUTE_WORK_OFFLINE: DWORD = 0x00000400;
+    pub(crate) const PRINTER_ATTRIBUTE_ENABLE_BIDI: DWORD = 0x00000800;
+    pub(crate) const PRINTER_ATTRIBUTE_RAW_ONLY: DWORD = 0x00001000;
+    pub(crate) const PRINTER_ATTRIBUTE_PUBLISHED: DWORD = 0x00002000;
+    pub(crate) const PRINTER_ATTRIBUTE_FAX: DWORD = 0x00004000;
+    pub(crate) const PRINTER_ATTRIBUTE_TS: DWORD = 0x00008000;
+    pub(crate) const PRINTER_ATTRIBUTE_PUSHED_USER: DWORD = 0x00020000;
+    pub(crate) const PRINTER_ATTRIBUTE_PUSHED_MACHINE: DWORD = 0x00040000;
+    pub(crate) const PRINTER_ATTRIBUTE_MACHINE: DWORD = 0x00080000;
+    pub(crate) const PRINTER_ATTRIBUTE_FRIENDLY_NAME: DWORD = 0x00100000;
+    pub(crate) const PRINTER_ATTRIBUTE_TS_GENERIC_DRIVER: DWORD = 0x00200000;
+    pub(crate) const PRINTER_ATTRIBUTE_PER_USER: DWORD = 0x00400000;
+
+    // Only ever pointed to, so nothing of its layout is needed
+    #[cfg(test)]
+    #[allow(clippy::upper_case_acronyms)]
+    pub(crate) enum DEVMODEW {}
+
+    // Laid out as winspool.h has them, so the code that fills them in for SetPrinterW is tested as it runs
+    #[cfg(test)]
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub(crate) struct PRINTER_INFO_2W {
+        pub(crate) pServerName: *mut u16,
+        pub(crate) pPrinterName: *mut u16,
+        pub(crate) pShareName: *mut u16,
+        pub(crate) pPortName: *mut u16,
+        pub(crate) pDriverName: *mut u16,
+        pub(crate) pComment: *mut u16,
+        pub(crate) pLocation: *mut u16,
+        pub(crate) pDevMode: *mut DEVMODEW,
+        pub(crate) pSepFile: *mut u16,
+        pub(crate) pPrintProcessor: *mut u16,
+        pub(crate) pDatatype: *mut u16,
+        pub(crate) pParameters: *mut u16,
+        pub(crate) pSecurityDescriptor: *mut std::ffi::c_void,
+        pub(crate) Attributes: DWORD,
+        pub(crate) Priority: DWORD,
+        pub(crate) DefaultPriority: DWORD,
+        pub(crate) StartTime: DWORD,
+        pub(crate) UntilTime: DWORD,
+        pub(crate) Status: DWORD,
+        pub(crate) cJobs: DWORD,
+        pub(crate) AveragePPM: DWORD,
+    }
+
+    #[cfg(test)]
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub(crate) struct PRINTER_INFO_5W {
+        pub(crate) pPrinterName: *mut u16,
+        pub(crate) pPortName: *mut u16,
+        pub(crate) Attributes: DWORD,
+        pub(crate) DeviceNotSelectedTimeout: DWORD,
+        pub(crate) TransmissionRetryTimeout: DWORD,
+    }
+}
+
+#[cfg(not(windows))]
+pub(crate) use definitions::*;
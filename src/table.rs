@@ -0,0 +1,88 @@
+use std::io::{self, IsTerminal};
+
+use log::info;
+use winapi::um::consoleapi::{GetConsoleMode, SetConsoleMode};
+use winapi::um::processenv::GetStdHandle;
+use winapi::um::winbase::STD_OUTPUT_HANDLE;
+use winapi::um::wincon::ENABLE_VIRTUAL_TERMINAL_PROCESSING;
+
+use wsd_to_ip::MinimalPrinterInfo;
+
+use crate::cli::ColorChoice;
+
+const HEADERS: [&str; 4] = ["PRINTER", "PORT", "DRIVER", "STATUS"];
+const COLUMN_GAP: &str = "  ";
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+// Console windows only interpret escape sequences once virtual terminal processing is switched on. Returns false
+// when it cannot be, such as on consoles older than Windows 10
+fn enable_virtual_terminal() -> bool {
+    unsafe {
+        let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+        if GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0 || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+// Whether to colour the status column. auto follows the NO_COLOR convention and leaves redirected output plain
+fn use_color(choice: ColorChoice) -> bool {
+    let wanted = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+    };
+
+    if wanted && !enable_virtual_terminal() {
+        info!("[{}] Could not enable escape sequences on this console, printing without colour", "use_color");
+        // Redirected output has no console mode to set, yet --color always still asks for the codes
+        return choice == ColorChoice::Always && !io::stdout().is_terminal();
+    }
+    wanted
+}
+
+// Print printers as a column-aligned table of name, port, driver and status, with the status green for printers
+// that are online and red for ones that are not
+pub fn print_printer_table(printers: &[MinimalPrinterInfo], color: ColorChoice) {
+    let rows: Vec<[String; 4]> = printers.iter()
+        .map(|printer| [
+            printer.printer_name.to_string_lossy().into_owned(),
+            printer.port_name.to_string_lossy().into_owned(),
+            printer.driver_name.to_string_lossy().into_owned(),
+            printer.status_text(),
+        ])
+        .collect();
+
+    // Pad by characters rather than bytes so names outside ASCII still line up
+    let mut widths = HEADERS.map(|header| header.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let pad = |cell: &str, width: usize| format!("{}{}", cell, " ".repeat(width - cell.chars().count()));
+
+    let header: Vec<String> = HEADERS.iter().zip(widths).map(|(header, width)| pad(header, width)).collect();
+    println!("{}", header.join(COLUMN_GAP).trim_end());
+
+    let color = use_color(color);
+    for (printer, row) in printers.iter().zip(&rows) {
+        let mut cells: Vec<String> = row[..3].iter().zip(widths).map(|(cell, width)| pad(cell, width)).collect();
+
+        // The status is the last column, so it needs no padding and the colour codes cannot upset the alignment
+        let status = &row[3];
+        cells.push(match (color, printer.is_offline()) {
+            (false, _) => status.clone(),
+            (true, true) => format!("{}{}{}", RED, status, RESET),
+            (true, false) => format!("{}{}{}", GREEN, status, RESET),
+        });
+
+        println!("{}", cells.join(COLUMN_GAP));
+    }
+}
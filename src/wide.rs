@@ -0,0 +1,40 @@
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+
+// Longest string the spooler hands back (UNICODE_STRING's limit), used to bound the scan for the terminator
+pub(crate) const MAX_WIDE_STR_LEN: usize = 32768;
+
+// Convert a null-terminated wide string from raw pointer to Vec<u16>.
+// A null pointer yields an empty Vec, and at most max_len units are read if no terminator turns up
+pub(crate) fn wide_str_from_raw_ptr(ptr: *const u16, max_len: usize) -> Vec<u16> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+
+    let mut length = 0;
+    unsafe {
+        while length < max_len && *ptr.add(length) != 0 {
+            length += 1;
+        }
+        std::slice::from_raw_parts(ptr, length).to_vec()
+    }
+}
+
+// Convert an OsStr into a null-terminated wide string suitable for passing to Win32
+pub(crate) fn to_wide_null(s: &OsStr) -> Vec<u16> {
+    s.encode_wide().chain(std::iter::once(0)).collect()
+}
+
+// Read a string back out of a fixed-size WCHAR array, stopping at the terminating null if there is one
+pub(crate) fn string_from_wide_array(src: &[u16]) -> String {
+    let length = src.iter().position(|unit| *unit == 0).unwrap_or(src.len());
+    String::from_utf16_lossy(&src[..length])
+}
+
+// Copy a string into a fixed-size WCHAR array, truncating so the terminating null always fits
+pub(crate) fn copy_to_wide_array(dest: &mut [u16], s: &str) {
+    let max_units = dest.len() - 1;
+    for (slot, unit) in dest.iter_mut().zip(s.encode_utf16().take(max_units)) {
+        *slot = unit;
+    }
+}